@@ -0,0 +1,266 @@
+//! Embeds the files of the frontend in `web/` into the binary.
+//!
+//! Assets other than HTML pages get a second name containing a hash of their contents, such as
+//! `viewer.0123456789abcdef.js`, which is what the pages and scripts refer to. These names can be
+//! cached forever by browsers, while the pages themselves are revalidated on every load.
+//! Compressible files are additionally stored compressed with gzip and brotli.
+//!
+//! The files are described by `static_resources.rs` in the output directory.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Files with these extensions are compressed.
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &["html", "js", "css", "json", "map", "svg", "txt", "ico"];
+
+/// Files with these extensions can refer to other files, which are replaced by their hashed names.
+const REWRITTEN_EXTENSIONS: &[&str] = &["html", "js", "css"];
+
+/// The number of hex digits of the hash in the hashed names.
+const NAME_HASH_LENGTH: usize = 16;
+
+/// The characters that end a path to another file in pages and scripts.
+const PATH_DELIMITERS: &[char] = &['"', '\'', '`', ')', '?', '#', '<', '>', ' ', '\n', '\t'];
+
+struct WebFile {
+    /// The path relative to `web/` with `/` as separator.
+    name: String,
+    contents: Vec<u8>,
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    let web_dir = manifest_dir.join("web");
+    println!("cargo:rerun-if-changed=web");
+    if !web_dir.is_dir() {
+        panic!("The frontend has not been built yet, run `make web` first");
+    }
+
+    let mut files = Vec::new();
+    collect_files(&web_dir, &web_dir, &mut files);
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Files are hashed after rewriting the names they refer to, so that their hash changes
+    // when one of these files changes. Thus, every file comes after the files it refers to.
+    let mut files = dependency_order(files);
+
+    let mut hashed_names = HashMap::new();
+    let mut table = String::from("&[\n");
+    for file in files.iter_mut() {
+        if REWRITTEN_EXTENSIONS.contains(&extension(&file.name)) {
+            if let Ok(text) = std::str::from_utf8(&file.contents) {
+                file.contents = rewrite_paths(text, &hashed_names).into_bytes();
+            }
+        }
+        let hash = format!("{:x}", Sha256::digest(&file.contents));
+        let hashed_name = if extension(&file.name) == "html" {
+            None
+        } else {
+            let hashed_name = hashed_file_name(&file.name, &hash[..NAME_HASH_LENGTH]);
+            hashed_names.insert(file.name.clone(), hashed_name.clone());
+            Some(hashed_name)
+        };
+
+        let output = out_dir.join("web").join(&file.name);
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, &file.contents).unwrap();
+        let (gzip, brotli) = if COMPRESSIBLE_EXTENSIONS.contains(&extension(&file.name)) {
+            (
+                write_if_smaller(&output, "gz", gzip(&file.contents), &file.contents),
+                write_if_smaller(&output, "br", brotli(&file.contents), &file.contents),
+            )
+        } else {
+            (None, None)
+        };
+
+        writeln!(
+            table,
+            "    StaticResource {{ name: {:?}, hashed_name: {:?}, hash: {:?}, contents: include_bytes!({:?}), gzip: {}, brotli: {} }},",
+            file.name,
+            hashed_name,
+            hash,
+            output,
+            include_optional(gzip),
+            include_optional(brotli),
+        )
+        .unwrap();
+    }
+    table.push(']');
+    std::fs::write(out_dir.join("static_resources.rs"), table).unwrap();
+}
+
+fn collect_files(web_dir: &Path, dir: &Path, files: &mut Vec<WebFile>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(web_dir, &path, files);
+        } else {
+            let name = path.strip_prefix(web_dir).unwrap().components();
+            let name = name
+                .map(|component| component.as_os_str().to_str().expect("File names must be UTF-8"))
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(WebFile {
+                name,
+                contents: std::fs::read(&path).unwrap(),
+            });
+        }
+    }
+}
+
+/// Sort the files so that each of them comes after the files it refers to.
+/// Panics if files refer to each other in a cycle, since their hashes would depend on each other.
+fn dependency_order(files: Vec<WebFile>) -> Vec<WebFile> {
+    let indices = files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| (file.name.clone(), index))
+        .collect::<HashMap<_, _>>();
+    let references = files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            referenced_names(file)
+                .iter()
+                .filter_map(|name| indices.get(name).copied())
+                // A file referring to itself keeps the plain name, which is served as well
+                .filter(|&referenced| referenced != index)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        files: &[WebFile],
+        references: &[Vec<usize>],
+        states: &mut [State],
+        order: &mut Vec<usize>,
+    ) {
+        match states[index] {
+            State::Done => return,
+            State::Visiting => panic!("{} refers to itself through other files", files[index].name),
+            State::New => {}
+        }
+        states[index] = State::Visiting;
+        for &referenced in references[index].iter() {
+            visit(referenced, files, references, states, order);
+        }
+        states[index] = State::Done;
+        order.push(index);
+    }
+
+    let mut states = vec![State::New; files.len()];
+    let mut order = Vec::with_capacity(files.len());
+    for index in 0..files.len() {
+        visit(index, &files, &references, &mut states, &mut order);
+    }
+
+    let mut files = files.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .map(|index| files[index].take().unwrap())
+        .collect()
+}
+
+/// The names of the files that a file refers to by their absolute paths `/web/<name>`.
+fn referenced_names(file: &WebFile) -> Vec<String> {
+    if !REWRITTEN_EXTENSIONS.contains(&extension(&file.name)) {
+        return Vec::new();
+    }
+    let mut names = Vec::new();
+    if let Ok(mut rest) = std::str::from_utf8(&file.contents) {
+        while let Some(start) = rest.find("/web/") {
+            let path = &rest[start + "/web/".len()..];
+            let end = path.find(PATH_DELIMITERS).unwrap_or(path.len());
+            names.push(path[..end].to_owned());
+            rest = &path[end..];
+        }
+    }
+    names
+}
+
+fn extension(name: &str) -> &str {
+    Path::new(name).extension().and_then(|extension| extension.to_str()).unwrap_or("")
+}
+
+/// Insert the hash before the extension of the file name, e.g. `viewer.<hash>.js`.
+fn hashed_file_name(name: &str, hash: &str) -> String {
+    let file_start = name.rfind('/').map_or(0, |slash| slash + 1);
+    match name[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}.{hash}{}", &name[..dot], &name[dot..])
+        }
+        _ => format!("{name}.{hash}"),
+    }
+}
+
+/// Replace the absolute paths `/web/<name>` of files that have a hashed name.
+fn rewrite_paths(text: &str, hashed_names: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("/web/") {
+        let (before, path) = rest.split_at(start + "/web/".len());
+        result.push_str(before);
+        let end = path.find(PATH_DELIMITERS).unwrap_or(path.len());
+        match hashed_names.get(&path[..end]) {
+            Some(hashed_name) => result.push_str(hashed_name),
+            None => result.push_str(&path[..end]),
+        }
+        rest = &path[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn gzip(contents: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(contents).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn brotli(contents: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut &contents[..], &mut compressed, &params).unwrap();
+    compressed
+}
+
+/// Store the compressed version next to the file, unless compressing didn't help.
+fn write_if_smaller(
+    output: &Path,
+    extension: &str,
+    compressed: Vec<u8>,
+    contents: &[u8],
+) -> Option<PathBuf> {
+    if compressed.len() >= contents.len() {
+        return None;
+    }
+    let mut path = output.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    let path = PathBuf::from(path);
+    std::fs::write(&path, compressed).unwrap();
+    Some(path)
+}
+
+fn include_optional(path: Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("Some(include_bytes!({path:?}))"),
+        None => "None".to_owned(),
+    }
+}
@@ -0,0 +1,142 @@
+//! CLI functions specific to the `albums` subcommand.
+
+use photo_archive::library::{Album, AlbumId, LibraryFiles, PhotoDatabase, PhotoFilter};
+
+use anyhow::{bail, format_err};
+use log::info;
+use std::borrow::Cow;
+
+use crate::cli;
+
+/// Create a new album, which is a smart album if the filter is not empty.
+pub fn create(
+    library: &LibraryFiles,
+    name: &str,
+    description: &str,
+    filter: &PhotoFilter,
+) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    if filter.is_empty() {
+        let id = photo_db.insert_album(name, description)?;
+        info!("Created album {} with id {}", name, id.0);
+    } else {
+        let id = photo_db.insert_smart_album(name, description, filter)?;
+        info!(
+            "Created smart album {} with id {} ({} photos)",
+            name,
+            id.0,
+            photo_db.query_filtered_photo_count(filter)?
+        );
+    }
+
+    Ok(())
+}
+
+/// Append photos to an album.
+pub fn add(library: &LibraryFiles, album_id: AlbumId, photos: &[String]) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let album = get_static_album(&photo_db, album_id)?;
+    let photo_ids = cli::resolve_photos(library, &photo_db, photos)?;
+
+    let added = photo_db.add_album_photos(album_id, &photo_ids)?;
+    info!("Added {} photos to album {}", added, album.name);
+
+    Ok(())
+}
+
+/// Remove photos from an album.
+pub fn remove(library: &LibraryFiles, album_id: AlbumId, photos: &[String]) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let album = get_static_album(&photo_db, album_id)?;
+    let photo_ids = cli::resolve_photos(library, &photo_db, photos)?;
+
+    let removed = photo_db.remove_album_photos(album_id, &photo_ids)?;
+    info!("Removed {} photos from album {}", removed, album.name);
+
+    Ok(())
+}
+
+/// Move a photo to a different position in an album.
+pub fn reorder(
+    library: &LibraryFiles,
+    album_id: AlbumId,
+    photo: &str,
+    position: usize,
+) -> Result<(), anyhow::Error> {
+    if position == 0 {
+        bail!("Positions start at 1");
+    }
+
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let album = get_static_album(&photo_db, album_id)?;
+    let photo_id = cli::resolve_photo(library, &photo_db, photo)?;
+
+    if !photo_db.move_album_photo(album_id, photo_id, position - 1)? {
+        bail!("Photo {} is not part of album {}", photo_id.0, album.name);
+    }
+    info!("Moved photo {} in album {}", photo_id.0, album.name);
+
+    Ok(())
+}
+
+/// List all albums.
+pub fn list(library: &LibraryFiles) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    println!("ID\tPhotos\tSmart\tName\tDescription");
+    for album in photo_db.query_albums()? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            album.id.0,
+            album.photo_count,
+            if album.filter.is_some() { "yes" } else { "no" },
+            album.name,
+            album.description
+        );
+    }
+
+    Ok(())
+}
+
+/// Show the photos of an album in order.
+pub fn show(library: &LibraryFiles, album_id: AlbumId) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let album = get_album(&photo_db, album_id)?;
+
+    println!("{}", album.name);
+    if !album.description.is_empty() {
+        println!("{}", album.description);
+    }
+    println!("total {}", album.photo_count);
+    println!("Position\tID\tCreated\tRelative path");
+    for (position, photo) in photo_db.query_album_photos(&album)?.iter().enumerate() {
+        println!(
+            "{}\t{}\t{}\t{}",
+            position + 1,
+            photo.id.0,
+            photo
+                .info
+                .created
+                .map_or(Cow::Borrowed("-"), |ts| Cow::Owned(ts.to_rfc3339())),
+            photo.relative_path,
+        );
+    }
+
+    Ok(())
+}
+
+fn get_album(photo_db: &PhotoDatabase, album_id: AlbumId) -> Result<Album, anyhow::Error> {
+    photo_db
+        .get_album(album_id)?
+        .ok_or_else(|| format_err!("No album with id {}", album_id.0))
+}
+
+/// Retrieve an album whose photos can be changed manually, i.e. that is not a smart album.
+fn get_static_album(photo_db: &PhotoDatabase, album_id: AlbumId) -> Result<Album, anyhow::Error> {
+    let album = get_album(photo_db, album_id)?;
+    if album.filter.is_some() {
+        bail!("The photos of smart album {} cannot be changed manually", album.name);
+    }
+    Ok(album)
+}
@@ -1,83 +1,249 @@
 //! Web server for browsing the photo collection.
 
 use crate::cli;
+use crate::cli::db_pool::PhotoDbPool;
+use crate::cli::events::EventFeed;
+use crate::cli::jobs::JobRunner;
+use crate::cli::login_throttle::LoginThrottle;
+use crate::cli::render_cache::RenderCache;
+use crate::cli::root_status::RootStatus;
+use crate::cli::tls::{self, TlsFiles};
+use actix_web::middleware::from_fn;
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
-use log::info;
-use photo_archive::library::{LibraryFiles, PhotoDatabase};
+use log::{info, warn};
+use photo_archive::library::LibraryFiles;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct WebData {
-    photo_db: Arc<Mutex<PhotoDatabase>>,
-    photo_root: PathBuf,
+    photo_db: PhotoDbPool,
+    library: LibraryFiles,
     web_root: Option<PathBuf>,
+    /// Whether clients must log in before accessing the library.
+    auth_required: bool,
+    /// A secret that clients can send as bearer token instead of logging in.
+    api_token: Option<String>,
+    render_cache: Arc<RenderCache>,
+    root_status: RootStatus,
+    events: EventFeed,
+    jobs: JobRunner,
+    login_throttle: Arc<LoginThrottle>,
 }
 
-impl WebData {
-    pub fn lock_photo_db(&self) -> MutexGuard<PhotoDatabase> {
-        if let Ok(guard) = self.photo_db.lock() {
-            guard
-        } else {
-            panic!("Photo database mutex was poisoned")
-        }
-    }
+/// Settings of the web server.
+pub struct BrowseOptions {
+    /// The addresses on which the web server listens.
+    pub binds: Vec<String>,
+    /// The directory from which the frontend is served instead of the builtin resources.
+    pub web_root: Option<PathBuf>,
+    /// Allow access without logging in.
+    pub no_auth: bool,
+    /// A secret that clients can send as bearer token instead of logging in.
+    pub api_token: Option<String>,
+    /// Serve HTTPS instead of HTTP.
+    pub tls: Option<TlsFiles>,
+    /// The directory in which resized photos are cached.
+    pub render_cache_dir: PathBuf,
+    /// The maximum total size of the cached resized photos in bytes.
+    pub render_cache_size: u64,
+    /// Serve the library without changing the photo database.
+    pub read_only: bool,
 }
 
 /// Start a webserver for browsing the library.
 pub async fn browse(
-    _context: &mut cli::AppContext,
+    context: &mut cli::AppContext,
     library: &LibraryFiles,
-    binds: &[String],
-    web_root: Option<PathBuf>,
+    options: BrowseOptions,
 ) -> Result<(), anyhow::Error> {
+    let photo_db = if options.read_only {
+        info!("Serving the library read-only, changes are rejected");
+        PhotoDbPool::open_read_only(&library.photo_db_file)?
+    } else {
+        PhotoDbPool::open(&library.photo_db_file)?
+    };
+    if options.no_auth {
+        warn!("Authentication is disabled, everyone who can reach the web server can access and change the library");
+    } else if photo_db.read(|db| db.query_users()).await?.is_empty() {
+        warn!("No users exist yet, create one with `photoctl users add <name>` in order to log in");
+    }
+    let events = EventFeed::start(photo_db.clone()).await?;
     let data = WebData {
-        photo_root: library.root_dir.to_path_buf(),
-        photo_db: Arc::new(Mutex::new(PhotoDatabase::open_or_create(&library.photo_db_file)?)),
-        web_root: web_root,
+        library: library.clone(),
+        photo_db,
+        web_root: options.web_root,
+        auth_required: !options.no_auth,
+        api_token: options.api_token.filter(|token| !token.is_empty()),
+        render_cache: Arc::new(RenderCache::open(
+            options.render_cache_dir,
+            options.render_cache_size,
+        )?),
+        root_status: RootStatus::new(library.clone()),
+        events,
+        jobs: JobRunner::new(library.clone(), context.progress().clone()),
+        login_throttle: Arc::new(LoginThrottle::default()),
     };
+    info!("Caching resized photos in {}", data.render_cache.directory().display());
+
+    let tls_config = options.tls.map(tls::server_config).transpose()?;
 
     info!("Starting web server");
 
     let factory = HttpServer::new(move || {
         App::new()
             .app_data(Data::new(data.clone()))
-            // REST API:
-            .service(web::resource("/photos").route(web::get().to(handlers::photos_get)))
-            .service(web::resource("/photos/{id}").route(web::get().to(handlers::photo_get)))
+            .app_data(web::QueryConfig::default().error_handler(handlers::query_error_handler))
+            .app_data(web::JsonConfig::default().error_handler(handlers::json_error_handler))
+            // Everything except for the login page and the frontend requires logging in
+            .wrap(from_fn(handlers::authenticate))
+            .wrap(from_fn(handlers::record_metrics))
+            // Monitoring:
+            .service(web::resource("/healthz").route(web::get().to(handlers::healthz_get)))
+            .service(web::resource("/metrics").route(web::get().to(handlers::metrics_get)))
             .service(
-                web::resource("/photos/{id}/thumbnail")
-                    .route(web::get().to(handlers::photo_thumbnail_get)),
+                web::resource("/api/openapi.json").route(web::get().to(handlers::openapi_get)),
             )
+            .service(web::resource("/login").route(web::get().to(handlers::static_file_handler)))
+            .service(web::resource("/s/{token}").route(web::get().to(handlers::share_page_get)))
+            // REST API, which is described in /api/openapi.json:
             .service(
-                web::resource("/photos/{id}/original")
-                    .route(web::get().to(handlers::photo_original_get)),
+                web::scope("/api/v1")
+                    .service(web::resource("/login").route(web::post().to(handlers::login_post)))
+                    .service(web::resource("/logout").route(web::post().to(handlers::logout_post)))
+                    .service(web::resource("/photos").route(web::get().to(handlers::photos_get)))
+                    .service(
+                        web::resource("/photos/count")
+                            .route(web::get().to(handlers::photos_count_get)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}")
+                            .route(web::get().to(handlers::photo_get))
+                            .route(web::patch().to(handlers::photo_patch)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}/tags")
+                            .route(web::get().to(handlers::photo_tags_get))
+                            .route(web::post().to(handlers::photo_tags_post)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}/tags/{tag}")
+                            .route(web::delete().to(handlers::photo_tag_delete)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}/thumbnail")
+                            .route(web::get().to(handlers::photo_thumbnail_get)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}/render")
+                            .route(web::get().to(handlers::photo_render_get)),
+                    )
+                    .service(
+                        web::resource("/photos/{id}/original")
+                            .route(web::get().to(handlers::photo_original_get))
+                            .route(web::head().to(handlers::photo_original_get)),
+                    )
+                    .service(web::resource("/events").route(web::get().to(handlers::events_get)))
+                    .service(
+                        web::resource("/download").route(web::post().to(handlers::download_post)),
+                    )
+                    .service(web::resource("/jobs").route(web::get().to(handlers::jobs_get)))
+                    .service(
+                        web::resource("/jobs/scan").route(web::post().to(handlers::scan_job_post)),
+                    )
+                    .service(
+                        web::resource("/jobs/thumbnails")
+                            .route(web::post().to(handlers::thumbnails_job_post)),
+                    )
+                    .service(web::resource("/jobs/{id}").route(web::get().to(handlers::job_get)))
+                    .service(
+                        web::resource("/jobs/{id}/cancel")
+                            .route(web::post().to(handlers::job_cancel_post)),
+                    )
+                    .service(
+                        web::resource("/timeline").route(web::get().to(handlers::timeline_get)),
+                    )
+                    .service(web::resource("/search").route(web::get().to(handlers::search_get)))
+                    .service(
+                        web::resource("/search/suggest")
+                            .route(web::get().to(handlers::search_suggest_get)),
+                    )
+                    .service(web::resource("/albums").route(web::get().to(handlers::albums_get)))
+                    .service(
+                        web::resource("/albums/{id}/photos")
+                            .route(web::get().to(handlers::album_photos_get))
+                            .route(web::post().to(handlers::album_photos_post)),
+                    )
+                    .service(
+                        web::resource("/albums/{id}/photos/{photo_id}")
+                            .route(web::patch().to(handlers::album_photo_patch))
+                            .route(web::delete().to(handlers::album_photo_delete)),
+                    )
+                    // Share links, which are authorized by the token in the path instead of logging in:
+                    .service(
+                        web::resource("/s/{token}/photos")
+                            .route(web::get().to(handlers::share_photos_get)),
+                    )
+                    .service(
+                        web::resource("/s/{token}/photos/{id}/thumbnail")
+                            .route(web::get().to(handlers::share_photo_thumbnail_get)),
+                    )
+                    .service(
+                        web::resource("/s/{token}/photos/{id}/render")
+                            .route(web::get().to(handlers::share_photo_render_get)),
+                    )
+                    .service(
+                        web::resource("/s/{token}/photos/{id}/original")
+                            .route(web::get().to(handlers::share_photo_original_get))
+                            .route(web::head().to(handlers::share_photo_original_get)),
+                    ),
             )
             // Frontend:
             .default_service(web::to(handlers::static_file_handler))
     });
-    let factory = binds.iter().fold(Ok(factory), |factory, address| factory?.bind(address))?;
+    let factory = options.binds.iter().try_fold(factory, |factory, address| match tls_config {
+        Some(ref config) => factory.bind_rustls_0_23(address, config.clone()),
+        None => factory.bind(address),
+    })?;
     factory.run().await?;
 
     Ok(())
 }
 
 mod handlers {
-    use actix_web::{http, web, Responder, HttpResponse, HttpRequest};
+    use actix_files::NamedFile;
+    use actix_web::cookie::{Cookie, SameSite};
+    use actix_web::body::BoxBody;
+    use actix_web::dev::{ServiceRequest, ServiceResponse};
+    use actix_web::middleware::Next;
+    use actix_web::{http, web, HttpMessage, Responder, HttpResponse, HttpRequest};
     use anyhow::format_err;
-    use log::{error};
-    use photo_archive::formats::Sha256Hash;
-    use photo_archive::library::{PhotoId, PhotoPath};
-    use serde::Serialize;
+    use log::{error, warn};
+    use crate::cli::db_pool::ReadOnlyError;
+    use crate::cli::events;
+    use crate::cli::export;
+    use crate::cli::jobs::{Job, JobId, JobKind, JobState, JobTask};
+    use crate::cli::metrics;
+    use photo_archive::formats::{self, RenderFit, RenderFormat, RenderOptions, Sha256Hash};
+    use photo_archive::library::auth::{self, AccessToken};
+    use photo_archive::library::{
+        Album, AlbumId, ColorLabel, Flag, Photo, PhotoChanges, PhotoCursor, PhotoDatabase, PhotoEventId, PhotoFilter, PhotoId,
+        PhotoOrder, PhotoQuery, QueryError, RootId, Share, TagError, TagName, TimelineGranularity,
+        User, MAX_RATING,
+    };
+    use serde::{Deserialize, Serialize};
+    use utoipa::{IntoParams, OpenApi, ToSchema};
+    use std::future::Future;
     use std::path::Path;
     use lazy_static::lazy_static;
     use std::collections::HashMap;
+    use std::time::Instant;
 
     use super::WebData;
 
     /// JSON formatted error response returned by all endpoints.
-    #[derive(Serialize)]
+    #[derive(Serialize, ToSchema)]
     struct ErrorResponse {
         message: String,
     }
@@ -94,229 +260,2101 @@ mod handlers {
         }
     }
 
-    #[derive(Serialize)]
+    #[derive(Serialize, ToSchema)]
     struct PhotoObject {
         id: PhotoId,
+        root: RootId,
         relative_path: String,
+        #[schema(required = true)]
+        created: Option<chrono::DateTime<chrono::Utc>>,
+        /// Whether the capture time was set by the user instead of being read from the file.
+        created_overridden: bool,
+        #[schema(required = true)]
+        title: Option<String>,
+        #[schema(required = true)]
+        comment: Option<String>,
+        rating: u8,
+        #[schema(required = true)]
+        flag: Option<Flag>,
+        #[schema(required = true)]
+        color_label: Option<ColorLabel>,
+        /// Whether the original file can currently be accessed,
+        /// i.e. the root directory it is stored in is online.
+        available: bool,
+    }
+
+    impl PhotoObject {
+        fn new(photo: Photo, available: bool) -> Self {
+            Self {
+                id: photo.id,
+                root: photo.root_id,
+                relative_path: photo.relative_path,
+                created: photo.info.created,
+                created_overridden: photo.created_overridden,
+                title: photo.title,
+                comment: photo.comment,
+                rating: photo.rating,
+                flag: photo.flag,
+                color_label: photo.color_label,
+                available,
+            }
+        }
+    }
+
+    /// A photo viewed through a share link. Unlike `PhotoObject`, it reveals nothing about how
+    /// the photo is stored in the library or how it has been annotated privately.
+    #[derive(Serialize, ToSchema)]
+    struct SharedPhotoObject {
+        id: PhotoId,
+        #[schema(required = true)]
         created: Option<chrono::DateTime<chrono::Utc>>,
+        #[schema(required = true)]
+        title: Option<String>,
+        /// Whether the original file can currently be accessed.
+        available: bool,
+    }
+
+    impl From<PhotoObject> for SharedPhotoObject {
+        fn from(photo: PhotoObject) -> Self {
+            Self {
+                id: photo.id,
+                created: photo.created,
+                title: photo.title,
+                available: photo.available,
+            }
+        }
+    }
+
+    /// Changes to the metadata of a photo. Missing fields are left unchanged,
+    /// empty strings remove the capture time override, title, comment, flag or color label.
+    #[derive(Deserialize, ToSchema)]
+    pub struct PhotoPatch {
+        /// The capture time, either in RFC 3339 format or as local time `YYYY-MM-DDTHH:MM:SS`.
+        created: Option<String>,
+        title: Option<String>,
+        comment: Option<String>,
+        rating: Option<u8>,
+        flag: Option<String>,
+        color_label: Option<String>,
+        /// Tags to assign, written as `<kind>=<value>`.
+        #[serde(default)]
+        add_tags: Vec<String>,
+        /// Tags to remove, written as `<kind>=<value>`.
+        #[serde(default)]
+        remove_tags: Vec<String>,
+    }
+
+    impl PhotoPatch {
+        /// Validate the patch, returning an error message if it is invalid.
+        fn to_changes(&self) -> Result<PhotoChanges, String> {
+            fn parse_tags(tags: &[String]) -> Result<Vec<TagName>, String> {
+                tags.iter()
+                    .map(|tag| tag.parse().map_err(|err: TagError| err.to_string()))
+                    .collect()
+            }
+            fn non_empty(value: &Option<String>) -> Option<Option<String>> {
+                value
+                    .as_ref()
+                    .map(|value| Some(value.clone()).filter(|value| !value.is_empty()))
+            }
+
+            if self.rating.filter(|rating| *rating > MAX_RATING).is_some() {
+                return Err(format!("Ratings must be between 0 and {}", MAX_RATING));
+            }
+            Ok(PhotoChanges {
+                created: self
+                    .created
+                    .as_deref()
+                    .map(parse_capture_time)
+                    .transpose()?,
+                title: non_empty(&self.title),
+                comment: non_empty(&self.comment),
+                rating: self.rating,
+                flag: self
+                    .flag
+                    .as_deref()
+                    .map(Flag::parse_optional)
+                    .transpose()
+                    .map_err(|err| err.to_string())?,
+                color_label: self
+                    .color_label
+                    .as_deref()
+                    .map(ColorLabel::parse_optional)
+                    .transpose()
+                    .map_err(|err| err.to_string())?,
+                add_tags: parse_tags(&self.add_tags)?,
+                remove_tags: parse_tags(&self.remove_tags)?,
+            })
+        }
+    }
+
+    /// Parse a capture time given in RFC 3339 format or as local time. An empty string stands for no time.
+    fn parse_capture_time(value: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+        use chrono::TimeZone;
+
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(created) = chrono::DateTime::parse_from_rfc3339(value) {
+            return Ok(Some(created.with_timezone(&chrono::Utc)));
+        }
+        chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+            .ok()
+            .and_then(|local| chrono::Local.from_local_datetime(&local).single())
+            .map(|local| Some(local.with_timezone(&chrono::Utc)))
+            .ok_or_else(|| {
+                format!("Invalid capture time {}, expected RFC 3339 or YYYY-MM-DDTHH:MM:SS", value)
+            })
+    }
+
+    #[derive(Deserialize, ToSchema)]
+    pub struct TagAssignment {
+        /// The tag written as `<kind>=<value>`.
+        tag: String,
+    }
+
+    #[derive(Deserialize, ToSchema)]
+    pub struct AlbumPhotosAddition {
+        photos: Vec<PhotoId>,
+    }
+
+    #[derive(Deserialize, ToSchema)]
+    pub struct AlbumPhotoPatch {
+        /// The new zero-based position of the photo within the album.
+        position: usize,
+    }
+
+    /// Query parameters for filtering and paginating the list of photos.
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct PhotosQuery {
+        min_rating: Option<u8>,
+        /// A query in the photo query language, e.g. `taken:2019 tag:people=Anna`.
+        q: Option<String>,
+        /// The first day (inclusive) on which the photos may have been taken.
+        from: Option<chrono::NaiveDate>,
+        /// The last day (inclusive) on which the photos may have been taken.
+        to: Option<chrono::NaiveDate>,
+        #[serde(default)]
+        sort: PhotoOrder,
+        /// The cursor `<created>,<id>` of the last photo of the previous page.
+        after: Option<String>,
+        /// The maximum number of photos per page, by default 500 and at most 5000.
+        limit: Option<u32>,
+    }
+
+    /// The number of photos per page if the request doesn't specify a limit.
+    const DEFAULT_PAGE_SIZE: u32 = 500;
+
+    /// The largest number of photos that are returned in a single page.
+    const MAX_PAGE_SIZE: u32 = 5000;
+
+    impl PhotosQuery {
+        fn filter(&self) -> Result<PhotoFilter, QueryError> {
+            let query = self.q.as_deref().map(str::parse::<PhotoQuery>).transpose()?;
+            Ok(PhotoFilter {
+                from: self.from,
+                to: self.to,
+                min_rating: self.min_rating,
+                query,
+                ..PhotoFilter::default()
+            })
+        }
+
+        fn page_size(&self) -> u32 {
+            self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+        }
+    }
+
+    /// The largest width or height in which photos can be rendered.
+    const MAX_RENDER_SIZE: u32 = 8192;
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct RenderQuery {
+        /// The maximum width of the rendered photo.
+        w: u32,
+        /// The maximum height of the rendered photo.
+        h: u32,
+        #[serde(default)]
+        fit: RenderFit,
+        #[serde(default)]
+        format: RenderFormat,
+    }
+
+    impl RenderQuery {
+        fn to_options(&self) -> Result<RenderOptions, String> {
+            let valid_size = 1..=MAX_RENDER_SIZE;
+            if !valid_size.contains(&self.w) || !valid_size.contains(&self.h) {
+                return Err(format!("Width and height must be between 1 and {}", MAX_RENDER_SIZE));
+            }
+            Ok(RenderOptions {
+                width: self.w,
+                height: self.h,
+                fit: self.fit,
+                format: self.format,
+            })
+        }
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct CountObject {
+        count: u32,
+    }
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct TimelineQuery {
+        granularity: Option<TimelineGranularity>,
+        /// A query in the photo query language restricting the photos on the timeline.
+        q: Option<String>,
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct TimelineBucketObject {
+        /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, or null for photos without a capture date.
+        #[schema(required = true)]
+        period: Option<String>,
+        photo_count: u32,
+        /// Offset of the first photo of the period in the default order of `/photos`.
+        offset: u32,
     }
 
-    /// A static file that is served by the builtin webserver.
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct SearchQuery {
+        q: String,
+    }
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct SuggestQuery {
+        prefix: String,
+        limit: Option<u32>,
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct SuggestionObject {
+        term: String,
+        photo_count: u32,
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct AlbumObject {
+        id: AlbumId,
+        name: String,
+        description: String,
+        photo_count: u32,
+        /// The filter selecting the photos of smart albums.
+        #[schema(required = true)]
+        filter: Option<PhotoFilter>,
+    }
+
+    impl From<Album> for AlbumObject {
+        fn from(album: Album) -> Self {
+            Self {
+                id: album.id,
+                name: album.name,
+                description: album.description,
+                photo_count: album.photo_count,
+                filter: album.filter,
+            }
+        }
+    }
+
+    /// The photos to download, selected either by id, by album or by a query.
+    #[derive(Deserialize, ToSchema)]
+    pub struct DownloadSelection {
+        photos: Option<Vec<PhotoId>>,
+        album: Option<AlbumId>,
+        /// A query in the photo query language, e.g. `taken:2019 tag:people=Anna`.
+        q: Option<String>,
+    }
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct ScanJobQuery {
+        /// Also read the files that have been scanned before again.
+        #[serde(default)]
+        rescan: bool,
+    }
+
+    #[derive(Deserialize, IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct ThumbnailsJobQuery {
+        #[serde(default)]
+        regenerate: bool,
+        #[serde(default)]
+        retry_failed: bool,
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct JobObject {
+        id: JobId,
+        kind: JobKind,
+        state: JobState,
+        /// The reason why the job failed.
+        #[schema(required = true)]
+        error: Option<String>,
+        started: chrono::DateTime<chrono::Utc>,
+        #[schema(required = true)]
+        finished: Option<chrono::DateTime<chrono::Utc>>,
+        progress: JobProgressObject,
+    }
+
+    /// The number of photos the job has seen so far and what happened to them.
+    /// For thumbnail jobs, `added` is the number of generated thumbnails.
+    #[derive(Serialize, ToSchema)]
+    struct JobProgressObject {
+        total: usize,
+        skipped: usize,
+        added: usize,
+        failed: usize,
+    }
+
+    impl<'a> From<&'a Job> for JobObject {
+        fn from(job: &'a Job) -> Self {
+            let stats = job.stats();
+            Self {
+                id: job.id(),
+                kind: job.kind(),
+                state: job.state(),
+                error: job.error(),
+                started: job.started(),
+                finished: job.finished(),
+                progress: JobProgressObject {
+                    total: stats.total(),
+                    skipped: stats.skipped(),
+                    added: stats.added(),
+                    failed: stats.failed(),
+                },
+            }
+        }
+    }
+
+    /// The description of the REST API, whose paths are relative to `/api/v1`.
+    #[derive(OpenApi)]
+    #[openapi(
+        info(title = "Photo Archive API"),
+        servers((url = "/api/v1")),
+        paths(
+            login_post,
+            logout_post,
+            photos_get,
+            photos_count_get,
+            photo_get,
+            photo_patch,
+            photo_tags_get,
+            photo_tags_post,
+            photo_tag_delete,
+            photo_thumbnail_get,
+            photo_render_get,
+            photo_original_get,
+            events_get,
+            download_post,
+            jobs_get,
+            scan_job_post,
+            thumbnails_job_post,
+            job_get,
+            job_cancel_post,
+            timeline_get,
+            search_get,
+            search_suggest_get,
+            albums_get,
+            album_photos_get,
+            album_photos_post,
+            album_photo_patch,
+            album_photo_delete,
+            share_photos_get,
+            share_photo_thumbnail_get,
+            share_photo_render_get,
+            share_photo_original_get,
+        ),
+        // Schemas that are only used by query parameters
+        components(schemas(PhotoOrder, TimelineGranularity, RenderFit, RenderFormat)),
+        tags(
+            (name = "photos", description = "Browsing and editing photos"),
+            (name = "albums", description = "Manually curated and smart albums"),
+            (name = "search", description = "Full text search"),
+            (name = "events", description = "Notifications about changes to the library"),
+            (name = "jobs", description = "Scans and thumbnail generation running in the background"),
+            (name = "shares", description = "Photos shared by link, accessible without logging in"),
+            (name = "session", description = "Logging in and out"),
+        )
+    )]
+    pub struct ApiDoc;
+
+    lazy_static! {
+        static ref OPENAPI_JSON: String = ApiDoc::openapi()
+            .to_pretty_json()
+            .expect("OpenAPI description cannot be serialized");
+    }
+
+    /// Serve the OpenAPI description of the REST API.
+    pub async fn openapi_get() -> impl Responder {
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(OPENAPI_JSON.as_str())
+    }
+
+    /// A file of the frontend, which is embedded into the binary by `build.rs`.
     struct StaticResource {
-        content_type: &'static str,
+        /// The path relative to `web/`.
+        name: &'static str,
+        /// The name containing a hash of the contents, which pages and scripts refer to.
+        /// Pages themselves have no hashed name, since their URLs must stay the same.
+        hashed_name: Option<&'static str>,
+        /// The SHA-256 hash of the contents as hex string.
+        hash: &'static str,
         contents: &'static [u8],
+        gzip: Option<&'static [u8]>,
+        brotli: Option<&'static [u8]>,
+    }
+
+    static EMBEDDED_RESOURCES: &[StaticResource] =
+        include!(concat!(env!("OUT_DIR"), "/static_resources.rs"));
+
+    /// An embedded resource along with how it can be cached when requested by a path.
+    struct StaticEntry {
+        resource: &'static StaticResource,
         /// The hash is used as ETag.
         hash: Sha256Hash,
+        /// Whether the path is the hashed name of the resource, whose contents never change.
+        immutable: bool,
     }
 
-    macro_rules! static_resource {
-        ($content_type: expr, $path:expr) => {
-            {
-                let contents: &'static [u8] = include_bytes!($path);
-                StaticResource {
-                    content_type: $content_type,
-                    contents: contents,
-                    hash: Sha256Hash::hash_bytes(contents),
+    lazy_static! {
+        static ref STATIC_RESOURCES: HashMap<String, StaticEntry> = {
+            let mut resources = HashMap::new();
+            for resource in EMBEDDED_RESOURCES {
+                let hash = Sha256Hash::from_hex(resource.hash.as_bytes())
+                    .expect("Embedded resources have a valid hash");
+                let names = std::iter::once((resource.name, false))
+                    .chain(resource.hashed_name.map(|name| (name, true)));
+                for (name, immutable) in names {
+                    resources.insert(
+                        format!("/web/{}", name),
+                        StaticEntry { resource, hash: hash.clone(), immutable },
+                    );
                 }
             }
+            resources
         };
     }
 
-    lazy_static!{
-        static ref STATIC_RESOURCES: HashMap<&'static str, StaticResource> = {
-            let mut resources = HashMap::new();
-            resources.insert("/web/favicon.ico",
-                static_resource!("image/x-icon", "../../../web/favicon.ico"));
-            resources.insert("/web/favicon.png",
-                static_resource!("image/png", "../../../web/favicon.png"));
-            resources.insert("/web/index.html",
-                static_resource!("text/html; charset=utf-8", "../../../web/index.html"));
-            resources.insert("/web/viewer.js",
-                static_resource!("text/javascript; charset=utf-8", "../../../web/viewer.js"));
-            resources
-        };
+    /// Cache hashed names forever, but let browsers check for new versions of everything else.
+    const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+    const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+
+    /// The content type of a static file by its extension.
+    fn static_content_type(name: &str) -> &'static str {
+        match Path::new(name).extension().and_then(|extension| extension.to_str()) {
+            Some("html") => "text/html; charset=utf-8",
+            Some("js") => "text/javascript; charset=utf-8",
+            Some("css") => "text/css; charset=utf-8",
+            Some("json") | Some("map") => "application/json",
+            Some("txt") => "text/plain; charset=utf-8",
+            Some("svg") => "image/svg+xml",
+            Some("png") => "image/png",
+            Some("ico") => "image/x-icon",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("webp") => "image/webp",
+            Some("woff2") => "font/woff2",
+            _ => "application/octet-stream",
+        }
+    }
+
+    /// Whether the client accepts responses in the given content encoding.
+    fn accepts_encoding(request: &HttpRequest, encoding: &str) -> bool {
+        request
+            .headers()
+            .get_all(http::header::ACCEPT_ENCODING)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let name = parts.next().unwrap_or("");
+                // Encodings with a quality of zero are explicitly refused
+                let refused = parts.any(|parameter| {
+                    matches!(parameter.strip_prefix("q="), Some(quality) if quality.parse() == Ok(0.0))
+                });
+                (name.eq_ignore_ascii_case(encoding) || name == "*") && !refused
+            })
     }
 
     fn static_response(
-        req_etag: Option<Sha256Hash>,
-        content_type: &str,
-        etag: Option<Sha256Hash>,
-        data: web::Bytes,
+        request: &HttpRequest,
+        name: &str,
+        hash: &Sha256Hash,
+        cache_control: &str,
+        encoding: Option<&str>,
+        contents: web::Bytes,
     ) -> HttpResponse {
-        if req_etag.is_some() && req_etag == etag {
-            HttpResponse::NotModified().into()
+        let not_modified = static_etag_matches(request, hash);
+        let mut response = if not_modified {
+            HttpResponse::NotModified()
         } else {
-            if let Some(etag) = etag {
-                // If the resource has an etag, send caching append_headers( as well)
-                HttpResponse::Ok()
-                    .content_type(content_type)
-                    .append_header(("Cache-Control", "private, max-age=3600"))
-                    .append_header(("ETag", format!("\"{}\"", etag)))
-                    .body(data)
-            } else {
-                // Otherwise only send the data
-                HttpResponse::Ok()
-                    .content_type(content_type)
-                    .body(data)
-            }
+            HttpResponse::Ok()
+        };
+        // Strong validators must differ between content codings
+        let etag = match encoding {
+            Some(encoding) => format!("\"{}-{}\"", hash, encoding),
+            None => format!("\"{}\"", hash),
+        };
+        response
+            .append_header(("Cache-Control", cache_control))
+            .append_header(("ETag", etag));
+        if let Some(encoding) = encoding {
+            response.append_header((http::header::CONTENT_ENCODING, encoding));
+        }
+        if not_modified {
+            response.finish()
+        } else {
+            response.content_type(static_content_type(name)).body(contents)
+        }
+    }
+
+    /// Check whether `If-None-Match` lists the ETag of any encoding of a static file with the
+    /// given hash. Since all of them have the same contents, the client's copy is still current.
+    fn static_etag_matches(request: &HttpRequest, hash: &Sha256Hash) -> bool {
+        let hash = hash.to_string();
+        request
+            .headers()
+            .get_all(http::header::IF_NONE_MATCH)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"'))
+            .any(|tag| {
+                tag == "*"
+                    || tag == hash
+                    || matches!(tag.strip_prefix(hash.as_str()), Some("-br") | Some("-gzip"))
+            })
+    }
+
+    /// Respond with an embedded resource, compressed if the client supports it.
+    fn embedded_response(request: &HttpRequest, entry: &StaticEntry) -> HttpResponse {
+        let resource = entry.resource;
+        let (encoding, contents) = match (resource.brotli, resource.gzip) {
+            (Some(brotli), _) if accepts_encoding(request, "br") => (Some("br"), brotli),
+            (_, Some(gzip)) if accepts_encoding(request, "gzip") => (Some("gzip"), gzip),
+            _ => (None, resource.contents),
+        };
+        let cache_control = if entry.immutable {
+            IMMUTABLE_CACHE_CONTROL
+        } else {
+            REVALIDATE_CACHE_CONTROL
+        };
+        let mut response =
+            static_response(request, resource.name, &entry.hash, cache_control, encoding, contents.into());
+        if resource.gzip.is_some() || resource.brotli.is_some() {
+            // The response depends on the encodings accepted by the client
+            response.headers_mut().insert(
+                http::header::VARY,
+                http::header::HeaderValue::from_static("Accept-Encoding"),
+            );
         }
+        response
+    }
+
+    /// Respond with a file from the web root given on the command line, which is used
+    /// instead of the embedded resources while developing the frontend.
+    async fn web_root_response(
+        request: &HttpRequest,
+        web_root: &Path,
+        name: &str,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        // Only serve files within the web root
+        let is_relative = Path::new(name)
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+        let filename = web_root.join(name);
+        let contents = if is_relative {
+            web::block(move || filename.is_file().then(|| std::fs::read(&filename)).transpose())
+                .await??
+        } else {
+            None
+        };
+        Ok(match contents {
+            Some(contents) => {
+                let hash = Sha256Hash::hash_bytes(&contents);
+                static_response(request, name, &hash, REVALIDATE_CACHE_CONTROL, None, contents.into())
+            }
+            None => static_file_not_found(),
+        })
+    }
+
+    fn static_file_not_found() -> HttpResponse {
+        HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(ErrorResponse::from("Not found"))
     }
 
     pub async fn static_file_handler(data: web::Data<WebData>, request: HttpRequest) -> impl Responder {
-        error_handler(|| {
-            let request_etag = get_if_none_match_sha256(&request);
+        error_handler(async {
             let rewritten_path = match request.path() {
                 "/" => "/web/index.html",
                 "/favicon.ico" => "/web/favicon.ico",
+                "/login" => "/web/login.html",
                 path => path,
             };
-            if let Some(resource) = STATIC_RESOURCES.get(rewritten_path) {
-                let (hash, contents) = if let Some(ref web_root) = data.web_root {
-                    // At this point, we have established that the path is a valid static resource,
-                    // and that it starts with `/web/` (otherwise there would be no matching entry in the hashmap).
-                    let filename = web_root.join(Path::new(rewritten_path.trim_start_matches("/web/")));
-                    let contents = std::fs::read(&filename)?;
-                    let hash = Sha256Hash::hash_bytes(&contents);
-                    (hash, contents.into())
-                } else {
-                    // use the builtin resources
-                    (resource.hash.clone(), resource.contents.into())
-                };
-                Ok(static_response(request_etag, resource.content_type, Some(hash), contents))
-            } else {
-                Ok(HttpResponse::NotFound()
-                    .content_type("application/json")
-                    .json(ErrorResponse::from("Not found")))
+            static_file_response(&data, &request, rewritten_path).await
+        })
+        .await
+    }
+
+    async fn static_file_response(
+        data: &WebData,
+        request: &HttpRequest,
+        rewritten_path: &str,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        match (&data.web_root, rewritten_path.strip_prefix("/web/")) {
+            (Some(web_root), Some(name)) => web_root_response(request, web_root, name).await,
+            (None, _) => Ok(STATIC_RESOURCES
+                .get(rewritten_path)
+                .map_or_else(static_file_not_found, |entry| embedded_response(request, entry))),
+            (Some(_), None) => Ok(static_file_not_found()),
+        }
+    }
+
+    /// List the photos matching the filter, one page at a time. If there may be more photos,
+    /// the URL of the next page is returned in a `Link` header with `rel="next"`.
+    #[utoipa::path(
+        get,
+        path = "/photos",
+        tag = "photos",
+        params(
+            PhotosQuery,
+        ),
+        responses(
+            (status = 200, description = "The matching photos. If there are more pages, the `Link` header refers to the next one.", body = [PhotoObject]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+        )
+    )]
+    pub async fn photos_get(
+        data: web::Data<WebData>,
+        query: web::Query<PhotosQuery>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        error_handler(async {
+            let filter = match query.filter() {
+                Ok(filter) => filter,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let after = match query.after.as_deref().map(str::parse::<PhotoCursor>).transpose() {
+                Ok(after) => after,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let (sort, limit) = (query.sort, query.page_size());
+            let photos = data
+                .photo_db
+                .read(move |db| db.query_photo_page(&filter, sort, after.as_ref(), Some(limit)))
+                .await?;
+
+            let mut response = HttpResponse::Ok();
+            let next_cursor = match photos.last() {
+                Some(last) if photos.len() == limit as usize => Some(PhotoCursor::after(last)),
+                _ => None,
+            };
+            if let Some(cursor) = next_cursor {
+                // Keep all other parameters of the current request
+                let mut params = req
+                    .query_string()
+                    .split('&')
+                    .filter(|param| !param.is_empty() && !param.starts_with("after="))
+                    .collect::<Vec<_>>();
+                let after_param = format!("after={}", cursor);
+                params.push(&after_param);
+                response.insert_header((
+                    http::header::LINK,
+                    format!("<{}?{}>; rel=\"next\"", req.path(), params.join("&")),
+                ));
             }
+            Ok(response
+                .content_type("application/json")
+                .json(photo_objects(&data, photos).await?))
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/photos/count",
+        tag = "photos",
+        params(
+            PhotosQuery,
+        ),
+        responses(
+            (status = 200, body = CountObject),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+        )
+    )]
+    pub async fn photos_count_get(
+        data: web::Data<WebData>,
+        query: web::Query<PhotosQuery>,
+    ) -> impl Responder {
+        error_handler(async {
+            let filter = match query.filter() {
+                Ok(filter) => filter,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let count = data
+                .photo_db
+                .read(move |db| db.query_filtered_photo_count(&filter))
+                .await?;
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(CountObject { count }))
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/timeline",
+        tag = "photos",
+        params(
+            TimelineQuery,
+        ),
+        responses(
+            (status = 200, body = [TimelineBucketObject]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+        )
+    )]
+    pub async fn timeline_get(
+        data: web::Data<WebData>,
+        query: web::Query<TimelineQuery>,
+    ) -> impl Responder {
+        error_handler(async {
+            let filter = match query.q.as_deref().map(str::parse::<PhotoQuery>).transpose() {
+                Ok(query) => PhotoFilter {
+                    query,
+                    ..PhotoFilter::default()
+                },
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let granularity = query.granularity.unwrap_or(TimelineGranularity::Month);
+            let buckets = data
+                .photo_db
+                .read(move |db| db.query_timeline(&filter, granularity))
+                .await?
+                .into_iter()
+                .map(|bucket| TimelineBucketObject {
+                    period: bucket.period,
+                    photo_count: bucket.photo_count,
+                    offset: bucket.offset,
+                })
+                .collect::<Vec<_>>();
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(buckets))
+        })
+        .await
+    }
+
+    /// Convert photos to their JSON representation.
+    async fn photo_objects(
+        data: &WebData,
+        photos: Vec<Photo>,
+    ) -> Result<Vec<PhotoObject>, anyhow::Error> {
+        let available_roots = data.root_status.available_roots().await?;
+        Ok(photos
+            .into_iter()
+            .map(|photo| {
+                let available = available_roots.contains(&photo.root_id);
+                PhotoObject::new(photo, available)
+            })
+            .collect())
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/search",
+        tag = "search",
+        params(
+            SearchQuery,
+        ),
+        responses(
+            (status = 200, description = "The matching photos, most relevant first", body = [PhotoObject]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+        )
+    )]
+    pub async fn search_get(
+        data: web::Data<WebData>,
+        query: web::Query<SearchQuery>,
+    ) -> impl Responder {
+        error_handler(async {
+            let query = query.into_inner();
+            let photos = data.photo_db.read(move |db| db.search_photos(&query.q)).await?;
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(photo_objects(&data, photos).await?))
         })
+        .await
     }
 
-    pub async fn photos_get(data: web::Data<WebData>) -> impl Responder {
-        error_handler(|| {
-            let photos = data.lock_photo_db().query_all_photos()?;
-            let photo_objects = photos
+    #[utoipa::path(
+        get,
+        path = "/search/suggest",
+        tag = "search",
+        params(
+            SuggestQuery,
+        ),
+        responses(
+            (status = 200, body = [SuggestionObject]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+        )
+    )]
+    pub async fn search_suggest_get(
+        data: web::Data<WebData>,
+        query: web::Query<SuggestQuery>,
+    ) -> impl Responder {
+        error_handler(async {
+            let query = query.into_inner();
+            let suggestions = data
+                .photo_db
+                .read(move |db| db.suggest_search_terms(&query.prefix, query.limit.unwrap_or(10)))
+                .await?
                 .into_iter()
-                .map(|photo| PhotoObject {
-                    id: photo.id,
-                    relative_path: photo.relative_path,
-                    created: photo.info.created,
+                .map(|suggestion| SuggestionObject {
+                    term: suggestion.term,
+                    photo_count: suggestion.photo_count,
                 })
                 .collect::<Vec<_>>();
 
             Ok(HttpResponse::Ok()
                 .content_type("application/json")
-                .json(photo_objects))
+                .json(suggestions))
         })
+        .await
     }
 
-    pub async fn photo_get(data: web::Data<WebData>, info: web::Path<i64>) -> impl Responder {
-        error_handler(|| {
-            let photo = data.lock_photo_db().get_photo(PhotoId(*info))?;
+    #[utoipa::path(
+        get,
+        path = "/albums",
+        tag = "albums",
+        responses(
+            (status = 200, body = [AlbumObject]),
+        )
+    )]
+    pub async fn albums_get(data: web::Data<WebData>) -> impl Responder {
+        error_handler(async {
+            let albums = data.photo_db.read(|db| db.query_albums()).await?;
+            let album_objects = albums
+                .into_iter()
+                .map(AlbumObject::from)
+                .collect::<Vec<_>>();
 
-            let response = if let Some(photo) = photo {
-                HttpResponse::Ok()
-                    .content_type("application/json")
-                    .json(PhotoObject {
-                        id: photo.id,
-                        relative_path: photo.relative_path,
-                        created: photo.info.created,
-                    })
-            } else {
-                HttpResponse::NotFound()
-                    .content_type("application/json")
-                    .json(ErrorResponse::from("Photo not found"))
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(album_objects))
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/albums/{id}/photos",
+        tag = "albums",
+        params(
+            ("id" = i64, Path, description = "The id of the album"),
+        ),
+        responses(
+            (status = 200, description = "The photos of the album in order", body = [PhotoObject]),
+            (status = 404, description = "Album not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn album_photos_get(data: web::Data<WebData>, info: web::Path<i64>) -> impl Responder {
+        error_handler(async {
+            let album_id = AlbumId(*info);
+            let photos = data
+                .photo_db
+                .read(move |db| match db.get_album(album_id)? {
+                    Some(album) => db.query_album_photos(&album).map(Some),
+                    None => Ok(None),
+                })
+                .await?;
+            let photos = match photos {
+                Some(photos) => photos,
+                None => return Ok(album_not_found()),
             };
-            Ok(response)
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(photo_objects(&data, photos).await?))
         })
+        .await
     }
 
-    pub async fn photo_original_get(
-        req: HttpRequest,
+    /// Append photos to a (non-smart) album, returning the number of photos that were added.
+    #[utoipa::path(
+        post,
+        path = "/albums/{id}/photos",
+        tag = "albums",
+        params(
+            ("id" = i64, Path, description = "The id of the album"),
+        ),
+        request_body = AlbumPhotosAddition,
+        responses(
+            (status = 200, description = "The number of photos that were added", body = CountObject),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Album not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn album_photos_post(
         data: web::Data<WebData>,
         info: web::Path<i64>,
+        addition: web::Json<AlbumPhotosAddition>,
     ) -> impl Responder {
-        error_handler(|| {
-            let photo_id = PhotoId(*info);
-            let etag_request = get_if_none_match_sha256(&req);
-
-            let result = {
-                let db = data.lock_photo_db();
-                let maybe_photo = db.get_photo(photo_id)?;
-                if let Some(photo) = maybe_photo {
-                    // early exit if the etag matches
-                    if Some(&photo.info.file_hash) == etag_request.as_ref() {
-                        return Ok(HttpResponse::NotModified().into());
+        error_handler(async {
+            let album_id = AlbumId(*info);
+            let photos = addition.into_inner().photos;
+            let result = data
+                .photo_db
+                .write(move |db| {
+                    if let Err(err) = check_static_album(db, album_id)? {
+                        return Ok(Err(err));
                     }
-                    // otherwise load the image file
-                    let path = PhotoPath::from_relative(&data.photo_root, &photo.relative_path);
-                    let data = std::fs::read(path.full_path)?;
-                    Some((data, photo.info.file_hash, "image/jpeg"))
-                } else {
-                    None
-                }
-            };
+                    for photo_id in photos.iter() {
+                        if db.get_photo(*photo_id)?.is_none() {
+                            return Ok(Err(AlbumChangeError::MissingPhoto(*photo_id)));
+                        }
+                    }
+                    db.add_album_photos(album_id, &photos).map(Ok)
+                })
+                .await?;
 
-            let response = if let Some((image_data, etag, content_type)) = result {
-                HttpResponse::Ok()
-                    .content_type(content_type)
-                    .append_header(("ETag", format!("\"{}\"", etag)))
-                    .append_header(("Cache-Control", "private, max-age=3600"))
-                    .body(image_data)
-            } else {
-                HttpResponse::NotFound()
+            Ok(match result {
+                Ok(added) => HttpResponse::Ok()
                     .content_type("application/json")
-                    .json(ErrorResponse::from("Photo not found"))
-            };
-            Ok(response)
+                    .json(CountObject { count: added as u32 }),
+                Err(err) => err.response(),
+            })
         })
+        .await
     }
 
-    pub async fn photo_thumbnail_get(
-        req: HttpRequest,
+    /// Move a photo of an album to a different position.
+    #[utoipa::path(
+        patch,
+        path = "/albums/{id}/photos/{photo_id}",
+        tag = "albums",
+        params(
+            ("id" = i64, Path, description = "The id of the album"),
+            ("photo_id" = i64, Path, description = "The id of the photo"),
+        ),
+        request_body = AlbumPhotoPatch,
+        responses(
+            (status = 204, description = "The photo was moved"),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Album or photo not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn album_photo_patch(
         data: web::Data<WebData>,
-        info: web::Path<i64>,
+        info: web::Path<(i64, i64)>,
+        patch: web::Json<AlbumPhotoPatch>,
     ) -> impl Responder {
-        error_handler(|| {
-            let photo_id = PhotoId(*info);
-            let etag_request = get_if_none_match_sha256(&req);
+        error_handler(async {
+            let (album_id, photo_id) = (AlbumId(info.0), PhotoId(info.1));
+            let position = patch.position;
+            let result = data
+                .photo_db
+                .write(move |db| {
+                    if let Err(err) = check_static_album(db, album_id)? {
+                        return Ok(Err(err));
+                    }
+                    db.move_album_photo(album_id, photo_id, position).map(Ok)
+                })
+                .await?;
 
-            let (etag_result, thumbnail_result) = {
-                let db = data.lock_photo_db();
-                let etag_result = db.query_thumbnail_hash(photo_id)?;
-                // early exit if the etag matches
-                if let Some(etag) = db.query_thumbnail_hash(photo_id)? {
-                    if Some(etag) == etag_request {
-                        return Ok(HttpResponse::NotModified().into());
+            Ok(match result {
+                Ok(true) => HttpResponse::NoContent().finish(),
+                Ok(false) => HttpResponse::NotFound()
+                    .content_type("application/json")
+                    .json(ErrorResponse::from("Photo not found in album")),
+                Err(err) => err.response(),
+            })
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/albums/{id}/photos/{photo_id}",
+        tag = "albums",
+        params(
+            ("id" = i64, Path, description = "The id of the album"),
+            ("photo_id" = i64, Path, description = "The id of the photo"),
+        ),
+        responses(
+            (status = 204, description = "The photo was removed from the album"),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Album or photo not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn album_photo_delete(
+        data: web::Data<WebData>,
+        info: web::Path<(i64, i64)>,
+    ) -> impl Responder {
+        error_handler(async {
+            let (album_id, photo_id) = (AlbumId(info.0), PhotoId(info.1));
+            let result = data
+                .photo_db
+                .write(move |db| {
+                    if let Err(err) = check_static_album(db, album_id)? {
+                        return Ok(Err(err));
                     }
+                    db.remove_album_photos(album_id, &[photo_id]).map(Ok)
+                })
+                .await?;
+
+            Ok(match result {
+                Ok(removed) if removed > 0 => HttpResponse::NoContent().finish(),
+                Ok(_) => HttpResponse::NotFound()
+                    .content_type("application/json")
+                    .json(ErrorResponse::from("Photo not found in album")),
+                Err(err) => err.response(),
+            })
+        })
+        .await
+    }
+
+    /// Reasons for rejecting changes to the photos of an album.
+    enum AlbumChangeError {
+        AlbumNotFound,
+        SmartAlbum(String),
+        MissingPhoto(PhotoId),
+    }
+
+    impl AlbumChangeError {
+        fn response(self) -> HttpResponse {
+            match self {
+                AlbumChangeError::AlbumNotFound => album_not_found(),
+                AlbumChangeError::SmartAlbum(name) => bad_request(format!(
+                    "The photos of the smart album {} are selected by its filter",
+                    name
+                )),
+                AlbumChangeError::MissingPhoto(photo_id) => {
+                    bad_request(format!("Photo {} does not exist", photo_id.0))
                 }
-                // otherwise, get the thumbnail and send it
-                (etag_result, db.query_thumbnail(photo_id)?)
-            };
+            }
+        }
+    }
+
+    /// Check that the photos of an album can be changed, i.e. that it exists and is not a smart album.
+    fn check_static_album(
+        db: &PhotoDatabase,
+        album_id: AlbumId,
+    ) -> Result<Result<(), AlbumChangeError>, anyhow::Error> {
+        let result = match db.get_album(album_id)? {
+            Some(album) if album.filter.is_some() => Err(AlbumChangeError::SmartAlbum(album.name)),
+            Some(_) => Ok(()),
+            None => Err(AlbumChangeError::AlbumNotFound),
+        };
+        Ok(result)
+    }
+
+    fn album_not_found() -> HttpResponse {
+        HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(ErrorResponse::from("Album not found"))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/photos/{id}",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        responses(
+            (status = 200, body = PhotoObject),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_get(data: web::Data<WebData>, info: web::Path<i64>) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(*info);
+            let photo = data.photo_db.read(move |db| db.get_photo(photo_id)).await?;
 
-            let response = if let Some(thumbnail) = thumbnail_result {
-                let etag =
-                    etag_result.ok_or(format_err!("Thumbnail {:?} without hash", photo_id))?;
+            let response = if let Some(photo) = photo {
+                let available = data.root_status.is_photo_available(&photo).await?;
                 HttpResponse::Ok()
-                    .content_type("image/jpeg")
-                    .append_header(("ETag", format!("\"{}\"", etag)))
-                    .append_header(("Cache-Control", "private, max-age=3600"))
-                    .body(thumbnail.into_jpg_bytes())
+                    .content_type("application/json")
+                    .json(PhotoObject::new(photo, available))
             } else {
                 HttpResponse::NotFound()
                     .content_type("application/json")
-                    .json(ErrorResponse::from("This photo has no thumbnail"))
+                    .json(ErrorResponse::from("Photo not found"))
             };
             Ok(response)
         })
+        .await
     }
 
-    fn error_handler<F: FnOnce() -> Result<HttpResponse, anyhow::Error>>(
-        callback: F,
-    ) -> HttpResponse {
-        match callback() {
-            Ok(response) => response,
-            Err(err) => {
-                error!("Error while handling request: {}", err);
+    #[utoipa::path(
+        patch,
+        path = "/photos/{id}",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        request_body = PhotoPatch,
+        responses(
+            (status = 200, description = "The changed photo", body = PhotoObject),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_patch(
+        data: web::Data<WebData>,
+        info: web::Path<i64>,
+        patch: web::Json<PhotoPatch>,
+    ) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(*info);
+
+            // Validate everything before changing anything
+            let changes = match patch.to_changes() {
+                Ok(changes) => changes,
+                Err(message) => return Ok(bad_request(message)),
+            };
+
+            let result = data
+                .photo_db
+                .write(move |db| match db.update_photo_metadata(photo_id, &changes) {
+                    Ok(true) => db.get_photo(photo_id).map(Ok),
+                    Ok(false) => Ok(Ok(None)),
+                    Err(err) => Ok(Err(err)),
+                })
+                .await?;
+            let photo = match result {
+                Ok(photo) => photo,
+                Err(err) => return tag_error_response(err),
+            };
+
+            let response = if let Some(photo) = photo {
+                let available = data.root_status.is_photo_available(&photo).await?;
+                HttpResponse::Ok()
+                    .content_type("application/json")
+                    .json(PhotoObject::new(photo, available))
+            } else {
+                photo_not_found()
+            };
+            Ok(response)
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/photos/{id}/tags",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        responses(
+            (status = 200, body = [TagName]),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_tags_get(data: web::Data<WebData>, info: web::Path<i64>) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(*info);
+            let tags = data
+                .photo_db
+                .read(move |db| match db.get_photo(photo_id)? {
+                    Some(_) => db.query_photo_tags(photo_id).map(Some),
+                    None => Ok(None),
+                })
+                .await?;
+
+            Ok(match tags {
+                Some(tags) => HttpResponse::Ok()
+                    .content_type("application/json")
+                    .json(tags),
+                None => photo_not_found(),
+            })
+        })
+        .await
+    }
+
+    /// Assign a tag to a photo, returning all tags of the photo.
+    #[utoipa::path(
+        post,
+        path = "/photos/{id}/tags",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        request_body = TagAssignment,
+        responses(
+            (status = 200, description = "The tags of the photo", body = [TagName]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_tags_post(
+        data: web::Data<WebData>,
+        info: web::Path<i64>,
+        assignment: web::Json<TagAssignment>,
+    ) -> impl Responder {
+        error_handler(async {
+            let tag = match assignment.tag.parse::<TagName>() {
+                Ok(tag) => tag,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let changes = PhotoChanges {
+                add_tags: vec![tag],
+                ..PhotoChanges::default()
+            };
+            change_photo_tags(&data, PhotoId(*info), changes).await
+        })
+        .await
+    }
+
+    /// Remove a tag from a photo, returning the remaining tags of the photo.
+    #[utoipa::path(
+        delete,
+        path = "/photos/{id}/tags/{tag}",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+            ("tag" = String, Path, description = "The tag written as `<kind>=<value>`"),
+        ),
+        responses(
+            (status = 200, description = "The remaining tags of the photo", body = [TagName]),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_tag_delete(
+        data: web::Data<WebData>,
+        info: web::Path<(i64, String)>,
+    ) -> impl Responder {
+        error_handler(async {
+            let tag = match info.1.parse::<TagName>() {
+                Ok(tag) => tag,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            let changes = PhotoChanges {
+                remove_tags: vec![tag],
+                ..PhotoChanges::default()
+            };
+            change_photo_tags(&data, PhotoId(info.0), changes).await
+        })
+        .await
+    }
+
+    async fn change_photo_tags(
+        data: &WebData,
+        photo_id: PhotoId,
+        changes: PhotoChanges,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let result = data
+            .photo_db
+            .write(move |db| match db.update_photo_metadata(photo_id, &changes) {
+                Ok(true) => db.query_photo_tags(photo_id).map(|tags| Ok(Some(tags))),
+                Ok(false) => Ok(Ok(None)),
+                Err(err) => Ok(Err(err)),
+            })
+            .await?;
+        match result {
+            Ok(Some(tags)) => Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(tags)),
+            Ok(None) => Ok(photo_not_found()),
+            Err(err) => tag_error_response(err),
+        }
+    }
+
+    /// Turn errors caused by invalid tags into a bad request response.
+    fn tag_error_response(err: anyhow::Error) -> Result<HttpResponse, anyhow::Error> {
+        match err.downcast_ref::<TagError>() {
+            Some(tag_error) => Ok(bad_request(tag_error.to_string())),
+            None => Err(err),
+        }
+    }
+
+    #[utoipa::path(
+        method(get, head),
+        path = "/photos/{id}/original",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        responses(
+            (status = 200, description = "The original file of the photo", content_type = "image/jpeg"),
+            (status = 206, description = "The requested range of the original file", content_type = "image/jpeg"),
+            (status = 304, description = "The cached version is still current"),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+            (status = 503, description = "The root of the photo is offline", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_original_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<i64>,
+    ) -> impl Responder {
+        error_handler(photo_original_response(&req, &data, PhotoId(*info))).await
+    }
+
+    /// Stream the original file of a photo. Besides `If-None-Match`, this supports range requests
+    /// and `If-Modified-Since` based on the modification time of the file.
+    async fn photo_original_response(
+        req: &HttpRequest,
+        data: &WebData,
+        photo_id: PhotoId,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let etag_request = get_if_none_match_sha256(req);
+
+        let photo = match data.photo_db.read(move |db| db.get_photo(photo_id)).await? {
+            Some(photo) => photo,
+            None => return Ok(photo_not_found()),
+        };
+        // early exit if the etag matches
+        if Some(&photo.info.file_hash) == etag_request.as_ref() {
+            return Ok(HttpResponse::NotModified().into());
+        }
+        if !data.root_status.is_photo_available(&photo).await? {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .content_type("application/json")
+                .json(ErrorResponse::from("The root of this photo is offline")));
+        }
+        let path = data
+            .library
+            .get_full_path(&photo)
+            .ok_or_else(|| format_err!("Photo {:?} has no root", photo_id))?;
+
+        // The file is read in chunks on the blocking thread pool while the response is streamed
+        let file = web::block(move || NamedFile::open(path)).await??;
+        // The hash of the contents is a better ETag than the one derived from the file metadata
+        let mut response = file.use_etag(false).into_response(req);
+        insert_caching_headers(&mut response, &photo.info.file_hash)?;
+        Ok(response)
+    }
+
+    /// Set the ETag and allow clients to cache the response.
+    fn insert_caching_headers(response: &mut HttpResponse, etag: &Sha256Hash) -> Result<(), anyhow::Error> {
+        let headers = response.headers_mut();
+        headers.insert(
+            http::header::ETAG,
+            http::header::HeaderValue::from_str(&format!("\"{}\"", etag))?,
+        );
+        headers.insert(
+            http::header::CACHE_CONTROL,
+            http::header::HeaderValue::from_static("private, max-age=3600"),
+        );
+        Ok(())
+    }
+
+    /// Render a photo in the requested size, e.g. for a slideshow.
+    #[utoipa::path(
+        get,
+        path = "/photos/{id}/render",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+            RenderQuery,
+        ),
+        responses(
+            (status = 200, description = "The resized photo as JPEG or WebP image", content(("image/jpeg"), ("image/webp"))),
+            (status = 304, description = "The cached version is still current"),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Photo not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_render_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<i64>,
+        query: web::Query<RenderQuery>,
+    ) -> impl Responder {
+        error_handler(photo_render_response(&req, &data, PhotoId(*info), &query)).await
+    }
+
+    /// Resized photos are cached on disk, because rendering them on the blocking thread pool is slow.
+    async fn photo_render_response(
+        req: &HttpRequest,
+        data: &WebData,
+        photo_id: PhotoId,
+        query: &RenderQuery,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let options = match query.to_options() {
+            Ok(options) => options,
+            Err(message) => return Ok(bad_request(message)),
+        };
+        let photo = match data.photo_db.read(move |db| db.get_photo(photo_id)).await? {
+            Some(photo) => photo,
+            None => return Ok(photo_not_found()),
+        };
+
+        // The rendered photo only depends on the contents of the original and the options
+        let etag = Sha256Hash::hash_bytes(
+            format!("{}-{}", photo.info.file_hash, options.cache_key()).as_bytes(),
+        );
+        if get_if_none_match_sha256(req).as_ref() == Some(&etag) {
+            return Ok(HttpResponse::NotModified().into());
+        }
+        let file_name = format!("{}.{}", etag, options.format.extension());
+        // The file might have been evicted from the cache in the meantime, then it is rendered again
+        let render_cache = data.render_cache.clone();
+        let cache_name = file_name.clone();
+        let cached_file = web::block(move || {
+            render_cache
+                .get(&cache_name)
+                .and_then(|path| NamedFile::open(path).ok())
+        })
+        .await?;
+        metrics::record_render_cache_lookup(cached_file.is_some());
+        if let Some(file) = cached_file {
+            let mut response = file
+                .use_etag(false)
+                .use_last_modified(false)
+                .disable_content_disposition()
+                .into_response(req);
+            insert_caching_headers(&mut response, &etag)?;
+            return Ok(response);
+        }
+
+        if !data.root_status.is_photo_available(&photo).await? {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .content_type("application/json")
+                .json(ErrorResponse::from("The root of this photo is offline")));
+        }
+        let path = data
+            .library
+            .get_full_path(&photo)
+            .ok_or_else(|| format_err!("Photo {:?} has no root", photo_id))?;
+        let render_cache = data.render_cache.clone();
+        let rendered = web::block(move || -> Result<Vec<u8>, anyhow::Error> {
+            let rendered = formats::render_photo(&path, &options)?;
+            if let Err(err) = render_cache.insert(&file_name, &rendered) {
+                warn!("Failed to cache rendered photo {}: {}", file_name, err);
+            }
+            Ok(rendered)
+        })
+        .await??;
+
+        let mut response = HttpResponse::Ok()
+            .content_type(options.format.content_type())
+            .body(rendered);
+        insert_caching_headers(&mut response, &etag)?;
+        Ok(response)
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/photos/{id}/thumbnail",
+        tag = "photos",
+        params(
+            ("id" = i64, Path, description = "The id of the photo"),
+        ),
+        responses(
+            (status = 200, description = "The thumbnail as JPEG image", content_type = "image/jpeg"),
+            (status = 304, description = "The cached version is still current"),
+            (status = 404, description = "Photo or thumbnail not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn photo_thumbnail_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<i64>,
+    ) -> impl Responder {
+        error_handler(photo_thumbnail_response(&req, &data, PhotoId(*info))).await
+    }
+
+    async fn photo_thumbnail_response(
+        req: &HttpRequest,
+        data: &WebData,
+        photo_id: PhotoId,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let etag_request = get_if_none_match_sha256(req);
+
+        let result = data
+            .photo_db
+            .read(move |db| {
+                let etag_result = db.query_thumbnail_hash(photo_id)?;
+                // early exit if the etag matches
+                if etag_result.is_some() && etag_result == etag_request {
+                    return Ok(None);
+                }
+                // otherwise, get the thumbnail and send it
+                Ok(Some((etag_result, db.query_thumbnail(photo_id)?)))
+            })
+            .await?;
+        let (etag_result, thumbnail_result) = match result {
+            Some(result) => result,
+            None => {
+                metrics::record_thumbnail_lookup(true);
+                return Ok(HttpResponse::NotModified().into());
+            }
+        };
+
+        metrics::record_thumbnail_lookup(thumbnail_result.is_some());
+        let response = if let Some(thumbnail) = thumbnail_result {
+            let etag =
+                etag_result.ok_or(format_err!("Thumbnail {:?} without hash", photo_id))?;
+            HttpResponse::Ok()
+                .content_type("image/jpeg")
+                .append_header(("ETag", format!("\"{}\"", etag)))
+                .append_header(("Cache-Control", "private, max-age=3600"))
+                .body(thumbnail.into_jpg_bytes())
+        } else {
+            HttpResponse::NotFound()
+                .content_type("application/json")
+                .json(ErrorResponse::from("This photo has no thumbnail"))
+        };
+        Ok(response)
+    }
+
+    /// Stream changes of the library as server-sent events. Clients that reconnect with
+    /// `Last-Event-ID` receive the events they missed in the meantime, or a `reset` event
+    /// if these are no longer available.
+    #[utoipa::path(
+        get,
+        path = "/events",
+        tag = "events",
+        params(
+            ("Last-Event-ID" = Option<i64>, Header, description = "The id of the last event the client received, for catching up after reconnecting"),
+        ),
+        responses(
+            (status = 200, description = "Server-sent events named `photo_added`, `photo_updated`, `photo_removed` and `thumbnail_ready` with the data `{\"photo_id\": <id>}`, or `reset` when the client must reload everything", content_type = "text/event-stream"),
+        )
+    )]
+    pub async fn events_get(req: HttpRequest, data: web::Data<WebData>) -> impl Responder {
+        error_handler(async {
+            let last_event_id = req
+                .headers()
+                .get("Last-Event-ID")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .map(PhotoEventId);
+
+            let receiver = data.events.subscribe();
+            let (missed, reset) = match last_event_id {
+                Some(after) => {
+                    let missed = data
+                        .photo_db
+                        .read(move |db| db.query_photo_events(after, events::EVENT_BATCH_SIZE))
+                        .await?;
+                    // There are too many missed events, or some have been removed already
+                    let incomplete = missed.len() == events::EVENT_BATCH_SIZE as usize
+                        || matches!(missed.first(), Some(event) if event.id.0 != after.0 + 1);
+                    if incomplete {
+                        (Vec::new(), true)
+                    } else {
+                        (missed, false)
+                    }
+                }
+                None => (Vec::new(), false),
+            };
+
+            Ok(HttpResponse::Ok()
+                .content_type("text/event-stream")
+                .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+                .streaming(events::event_stream(receiver, missed, reset)))
+        })
+        .await
+    }
+
+    /// Download the originals of the selected photos as ZIP archive, which is created while it is sent.
+    #[utoipa::path(
+        post,
+        path = "/download",
+        tag = "photos",
+        request_body = DownloadSelection,
+        responses(
+            (status = 200, description = "A ZIP archive of the original files", content_type = "application/zip"),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Album not found", body = ErrorResponse),
+            (status = 409, description = "A photo is stored in a root that is offline", body = ErrorResponse),
+        )
+    )]
+    pub async fn download_post(
+        data: web::Data<WebData>,
+        selection: web::Json<DownloadSelection>,
+    ) -> impl Responder {
+        error_handler(async {
+            let photos = match selection.into_inner() {
+                DownloadSelection { photos: Some(photo_ids), album: None, q: None } => {
+                    let result = data
+                        .photo_db
+                        .read(move |db| {
+                            let mut photos = Vec::with_capacity(photo_ids.len());
+                            for photo_id in photo_ids {
+                                match db.get_photo(photo_id)? {
+                                    Some(photo) => photos.push(photo),
+                                    None => return Ok(Err(photo_id)),
+                                }
+                            }
+                            Ok(Ok(photos))
+                        })
+                        .await?;
+                    match result {
+                        Ok(photos) => photos,
+                        Err(photo_id) => {
+                            return Ok(bad_request(format!("Photo {} does not exist", photo_id.0)))
+                        }
+                    }
+                }
+                DownloadSelection { photos: None, album: Some(album_id), q: None } => {
+                    let photos = data
+                        .photo_db
+                        .read(move |db| match db.get_album(album_id)? {
+                            Some(album) => db.query_album_photos(&album).map(Some),
+                            None => Ok(None),
+                        })
+                        .await?;
+                    match photos {
+                        Some(photos) => photos,
+                        None => return Ok(album_not_found()),
+                    }
+                }
+                DownloadSelection { photos: None, album: None, q: Some(q) } => {
+                    let query = match q.parse::<PhotoQuery>() {
+                        Ok(query) => query,
+                        Err(err) => return Ok(bad_request(err.to_string())),
+                    };
+                    let filter = PhotoFilter {
+                        query: Some(query),
+                        ..PhotoFilter::default()
+                    };
+                    data.photo_db.read(move |db| db.query_filtered_photos(&filter)).await?
+                }
+                _ => {
+                    return Ok(bad_request(
+                        "Select the photos either by id, by album or by query".to_owned(),
+                    ))
+                }
+            };
+
+            let available_roots = data.root_status.available_roots().await?;
+            if let Some(photo) = photos.iter().find(|photo| !available_roots.contains(&photo.root_id)) {
+                return Ok(HttpResponse::Conflict()
+                    .content_type("application/json")
+                    .json(ErrorResponse::new(format!(
+                        "Photo {} is stored in a root that is offline",
+                        photo.id.0
+                    ))));
+            }
+            let entries = export::archive_entries(&data.library, &available_roots, photos)?;
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header(http::header::ContentDisposition::attachment("photos.zip"))
+                .streaming(export::zip_stream(entries)))
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/jobs",
+        tag = "jobs",
+        responses(
+            (status = 200, description = "The running job and the recently finished ones", body = [JobObject]),
+        )
+    )]
+    pub async fn jobs_get(data: web::Data<WebData>) -> impl Responder {
+        let jobs = data.jobs.jobs();
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .json(jobs.iter().map(|job| JobObject::from(&**job)).collect::<Vec<_>>())
+    }
+
+    /// Start scanning the library for new and changed photos in the background.
+    #[utoipa::path(
+        post,
+        path = "/jobs/scan",
+        tag = "jobs",
+        params(
+            ScanJobQuery,
+        ),
+        responses(
+            (status = 202, description = "The started job", body = JobObject),
+            (status = 409, description = "Another job is still running", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn scan_job_post(data: web::Data<WebData>, query: web::Query<ScanJobQuery>) -> impl Responder {
+        start_job(&data, JobTask::Scan { rescan: query.rescan })
+    }
+
+    /// Start generating thumbnails in the background.
+    #[utoipa::path(
+        post,
+        path = "/jobs/thumbnails",
+        tag = "jobs",
+        params(
+            ThumbnailsJobQuery,
+        ),
+        responses(
+            (status = 202, description = "The started job", body = JobObject),
+            (status = 409, description = "Another job is still running", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+        )
+    )]
+    pub async fn thumbnails_job_post(
+        data: web::Data<WebData>,
+        query: web::Query<ThumbnailsJobQuery>,
+    ) -> impl Responder {
+        start_job(
+            &data,
+            JobTask::Thumbnails {
+                regenerate: query.regenerate,
+                retry_failed: query.retry_failed,
+            },
+        )
+    }
+
+    fn start_job(data: &WebData, task: JobTask) -> HttpResponse {
+        // Jobs change the library through their own connections
+        if data.photo_db.is_read_only() {
+            return read_only();
+        }
+        match data.jobs.start(task) {
+            Ok(job) => HttpResponse::Accepted()
+                .content_type("application/json")
+                .insert_header((http::header::LOCATION, format!("/api/v1/jobs/{}", job.id().0)))
+                .json(JobObject::from(&*job)),
+            Err(running) => HttpResponse::Conflict()
+                .content_type("application/json")
+                .json(ErrorResponse::new(format!("Job {} is still running", running.0))),
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/jobs/{id}",
+        tag = "jobs",
+        params(
+            ("id" = u64, Path, description = "The id of the job"),
+        ),
+        responses(
+            (status = 200, body = JobObject),
+            (status = 404, description = "Job not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn job_get(data: web::Data<WebData>, info: web::Path<u64>) -> impl Responder {
+        match data.jobs.get(JobId(*info)) {
+            Some(job) => HttpResponse::Ok()
+                .content_type("application/json")
+                .json(JobObject::from(&*job)),
+            None => job_not_found(),
+        }
+    }
+
+    /// Ask a running job to stop. It keeps running until it notices, so it's returned as accepted.
+    #[utoipa::path(
+        post,
+        path = "/jobs/{id}/cancel",
+        tag = "jobs",
+        params(
+            ("id" = u64, Path, description = "The id of the job"),
+        ),
+        responses(
+            (status = 200, description = "The job had already finished", body = JobObject),
+            (status = 202, description = "The job is stopping", body = JobObject),
+            (status = 404, description = "Job not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn job_cancel_post(data: web::Data<WebData>, info: web::Path<u64>) -> impl Responder {
+        match data.jobs.cancel(JobId(*info)) {
+            Some(job) if job.state() == JobState::Running => HttpResponse::Accepted()
+                .content_type("application/json")
+                .json(JobObject::from(&*job)),
+            Some(job) => HttpResponse::Ok()
+                .content_type("application/json")
+                .json(JobObject::from(&*job)),
+            None => job_not_found(),
+        }
+    }
+
+    fn job_not_found() -> HttpResponse {
+        HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(ErrorResponse::from("Job not found"))
+    }
+
+    #[derive(Serialize)]
+    struct HealthObject {
+        /// Whether the photo database can be queried.
+        database: bool,
+        roots: Vec<RootHealthObject>,
+    }
+
+    #[derive(Serialize)]
+    struct RootHealthObject {
+        name: String,
+        online: bool,
+    }
+
+    /// Check whether the server can do its job, i.e. the photo database is reachable and all roots
+    /// of the library are mounted. Responds with 503 otherwise, along with what is missing.
+    pub async fn healthz_get(data: web::Data<WebData>) -> impl Responder {
+        let database = match data.photo_db.read(|db| db.query_photo_count()).await {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("Health check failed to query the photo database: {:#}", err);
+                false
+            }
+        };
+        let available_roots = match data.root_status.available_roots().await {
+            Ok(available_roots) => available_roots,
+            Err(err) => {
+                warn!("Health check failed to check the roots: {:#}", err);
+                Default::default()
+            }
+        };
+        let roots = data
+            .library
+            .roots
+            .iter()
+            .map(|root| RootHealthObject {
+                name: root.name.clone(),
+                online: available_roots.contains(&root.id),
+            })
+            .collect::<Vec<_>>();
+        let healthy = database && roots.iter().all(|root| root.online);
+        let status = if healthy {
+            http::StatusCode::OK
+        } else {
+            http::StatusCode::SERVICE_UNAVAILABLE
+        };
+        HttpResponse::build(status)
+            .content_type("application/json")
+            .json(HealthObject { database, roots })
+    }
+
+    /// Report metrics of the server and the library in the text format of Prometheus.
+    pub async fn metrics_get(data: web::Data<WebData>) -> impl Responder {
+        error_handler(async {
+            let (photos, thumbnails, failed_thumbnails) = data
+                .photo_db
+                .read(|db| {
+                    Ok((
+                        db.query_photo_count()?,
+                        db.query_thumbnail_row_count()?,
+                        db.query_thumbnail_failed_count()?,
+                    ))
+                })
+                .await?;
+            let available_roots = data.root_status.available_roots().await?;
+            let totals = metrics::LibraryTotals {
+                photos,
+                thumbnails,
+                failed_thumbnails,
+                roots: data
+                    .library
+                    .roots
+                    .iter()
+                    .map(|root| (root.name.as_str(), available_roots.contains(&root.id)))
+                    .collect(),
+            };
+            Ok(HttpResponse::Ok()
+                .content_type(metrics::CONTENT_TYPE)
+                .body(metrics::encode(&totals)?))
+        })
+        .await
+    }
+
+    /// Middleware recording the number and duration of requests for the metrics.
+    pub async fn record_metrics(
+        req: ServiceRequest,
+        next: Next<BoxBody>,
+    ) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+        // Requests that match no route are served by the static file handler
+        let route = req.match_pattern().unwrap_or_else(|| "static".to_owned());
+        let method = req.method().clone();
+        let start = Instant::now();
+        let result = next.call(req).await;
+        let status = match &result {
+            Ok(response) => response.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+        metrics::record_request(&route, method.as_str(), status, start.elapsed());
+        result
+    }
+
+    /// Look up the share link with the token from the path, unless it doesn't exist or has expired.
+    async fn find_share(data: &WebData, token: &str) -> Result<Option<Share>, anyhow::Error> {
+        let token = AccessToken::new(token.to_owned());
+        data.photo_db.read(move |db| db.query_share(&token)).await
+    }
+
+    /// Check whether a photo can be viewed through the share link with the given token.
+    async fn is_shared_photo(data: &WebData, token: &str, photo_id: PhotoId) -> Result<bool, anyhow::Error> {
+        let token = AccessToken::new(token.to_owned());
+        data.photo_db
+            .read(move |db| match db.query_share(&token)? {
+                Some(share) => db.is_photo_shared(&share, photo_id),
+                None => Ok(false),
+            })
+            .await
+    }
+
+    fn share_not_found() -> HttpResponse {
+        HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(ErrorResponse::from("Share link not found or expired"))
+    }
+
+    /// Serve the page for viewing the photos of a share link.
+    pub async fn share_page_get(
+        data: web::Data<WebData>,
+        info: web::Path<String>,
+        request: HttpRequest,
+    ) -> impl Responder {
+        error_handler(async {
+            if find_share(&data, &info).await?.is_none() {
+                return Ok(share_not_found());
+            }
+            static_file_response(&data, &request, "/web/share.html").await
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/s/{token}/photos",
+        tag = "shares",
+        params(
+            ("token" = String, Path, description = "The token of the share link"),
+        ),
+        responses(
+            (status = 200, body = [SharedPhotoObject]),
+            (status = 404, description = "Share link not found", body = ErrorResponse),
+        )
+    )]
+    pub async fn share_photos_get(data: web::Data<WebData>, info: web::Path<String>) -> impl Responder {
+        error_handler(async {
+            let share = match find_share(&data, &info).await? {
+                Some(share) => share,
+                None => return Ok(share_not_found()),
+            };
+            let photos = data.photo_db.read(move |db| db.query_share_photos(&share)).await?;
+            let photos = photo_objects(&data, photos)
+                .await?
+                .into_iter()
+                .map(SharedPhotoObject::from)
+                .collect::<Vec<_>>();
+
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(photos))
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/s/{token}/photos/{id}/thumbnail",
+        tag = "shares",
+        params(
+            ("token" = String, Path, description = "The token of the share link"),
+            ("id" = i64, Path, description = "The id of a shared photo"),
+        ),
+        responses(
+            (status = 200, description = "The thumbnail as JPEG image", content_type = "image/jpeg"),
+            (status = 304, description = "The cached version is still current"),
+            (status = 404, description = "Share link not found or expired, or the photo is not shared", body = ErrorResponse),
+        )
+    )]
+    pub async fn share_photo_thumbnail_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<(String, i64)>,
+    ) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(info.1);
+            if !is_shared_photo(&data, &info.0, photo_id).await? {
+                return Ok(photo_not_found());
+            }
+            photo_thumbnail_response(&req, &data, photo_id).await
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/s/{token}/photos/{id}/render",
+        tag = "shares",
+        params(
+            ("token" = String, Path, description = "The token of the share link"),
+            ("id" = i64, Path, description = "The id of a shared photo"),
+            RenderQuery,
+        ),
+        responses(
+            (status = 200, description = "The resized photo as JPEG or WebP image", content(("image/jpeg"), ("image/webp"))),
+            (status = 304, description = "The cached version is still current"),
+            (status = 400, description = "Invalid request", body = ErrorResponse),
+            (status = 404, description = "Share link not found or expired, or the photo is not shared", body = ErrorResponse),
+        )
+    )]
+    pub async fn share_photo_render_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<(String, i64)>,
+        query: web::Query<RenderQuery>,
+    ) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(info.1);
+            if !is_shared_photo(&data, &info.0, photo_id).await? {
+                return Ok(photo_not_found());
+            }
+            photo_render_response(&req, &data, photo_id, &query).await
+        })
+        .await
+    }
+
+    #[utoipa::path(
+        method(get, head),
+        path = "/s/{token}/photos/{id}/original",
+        tag = "shares",
+        params(
+            ("token" = String, Path, description = "The token of the share link"),
+            ("id" = i64, Path, description = "The id of a shared photo"),
+        ),
+        responses(
+            (status = 200, description = "The original file of the photo", content_type = "image/jpeg"),
+            (status = 206, description = "The requested range of the original file", content_type = "image/jpeg"),
+            (status = 304, description = "The cached version is still current"),
+            (status = 404, description = "Share link not found or expired, or the photo is not shared", body = ErrorResponse),
+        )
+    )]
+    pub async fn share_photo_original_get(
+        req: HttpRequest,
+        data: web::Data<WebData>,
+        info: web::Path<(String, i64)>,
+    ) -> impl Responder {
+        error_handler(async {
+            let photo_id = PhotoId(info.1);
+            if !is_shared_photo(&data, &info.0, photo_id).await? {
+                return Ok(photo_not_found());
+            }
+            photo_original_response(&req, &data, photo_id).await
+        })
+        .await
+    }
+
+    async fn error_handler<F: Future<Output = Result<HttpResponse, anyhow::Error>>>(
+        response: F,
+    ) -> HttpResponse {
+        match response.await {
+            Ok(response) => response,
+            Err(err) if err.is::<ReadOnlyError>() => read_only(),
+            Err(err) => {
+                error!("Error while handling request: {}", err);
                 HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
                     .content_type("application/json")
                     .json(ErrorResponse::from("internal server error"))
@@ -324,10 +2362,653 @@ mod handlers {
         }
     }
 
+    /// The response to requests that would change the library while the server is read-only.
+    fn read_only() -> HttpResponse {
+        HttpResponse::Forbidden()
+            .content_type("application/json")
+            .json(ErrorResponse::from("The server is read-only"))
+    }
+
+    fn photo_not_found() -> HttpResponse {
+        HttpResponse::NotFound()
+            .content_type("application/json")
+            .json(ErrorResponse::from("Photo not found"))
+    }
+
+    /// Name of the cookie containing the session token.
+    const SESSION_COOKIE: &str = "session";
+
+    /// How long a login session is valid.
+    const SESSION_DAYS: i64 = 30;
+
+    /// Whether a path can be accessed without logging in. This is the case for the login page,
+    /// the static files of the frontend, the API description, the health check and share links,
+    /// which check their token themselves.
+    fn is_public_path(path: &str) -> bool {
+        matches!(
+            path,
+            "/" | "/favicon.ico"
+                | "/login"
+                | "/healthz"
+                | "/api/openapi.json"
+                | "/api/v1/login"
+                | "/api/v1/logout"
+        ) || path.starts_with("/web/")
+            || path.starts_with("/s/")
+            || path.starts_with("/api/v1/s/")
+    }
+
+    /// Middleware rejecting requests that neither belong to a login session nor carry the API token
+    /// of the server. The user of the session is stored in the request extensions.
+    pub async fn authenticate(
+        req: ServiceRequest,
+        next: Next<BoxBody>,
+    ) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+        match check_authentication(&req).await {
+            None => next.call(req).await,
+            Some(response) => Ok(req.into_response(response)),
+        }
+    }
+
+    /// Check the credentials of a request, returning the error response if they are missing or invalid.
+    async fn check_authentication(req: &ServiceRequest) -> Option<HttpResponse> {
+        let data = match req.app_data::<web::Data<WebData>>() {
+            Some(data) => data,
+            None => {
+                error!("Application data is missing, cannot check authentication");
+                return Some(
+                    HttpResponse::InternalServerError()
+                        .content_type("application/json")
+                        .json(ErrorResponse::from("internal server error")),
+                );
+            }
+        };
+        if !data.auth_required || is_public_path(req.path()) {
+            return None;
+        }
+
+        let bearer_token = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if let (Some(token), Some(api_token)) = (bearer_token, data.api_token.as_ref()) {
+            if constant_time_eq(token.as_bytes(), api_token.as_bytes()) {
+                return None;
+            }
+        }
+
+        if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+            let token = AccessToken::new(cookie.value().to_owned());
+            match data.photo_db.read(move |db| db.query_session_user(&token)).await {
+                Ok(Some(user)) => {
+                    req.extensions_mut().insert(user);
+                    return None;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Error while checking session: {}", err);
+                    return Some(
+                        HttpResponse::InternalServerError()
+                            .content_type("application/json")
+                            .json(ErrorResponse::from("internal server error")),
+                    );
+                }
+            }
+        }
+
+        Some(
+            HttpResponse::Unauthorized()
+                .content_type("application/json")
+                .json(ErrorResponse::from("Not logged in")),
+        )
+    }
+
+    #[derive(Deserialize, ToSchema)]
+    pub struct Credentials {
+        name: String,
+        password: String,
+    }
+
+    #[derive(Serialize, ToSchema)]
+    struct UserObject {
+        name: String,
+    }
+
+    /// Log in with user name and password, which starts a session identified by a cookie.
+    #[utoipa::path(
+        post,
+        path = "/login",
+        tag = "session",
+        request_body = Credentials,
+        responses(
+            (status = 200, description = "The session cookie is set", body = UserObject),
+            (status = 401, description = "Invalid user name or password", body = ErrorResponse),
+            (status = 403, description = "The server is read-only", body = ErrorResponse),
+            (status = 429, description = "Too many failed attempts, the client must wait for the time in the `Retry-After` header", body = ErrorResponse),
+        )
+    )]
+    pub async fn login_post(
+        data: web::Data<WebData>,
+        credentials: web::Json<Credentials>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        error_handler(async {
+            let client = req.peer_addr().map(|address| address.ip());
+            if let Some(client) = client {
+                if let Err(delay) = data.login_throttle.begin_attempt(client) {
+                    let seconds = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+                    return Ok(HttpResponse::TooManyRequests()
+                        .insert_header((http::header::RETRY_AFTER, seconds))
+                        .content_type("application/json")
+                        .json(ErrorResponse::from(
+                            "Too many failed login attempts, try again later",
+                        )));
+                }
+            }
+            let response = match start_session(&data, credentials.into_inner()).await? {
+                Some((user, token)) => {
+                    if let Some(client) = client {
+                        data.login_throttle.succeeded(client);
+                    }
+                    HttpResponse::Ok()
+                        .cookie(session_cookie(&req, token.as_str().to_owned()))
+                        .content_type("application/json")
+                        .json(UserObject { name: user.name })
+                }
+                None => HttpResponse::Unauthorized()
+                    .content_type("application/json")
+                    .json(ErrorResponse::from("Invalid user name or password")),
+            };
+            Ok(response)
+        })
+        .await
+    }
+
+    /// Check the credentials and start a session if they are valid.
+    async fn start_session(
+        data: &WebData,
+        credentials: Credentials,
+    ) -> Result<Option<(User, AccessToken)>, anyhow::Error> {
+        let name = credentials.name.clone();
+        let user = data
+            .photo_db
+            .read(move |db| db.query_user_credentials(&name))
+            .await?;
+        // Verifying passwords is deliberately slow, so it must not block the worker threads
+        let user = web::block(move || match user {
+            Some((user, password_hash))
+                if auth::verify_password(&password_hash, &credentials.password) =>
+            {
+                Some(user)
+            }
+            _ => None,
+        })
+        .await?;
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let token = AccessToken::generate();
+        let expires = chrono::Utc::now() + chrono::Duration::days(SESSION_DAYS);
+        let session_token = token.clone();
+        let user_id = user.id;
+        data.photo_db
+            .write(move |db| db.insert_session(&session_token, user_id, expires))
+            .await?;
+        Ok(Some((user, token)))
+    }
+
+    /// End the current session, if any.
+    #[utoipa::path(
+        post,
+        path = "/logout",
+        tag = "session",
+        responses(
+            (status = 204, description = "The session cookie is removed"),
+        )
+    )]
+    pub async fn logout_post(data: web::Data<WebData>, req: HttpRequest) -> impl Responder {
+        error_handler(async {
+            if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+                let token = AccessToken::new(cookie.value().to_owned());
+                data.photo_db.write(move |db| db.delete_session(&token)).await?;
+            }
+            let mut cookie = session_cookie(&req, String::new());
+            cookie.make_removal();
+            Ok(HttpResponse::NoContent().cookie(cookie).finish())
+        })
+        .await
+    }
+
+    fn session_cookie(req: &HttpRequest, token: String) -> Cookie<'static> {
+        Cookie::build(SESSION_COOKIE, token)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .secure(req.connection_info().scheme() == "https")
+            .max_age(actix_web::cookie::time::Duration::days(SESSION_DAYS))
+            .finish()
+    }
+
+    /// Compare secrets in a way that doesn't reveal the length of the common prefix.
+    fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+
+    /// Report invalid query parameters as JSON formatted error.
+    pub fn query_error_handler(
+        err: actix_web::error::QueryPayloadError,
+        _req: &HttpRequest,
+    ) -> actix_web::Error {
+        let response = bad_request(err.to_string());
+        actix_web::error::InternalError::from_response(err, response).into()
+    }
+
+    /// Report malformed JSON request bodies as JSON formatted error.
+    pub fn json_error_handler(
+        err: actix_web::error::JsonPayloadError,
+        _req: &HttpRequest,
+    ) -> actix_web::Error {
+        let response = bad_request(err.to_string());
+        actix_web::error::InternalError::from_response(err, response).into()
+    }
+
+    fn bad_request(message: String) -> HttpResponse {
+        HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(ErrorResponse::new(message))
+    }
+
     fn get_if_none_match_sha256(req: &HttpRequest) -> Option<Sha256Hash> {
         req.headers()
             .get("If-None-Match")
             .and_then(|value| value.as_bytes().get(1..65))
             .and_then(Sha256Hash::from_hex)
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde::de::DeserializeOwned;
+        use serde_json::{json, Value};
+
+        /// The TypeScript types of the API used by the frontend, which are generated by `api_types`.
+        const API_TYPES_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/websrc/api.ts");
+
+        fn api_doc() -> Value {
+            serde_json::to_value(ApiDoc::openapi()).unwrap()
+        }
+
+        fn component<'a>(doc: &'a Value, name: &str) -> &'a Value {
+            doc.pointer(&format!("/components/schemas/{}", name))
+                .unwrap_or_else(|| panic!("Schema {} is missing from the API description", name))
+        }
+
+        fn resolve<'a>(doc: &'a Value, schema: &'a Value) -> &'a Value {
+            match schema["$ref"].as_str() {
+                Some(reference) => {
+                    resolve(doc, component(doc, reference.trim_start_matches("#/components/schemas/")))
+                }
+                None => schema,
+            }
+        }
+
+        /// Check a JSON value against a schema, returning where and why it doesn't match.
+        /// This covers the subset of JSON schema that utoipa generates for our types.
+        fn validate(doc: &Value, schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+            let schema = resolve(doc, schema);
+            if let Some(variants) = schema["oneOf"].as_array() {
+                let matching = variants
+                    .iter()
+                    .filter(|variant| validate(doc, variant, value, at).is_ok())
+                    .count();
+                if matching != 1 {
+                    return Err(format!("{}: {} matches {} variants of oneOf", at, value, matching));
+                }
+            }
+            if let Some(variants) = schema["anyOf"].as_array() {
+                if !variants.iter().any(|variant| validate(doc, variant, value, at).is_ok()) {
+                    return Err(format!("{}: {} matches no variant of anyOf", at, value));
+                }
+            }
+            if let Some(parts) = schema["allOf"].as_array() {
+                for part in parts {
+                    validate(doc, part, value, at)?;
+                }
+            }
+            if let Some(variants) = schema["enum"].as_array() {
+                if !variants.contains(value) {
+                    return Err(format!("{}: {} is not one of {:?}", at, value, variants));
+                }
+            }
+
+            let types = match &schema["type"] {
+                Value::String(ty) => vec![ty.as_str()],
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                _ => return Ok(()),
+            };
+            let ty = match value {
+                Value::Null => "null",
+                Value::Bool(_) => "boolean",
+                Value::Number(number) if number.is_f64() => "number",
+                Value::Number(_) => "integer",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            };
+            if !(types.contains(&ty) || ty == "integer" && types.contains(&"number")) {
+                return Err(format!("{}: {} is not of type {:?}", at, value, types));
+            }
+
+            match value {
+                Value::String(string) => {
+                    let valid = match schema["format"].as_str() {
+                        Some("date-time") => chrono::DateTime::parse_from_rfc3339(string).is_ok(),
+                        Some("date") => string.parse::<chrono::NaiveDate>().is_ok(),
+                        _ => true,
+                    };
+                    if !valid {
+                        return Err(format!("{}: {} has not the format {}", at, value, schema["format"]));
+                    }
+                }
+                Value::Number(number) => {
+                    if let (Some(minimum), Some(number)) = (schema["minimum"].as_f64(), number.as_f64()) {
+                        if number < minimum {
+                            return Err(format!("{}: {} is less than {}", at, number, minimum));
+                        }
+                    }
+                }
+                Value::Array(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        validate(doc, &schema["items"], item, &format!("{}[{}]", at, index))?;
+                    }
+                }
+                Value::Object(fields) => {
+                    let properties = schema["properties"].as_object().cloned().unwrap_or_default();
+                    for (name, field) in fields {
+                        let property = properties
+                            .get(name)
+                            .ok_or_else(|| format!("{}: property {} is not in the schema", at, name))?;
+                        validate(doc, property, field, &format!("{}.{}", at, name))?;
+                    }
+                    for required in schema["required"].as_array().into_iter().flatten() {
+                        let required = required.as_str().unwrap();
+                        if !fields.contains_key(required) {
+                            return Err(format!("{}: required property {} is missing", at, required));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        /// Check that a DTO serializes to JSON that matches its schema. Since responses always
+        /// contain all fields, the schema must require them, even if they can be null.
+        fn assert_serializes_to_schema<T: Serialize + ToSchema>(value: &T) {
+            let doc = api_doc();
+            let name = T::name();
+            let schema = component(&doc, &name);
+            let json = serde_json::to_value(value).unwrap();
+            if let Err(err) = validate(&doc, schema, &json, &name) {
+                panic!("Serialized {} does not match its schema: {}", name, err);
+            }
+            for field in json.as_object().into_iter().flat_map(|fields| fields.keys()) {
+                let required = schema["required"].as_array().cloned().unwrap_or_default();
+                assert!(
+                    required.contains(&json!(field)),
+                    "{}.{} is always serialized, but not required by the schema",
+                    name,
+                    field
+                );
+            }
+        }
+
+        /// Create a value matching the schema with all optional properties present.
+        fn example(doc: &Value, schema: &Value) -> Value {
+            let schema = resolve(doc, schema);
+            if let Some(variant) = schema["oneOf"].as_array().or_else(|| schema["anyOf"].as_array()) {
+                let variant = variant.iter().find(|variant| variant["type"] != "null");
+                return example(doc, variant.unwrap());
+            }
+            if let Some(variant) = schema["enum"].as_array().and_then(|variants| variants.first()) {
+                return variant.clone();
+            }
+            let ty = match &schema["type"] {
+                Value::Array(types) => types.iter().find(|ty| *ty != "null").unwrap(),
+                ty => ty,
+            };
+            match (ty.as_str().unwrap(), schema["format"].as_str()) {
+                ("string", Some("date-time")) => json!("2021-06-01T12:00:00Z"),
+                ("string", Some("date")) => json!("2021-06-01"),
+                ("string", _) => json!("people=Anna"),
+                ("integer", _) | ("number", _) => json!(1),
+                ("boolean", _) => json!(true),
+                ("array", _) => json!([example(doc, &schema["items"])]),
+                ("object", _) => Value::Object(
+                    schema["properties"]
+                        .as_object()
+                        .into_iter()
+                        .flatten()
+                        .map(|(name, property)| (name.clone(), example(doc, property)))
+                        .collect(),
+                ),
+                (ty, _) => panic!("Unsupported type {}", ty),
+            }
+        }
+
+        /// Check that every property of the schema of a request body is understood by the DTO,
+        /// and that the DTO doesn't need more than the required properties.
+        fn assert_deserializes_from_schema<T: DeserializeOwned + ToSchema>() {
+            let doc = api_doc();
+            let name = T::name();
+            let schema = component(&doc, &name);
+            let full = example(&doc, schema);
+            if let Err(err) = serde_json::from_value::<T>(full.clone()) {
+                panic!("{} cannot be deserialized from {}: {}", name, full, err);
+            }
+            let mut minimal = full.as_object().unwrap().clone();
+            let required = schema["required"].as_array().cloned().unwrap_or_default();
+            minimal.retain(|name, _| required.contains(&json!(name)));
+            let minimal = Value::Object(minimal);
+            if let Err(err) = serde_json::from_value::<T>(minimal.clone()) {
+                panic!("{} cannot be deserialized from {}: {}", name, minimal, err);
+            }
+        }
+
+        fn photo_object(complete: bool) -> PhotoObject {
+            PhotoObject {
+                id: PhotoId(1),
+                root: RootId(2),
+                relative_path: "2021/IMG_0001.jpg".to_owned(),
+                created: Some(chrono::Utc::now()).filter(|_| complete),
+                created_overridden: complete,
+                title: Some("Title".to_owned()).filter(|_| complete),
+                comment: Some("Comment".to_owned()).filter(|_| complete),
+                rating: 3,
+                flag: Some(Flag::Pick).filter(|_| complete),
+                color_label: Some(ColorLabel::Purple).filter(|_| complete),
+                available: true,
+            }
+        }
+
+        fn job_object(complete: bool) -> JobObject {
+            JobObject {
+                id: JobId(1),
+                kind: JobKind::Thumbnails,
+                state: if complete { JobState::Failed } else { JobState::Running },
+                error: Some("No space left on device".to_owned()).filter(|_| complete),
+                started: chrono::Utc::now(),
+                finished: Some(chrono::Utc::now()).filter(|_| complete),
+                progress: JobProgressObject {
+                    total: 10,
+                    skipped: 1,
+                    added: 8,
+                    failed: 1,
+                },
+            }
+        }
+
+        fn album_object(filter: Option<PhotoFilter>) -> AlbumObject {
+            AlbumObject {
+                id: AlbumId(1),
+                name: "Holidays".to_owned(),
+                description: String::new(),
+                photo_count: 12,
+                filter,
+            }
+        }
+
+        #[test]
+        fn responses_match_schema() {
+            assert_serializes_to_schema(&ErrorResponse::from("Photo not found"));
+            assert_serializes_to_schema(&photo_object(true));
+            assert_serializes_to_schema(&photo_object(false));
+            assert_serializes_to_schema(&CountObject { count: 3 });
+            assert_serializes_to_schema(&TimelineBucketObject {
+                period: Some("2021-06".to_owned()),
+                photo_count: 5,
+                offset: 0,
+            });
+            assert_serializes_to_schema(&TimelineBucketObject {
+                period: None,
+                photo_count: 5,
+                offset: 5,
+            });
+            assert_serializes_to_schema(&SuggestionObject {
+                term: "anna".to_owned(),
+                photo_count: 2,
+            });
+            assert_serializes_to_schema(&album_object(None));
+            assert_serializes_to_schema(&album_object(Some(PhotoFilter::default())));
+            assert_serializes_to_schema(&album_object(Some(PhotoFilter {
+                tags: vec!["people=Anna".parse().unwrap()],
+                from: Some(chrono::NaiveDate::from_ymd_opt(2021, 1, 1).unwrap()),
+                to: Some(chrono::NaiveDate::from_ymd_opt(2021, 12, 31).unwrap()),
+                camera: Some("X100V".to_owned()),
+                min_rating: Some(4),
+                query: Some("taken:2021".parse().unwrap()),
+            })));
+            assert_serializes_to_schema(&job_object(true));
+            assert_serializes_to_schema(&job_object(false));
+            assert_serializes_to_schema(&UserObject {
+                name: "anna".to_owned(),
+            });
+            assert_serializes_to_schema::<TagName>(&"people=Anna".parse().unwrap());
+        }
+
+        #[test]
+        fn requests_match_schema() {
+            assert_deserializes_from_schema::<PhotoPatch>();
+            assert_deserializes_from_schema::<TagAssignment>();
+            assert_deserializes_from_schema::<AlbumPhotosAddition>();
+            assert_deserializes_from_schema::<AlbumPhotoPatch>();
+            assert_deserializes_from_schema::<DownloadSelection>();
+            assert_deserializes_from_schema::<Credentials>();
+        }
+
+        /// Write the schema as TypeScript type expression.
+        fn typescript_type(schema: &Value, indent: &str) -> String {
+            if let Some(reference) = schema["$ref"].as_str() {
+                return reference.trim_start_matches("#/components/schemas/").to_owned();
+            }
+            if let Some(variants) = schema["oneOf"].as_array().or_else(|| schema["anyOf"].as_array()) {
+                let variants = variants.iter().map(|variant| typescript_type(variant, indent));
+                return variants.collect::<Vec<_>>().join(" | ");
+            }
+            if let Some(variants) = schema["enum"].as_array() {
+                return variants.iter().map(Value::to_string).collect::<Vec<_>>().join(" | ");
+            }
+            let types = match &schema["type"] {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                ty => vec![ty.as_str().unwrap_or("object")],
+            };
+            let types = types.into_iter().map(|ty| match ty {
+                "integer" | "number" => "number".to_owned(),
+                "array" => match typescript_type(&schema["items"], indent) {
+                    item if item.contains(' ') && !item.ends_with('}') => format!("({})[]", item),
+                    item => format!("{}[]", item),
+                },
+                "object" => typescript_object(schema, indent),
+                ty => ty.to_owned(),
+            });
+            types.collect::<Vec<_>>().join(" | ")
+        }
+
+        fn typescript_object(schema: &Value, indent: &str) -> String {
+            let properties = match schema["properties"].as_object() {
+                Some(properties) => properties,
+                None => return "Record<string, unknown>".to_owned(),
+            };
+            let required = schema["required"].as_array().cloned().unwrap_or_default();
+            let inner = format!("{}    ", indent);
+            let mut object = "{\n".to_owned();
+            for (name, property) in properties {
+                object += &typescript_doc(property, &inner);
+                let optional = if required.contains(&json!(name)) { "" } else { "?" };
+                let ty = typescript_type(property, &inner);
+                object += &format!("{}{}{}: {},\n", inner, name, optional, ty);
+            }
+            object + indent + "}"
+        }
+
+        fn typescript_doc(schema: &Value, indent: &str) -> String {
+            schema["description"]
+                .as_str()
+                .into_iter()
+                .flat_map(str::lines)
+                .map(|line| format!("{}/// {}\n", indent, line))
+                .collect()
+        }
+
+        /// Generate the TypeScript types for all schemas of the API.
+        fn typescript_api_types(doc: &Value) -> String {
+            let mut types = "// Types of the REST API, generated from its OpenAPI description.\n\
+                // Don't edit this file, but run `UPDATE_API_TYPES=1 cargo test api_types` after changing the API.\n\
+                \n\
+                /// The base URL of all endpoints.\n\
+                export const API_BASE = '/api/v1';\n"
+                .to_owned();
+            for (name, schema) in doc["components"]["schemas"].as_object().unwrap() {
+                types += "\n";
+                types += &typescript_doc(schema, "");
+                types += &format!("export type {} = {};\n", name, typescript_type(schema, ""));
+            }
+            types
+        }
+
+        /// The frontend relies on the TypeScript types being in sync with the API.
+        #[test]
+        fn api_types_are_up_to_date() {
+            let generated = typescript_api_types(&api_doc());
+            if std::env::var_os("UPDATE_API_TYPES").is_some() {
+                std::fs::write(API_TYPES_FILE, generated).unwrap();
+                return;
+            }
+            let current = std::fs::read_to_string(API_TYPES_FILE).unwrap_or_default();
+            assert!(
+                current == generated,
+                "{} is outdated, run `UPDATE_API_TYPES=1 cargo test api_types` to update it",
+                API_TYPES_FILE
+            );
+        }
+
+        #[test]
+        fn static_etags_match_every_encoding() {
+            let hash = Sha256Hash::hash_bytes(b"contents");
+            let matches = |if_none_match: &str| {
+                let request = actix_web::test::TestRequest::default()
+                    .insert_header((http::header::IF_NONE_MATCH, if_none_match))
+                    .to_http_request();
+                static_etag_matches(&request, &hash)
+            };
+            assert!(matches(&format!("\"{}\"", hash)));
+            assert!(matches(&format!("\"{}-br\"", hash)));
+            assert!(matches(&format!("\"other\", W/\"{}-gzip\"", hash)));
+            assert!(matches("*"));
+            assert!(!matches(&format!("\"{}-deflate\"", hash)));
+            assert!(!matches("\"other\""));
+        }
+    }
 }
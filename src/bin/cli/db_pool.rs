@@ -0,0 +1,106 @@
+//! Access to the photo database from the async handlers of the web server.
+
+use crate::cli::metrics;
+use actix_web::web;
+use photo_archive::library::PhotoDatabase;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use thiserror::Error;
+
+/// The maximum number of read-only connections that are kept open while they are not in use.
+const MAX_IDLE_READERS: usize = 16;
+
+/// Runs database queries on the blocking thread pool, so that they don't stall the workers
+/// of the web server.
+///
+/// Queries use a pool of read-only connections and run concurrently, because the database
+/// is switched to write-ahead logging. Changes are made through a single connection, since
+/// SQLite only allows one writer at a time anyway.
+#[derive(Clone)]
+pub struct PhotoDbPool {
+    path: PathBuf,
+    /// The connection for making changes, unless the pool is read-only.
+    writer: Option<Arc<Mutex<PhotoDatabase>>>,
+    readers: Arc<Mutex<Vec<PhotoDatabase>>>,
+}
+
+/// Returned when trying to change a database that was opened read-only.
+#[derive(Debug, Error)]
+#[error("The photo database is opened read-only")]
+pub struct ReadOnlyError;
+
+impl PhotoDbPool {
+    /// Open the database, which must have been created already, and migrate it if necessary.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PhotoDbPool, anyhow::Error> {
+        let writer = PhotoDatabase::open_existing(&path)?;
+        writer.enable_write_ahead_log()?;
+        Ok(PhotoDbPool {
+            path: path.as_ref().to_owned(),
+            writer: Some(Arc::new(Mutex::new(writer))),
+            readers: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// Open the database without ever changing it, so its schema must be up to date already.
+    /// Writing fails with `ReadOnlyError`.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<PhotoDbPool, anyhow::Error> {
+        let reader = PhotoDatabase::open_read_only(&path)?;
+        Ok(PhotoDbPool {
+            path: path.as_ref().to_owned(),
+            writer: None,
+            readers: Arc::new(Mutex::new(vec![reader])),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.writer.is_none()
+    }
+
+    /// Run queries that don't change the database.
+    pub async fn read<T, F>(&self, query: F) -> Result<T, anyhow::Error>
+    where
+        F: FnOnce(&PhotoDatabase) -> Result<T, anyhow::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        web::block(move || {
+            let idle = lock(&pool.readers).pop();
+            let db = match idle {
+                Some(db) => db,
+                None => PhotoDatabase::open_read_only(&pool.path)?,
+            };
+            let result = query(&db);
+            let mut readers = lock(&pool.readers);
+            if readers.len() < MAX_IDLE_READERS {
+                readers.push(db);
+            }
+            result
+        })
+        .await?
+    }
+
+    /// Run queries that change the database. Only one of them runs at a time, the time spent
+    /// waiting for the others is recorded in the metrics.
+    pub async fn write<T, F>(&self, query: F) -> Result<T, anyhow::Error>
+    where
+        F: FnOnce(&PhotoDatabase) -> Result<T, anyhow::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let writer = self.writer.clone().ok_or(ReadOnlyError)?;
+        web::block(move || {
+            let start = Instant::now();
+            let db = lock(&writer);
+            metrics::record_db_write_lock_wait(start.elapsed());
+            query(&db)
+        })
+        .await?
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(_) => panic!("Photo database mutex was poisoned"),
+    }
+}
@@ -0,0 +1,174 @@
+//! Notifying clients of the web server about changes to the library.
+
+use crate::cli::db_pool::PhotoDbPool;
+use actix_web::web::Bytes;
+use futures_util::stream::Stream;
+use log::{debug, error};
+use photo_archive::database;
+use photo_archive::library::{PhotoDatabase, PhotoEvent, PhotoEventId};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// How often the database is checked for new events.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of events that are read from the database at once.
+pub const EVENT_BATCH_SIZE: u32 = 1000;
+
+/// The number of events that are buffered for each client. Clients that fall behind further
+/// are told to reload everything.
+const CHANNEL_CAPACITY: usize = 4 * EVENT_BATCH_SIZE as usize;
+
+/// How long events are kept in the database, so that clients can catch up after reconnecting.
+const RETENTION_DAYS: i64 = 1;
+
+/// How often old events are removed from the database.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often a comment is sent to idle clients, so that proxies keep the connection open
+/// and closed connections are noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Distributes the events recorded in the photo database to the connected clients.
+///
+/// Since the events are recorded by triggers in the database, this includes changes made by
+/// other processes, e.g. `photoctl photos scan`, which are noticed by polling the database.
+#[derive(Clone)]
+pub struct EventFeed {
+    sender: broadcast::Sender<PhotoEvent>,
+}
+
+impl EventFeed {
+    /// Start watching the database for events that happen from now on.
+    /// This requires running inside of the actix runtime.
+    pub async fn start(photo_db: PhotoDbPool) -> Result<EventFeed, anyhow::Error> {
+        let last_id = photo_db.read(|db| db.query_last_photo_event_id()).await?;
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        actix_rt::spawn(poll_events(photo_db, sender.clone(), last_id));
+        Ok(EventFeed { sender })
+    }
+
+    /// Receive the events that are recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<PhotoEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Create a stream of server-sent events, starting with the given events that the client missed.
+/// If the client missed more events than are available, it is told to reload everything instead.
+///
+/// The receiver should be subscribed before querying the missed events, so that no events are lost.
+pub fn event_stream(
+    receiver: broadcast::Receiver<PhotoEvent>,
+    missed: Vec<PhotoEvent>,
+    reset: bool,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let mut pending = missed.iter().map(format_event).collect::<VecDeque<_>>();
+    if reset {
+        pending.push_front(RESET_MESSAGE);
+    }
+    let state = StreamState {
+        receiver,
+        pending,
+        last_id: missed.last().map(|event| event.id),
+    };
+    futures_util::stream::unfold(state, |mut state| async move {
+        let message = state.next_message().await?;
+        Some((Ok(message), state))
+    })
+}
+
+/// Tells the client that it missed events and must reload everything.
+const RESET_MESSAGE: Bytes = Bytes::from_static(b"event: reset\ndata: {}\n\n");
+
+struct StreamState {
+    receiver: broadcast::Receiver<PhotoEvent>,
+    /// Messages that are sent before any new events.
+    pending: VecDeque<Bytes>,
+    /// The last event that was sent to the client.
+    last_id: Option<PhotoEventId>,
+}
+
+impl StreamState {
+    async fn next_message(&mut self) -> Option<Bytes> {
+        if let Some(message) = self.pending.pop_front() {
+            return Some(message);
+        }
+        loop {
+            match actix_rt::time::timeout(KEEP_ALIVE_INTERVAL, self.receiver.recv()).await {
+                Err(_) => return Some(Bytes::from_static(b": keep-alive\n\n")),
+                // The missed events can overlap with the first new ones
+                Ok(Ok(event)) if matches!(self.last_id, Some(id) if event.id.0 <= id.0) => {}
+                Ok(Ok(event)) => {
+                    self.last_id = Some(event.id);
+                    return Some(format_event(&event));
+                }
+                Ok(Err(RecvError::Lagged(count))) => {
+                    debug!("Client missed {} events", count);
+                    return Some(RESET_MESSAGE);
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            }
+        }
+    }
+}
+
+/// Remove the events that are older than the retention period. Besides the web server, this is
+/// done by the commands that change many photos, since the server may not be running.
+pub fn prune_old_events(photo_db: &PhotoDatabase) -> database::Result<usize> {
+    photo_db.prune_photo_events(chrono::Utc::now() - chrono::Duration::days(RETENTION_DAYS))
+}
+
+fn format_event(event: &PhotoEvent) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {{\"photo_id\":{}}}\n\n",
+        event.id.0,
+        event.kind.as_str(),
+        event.photo_id.0
+    ))
+}
+
+async fn poll_events(
+    photo_db: PhotoDbPool,
+    sender: broadcast::Sender<PhotoEvent>,
+    mut last_id: PhotoEventId,
+) {
+    let mut interval = actix_rt::time::interval(POLL_INTERVAL);
+    let mut last_prune: Option<Instant> = None;
+    loop {
+        interval.tick().await;
+
+        let prune_due = !matches!(last_prune, Some(time) if time.elapsed() < PRUNE_INTERVAL);
+        if prune_due && !photo_db.is_read_only() {
+            last_prune = Some(Instant::now());
+            match photo_db.write(prune_old_events).await {
+                Ok(count) => debug!("Removed {} old photo events", count),
+                Err(err) => error!("Failed to remove old photo events: {:#}", err),
+            }
+        }
+
+        loop {
+            let after = last_id;
+            let events = match photo_db
+                .read(move |db| db.query_photo_events(after, EVENT_BATCH_SIZE))
+                .await
+            {
+                Ok(events) => events,
+                Err(err) => {
+                    error!("Failed to read photo events: {:#}", err);
+                    break;
+                }
+            };
+            let complete = events.len() < EVENT_BATCH_SIZE as usize;
+            for event in events {
+                last_id = event.id;
+                // Sending only fails if no clients are connected
+                let _ = sender.send(event);
+            }
+            if complete {
+                break;
+            }
+        }
+    }
+}
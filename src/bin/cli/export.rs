@@ -0,0 +1,332 @@
+//! CLI functions specific to the `export` subcommand, which are also used for downloads from the web server.
+
+use photo_archive::library::{AlbumId, LibraryFiles, Photo, PhotoDatabase, PhotoFilter, RootId};
+
+use actix_web::web::Bytes;
+use anyhow::{bail, format_err};
+use chrono::{Datelike, Timelike};
+use futures_util::stream::Stream;
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cli;
+
+/// The size of the chunks in which archives are sent to clients.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The number of chunks that are buffered for slow clients.
+const BUFFERED_CHUNKS: usize = 16;
+
+/// The photos that should be exported. Exactly one of the fields must be set.
+#[derive(Debug, Default)]
+pub struct ExportSelection {
+    pub photos: Vec<String>,
+    pub album: Option<AlbumId>,
+    pub filter: Option<PhotoFilter>,
+}
+
+/// Write the originals of the selected photos to a ZIP archive, or to stdout if the output is `-`.
+pub fn zip(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    selection: &ExportSelection,
+    output: &Path,
+) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    let photos = match selection {
+        ExportSelection { photos, album: None, filter: None } if !photos.is_empty() => {
+            cli::resolve_photos(library, &photo_db, photos)?
+                .into_iter()
+                .map(|id| {
+                    photo_db
+                        .get_photo(id)?
+                        .ok_or_else(|| format_err!("No photo with id {}", id.0))
+                })
+                .collect::<Result<Vec<_>, anyhow::Error>>()?
+        }
+        ExportSelection { photos, album: Some(album_id), filter: None } if photos.is_empty() => {
+            let album = photo_db
+                .get_album(*album_id)?
+                .ok_or_else(|| format_err!("No album with id {}", album_id.0))?;
+            photo_db.query_album_photos(&album)?
+        }
+        ExportSelection { photos, album: None, filter: Some(filter) } if photos.is_empty() => {
+            photo_db.query_filtered_photos(filter)?
+        }
+        _ => bail!("Select the photos to export either by id, by album or by query"),
+    };
+    let entries = archive_entries(library, &library.available_roots(), photos)?;
+
+    info!("Exporting {} photos", entries.len());
+    let progress_bar = context.progress().begin_progress(entries.len());
+    let mut before_entry = || {
+        progress_bar.sender().inc_progress(1);
+        context.check_interrupted()
+    };
+
+    if output == Path::new("-") {
+        let stdout = io::stdout();
+        write_zip(&entries, BufWriter::new(stdout.lock()), &mut before_entry)?;
+    } else {
+        let file = File::create(output)?;
+        let result = write_zip(&entries, BufWriter::new(file), &mut before_entry);
+        if result.is_err() {
+            // Don't leave an incomplete archive behind
+            let _ = std::fs::remove_file(output);
+        }
+        result?;
+        info!("Exported photos to {}", output.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+/// The file of a photo and the name under which it is stored in an archive.
+pub struct ArchiveEntry {
+    name: String,
+    path: PathBuf,
+}
+
+/// Determine the names of the photos in an archive, which are their paths relative to their root.
+/// Photos with the same path in different roots get their id appended, and additionally a counter
+/// in the rare case that this is the name of another photo as well.
+///
+/// Fails if any of the photos is in a root that is not among the available roots.
+pub fn archive_entries(
+    library: &LibraryFiles,
+    available_roots: &HashSet<RootId>,
+    photos: Vec<Photo>,
+) -> Result<Vec<ArchiveEntry>, anyhow::Error> {
+    let mut seen = HashSet::new();
+    let photos = photos
+        .into_iter()
+        .filter(|photo| seen.insert(photo.id))
+        .collect::<Vec<_>>();
+
+    let mut name_counts = HashMap::new();
+    for photo in photos.iter() {
+        *name_counts.entry(archive_name(&photo.relative_path)).or_insert(0) += 1;
+    }
+    // The unique names are reserved first, so that they are never taken by the generated ones
+    let mut used_names = name_counts
+        .iter()
+        .filter(|(_, count)| **count == 1)
+        .map(|(name, _)| name.clone())
+        .collect::<HashSet<_>>();
+
+    photos
+        .iter()
+        .map(|photo| {
+            let root = library
+                .get_root(photo.root_id)
+                .ok_or_else(|| format_err!("Photo {} has no root", photo.id.0))?;
+            if !available_roots.contains(&root.id) {
+                bail!("Photo {} is stored in the offline root {}", photo.id.0, root.name);
+            }
+            let name = archive_name(&photo.relative_path);
+            let name = if name_counts[&name] > 1 {
+                let (stem, extension) = match name.rfind('.') {
+                    Some(dot) if !name[dot..].contains('/') => name.split_at(dot),
+                    _ => (name.as_str(), ""),
+                };
+                (1..)
+                    .map(|attempt| match attempt {
+                        1 => format!("{} ({}){}", stem, photo.id.0, extension),
+                        _ => format!("{} ({}-{}){}", stem, photo.id.0, attempt, extension),
+                    })
+                    .find(|candidate| !used_names.contains(candidate))
+                    .unwrap()
+            } else {
+                name
+            };
+            used_names.insert(name.clone());
+            Ok(ArchiveEntry {
+                name,
+                path: library.get_full_path(photo).unwrap(),
+            })
+        })
+        .collect()
+}
+
+fn archive_name(relative_path: &str) -> String {
+    relative_path.replace('\\', "/")
+}
+
+/// Write a ZIP archive of the given files, calling `before_entry` before adding each of them.
+///
+/// The archive is written front to back, so that it can be sent while it is being created.
+/// Photos are stored without compression, since they hardly get any smaller anyway.
+pub fn write_zip<W: Write>(
+    entries: &[ArchiveEntry],
+    writer: W,
+    mut before_entry: impl FnMut() -> io::Result<()>,
+) -> Result<W, anyhow::Error> {
+    let mut zip = ZipWriter::new_stream(writer);
+    for entry in entries {
+        before_entry()?;
+        let mut file = File::open(&entry.path)
+            .map_err(|err| format_err!("Cannot open {}: {}", entry.path.to_string_lossy(), err))?;
+        let metadata = file.metadata()?;
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(metadata.len() >= u32::MAX as u64)
+            .unix_permissions(0o644);
+        if let Ok(modified) = metadata.modified() {
+            let modified = chrono::DateTime::<chrono::Local>::from(modified);
+            if let Ok(time) = zip::DateTime::from_date_and_time(
+                modified.year() as u16,
+                modified.month() as u8,
+                modified.day() as u8,
+                modified.hour() as u8,
+                modified.minute() as u8,
+                modified.second() as u8,
+            ) {
+                options = options.last_modified_time(time);
+            }
+        }
+        zip.start_file(entry.name.as_str(), options)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+    let mut writer = zip.finish()?.into_inner();
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Create a ZIP archive of the given files while it is being sent to a client.
+///
+/// The archive is written by a separate thread, which stops when the client disconnects.
+/// If writing fails midway, the stream ends with an error, so that the client doesn't
+/// mistake the incomplete archive for a complete one.
+pub fn zip_stream(entries: Vec<ArchiveEntry>) -> impl Stream<Item = Result<Bytes, io::Error>> {
+    let (sender, receiver) = mpsc::channel(BUFFERED_CHUNKS);
+    std::thread::spawn(move || {
+        let chunk_writer = ChunkWriter {
+            sender: sender.clone(),
+            disconnected: false,
+        };
+        let writer = BufWriter::with_capacity(CHUNK_SIZE, chunk_writer);
+        if let Err(err) = write_zip(&entries, writer, || Ok(())) {
+            if sender.is_closed() {
+                info!("Client disconnected while downloading an archive");
+            } else {
+                error!("Failed to create archive: {:#}", err);
+                let _ = sender.blocking_send(Err(io::Error::other(err)));
+            }
+        }
+    });
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((chunk, receiver))
+    })
+}
+
+/// Passes everything written to it on to the stream of an archive.
+struct ChunkWriter {
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+    disconnected: bool,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.disconnected {
+            // The ZIP writer still finishes the archive when it is dropped after an error
+            return Ok(buf.len());
+        }
+        if self.sender.blocking_send(Ok(Bytes::copy_from_slice(buf))).is_err() {
+            self.disconnected = true;
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use photo_archive::formats::{PhotoInfo, Sha256Hash};
+
+    /// A library with an additional root, whose photos are stored in the photo database.
+    struct TestLibrary {
+        files: LibraryFiles,
+        photo_db: PhotoDatabase,
+        external: RootId,
+    }
+
+    impl TestLibrary {
+        fn new() -> TestLibrary {
+            let photo_db = PhotoDatabase::open_or_create(":memory:").unwrap();
+            let external = photo_db.insert_root("external", "/media/photos").unwrap();
+            let mut files = LibraryFiles::new(Path::new("/photos"));
+            files.load_roots(&photo_db).unwrap();
+            TestLibrary {
+                files,
+                photo_db,
+                external,
+            }
+        }
+
+        fn add_photo(&self, root_id: RootId, path: &str) -> Photo {
+            let info = PhotoInfo {
+                created: None,
+                file_hash: Sha256Hash::from_bytes(&[0; 32]).unwrap(),
+                camera: None,
+                rating: None,
+            };
+            let id = self.photo_db.insert_photo(root_id, path, &info).unwrap();
+            self.photo_db.get_photo(id).unwrap().unwrap()
+        }
+
+        fn entry_names(&self, photos: Vec<Photo>) -> Vec<String> {
+            let roots = HashSet::from([RootId(1), self.external]);
+            archive_entries(&self.files, &roots, photos)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn duplicate_paths_in_different_roots_are_disambiguated() {
+        let library = TestLibrary::new();
+        let main = library.add_photo(RootId(1), "2020/a.jpg");
+        let external = library.add_photo(library.external, "2020/a.jpg");
+        let other = library.add_photo(RootId(1), "2020/b.jpg");
+        assert_eq!(
+            library.entry_names(vec![main, external, other]),
+            vec!["2020/a (1).jpg", "2020/a (2).jpg", "2020/b.jpg"]
+        );
+    }
+
+    #[test]
+    fn disambiguated_names_do_not_collide_with_other_photos() {
+        let library = TestLibrary::new();
+        let main = library.add_photo(RootId(1), "a.jpg");
+        let external = library.add_photo(library.external, "a.jpg");
+        // The name that the photo from the external root would get otherwise
+        let clashing = library.add_photo(RootId(1), "a (2).jpg");
+        assert_eq!(
+            library.entry_names(vec![main, external, clashing]),
+            vec!["a (1).jpg", "a (2-2).jpg", "a (2).jpg"]
+        );
+    }
+
+    #[test]
+    fn photos_are_exported_once() {
+        let library = TestLibrary::new();
+        let photo = library.add_photo(RootId(1), "a.jpg");
+        let same_photo = library.photo_db.get_photo(photo.id).unwrap().unwrap();
+        assert_eq!(library.entry_names(vec![photo, same_photo]), vec!["a.jpg"]);
+    }
+}
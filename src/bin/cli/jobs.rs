@@ -0,0 +1,230 @@
+//! Long-running tasks that the web server runs in the background, like scanning the library.
+
+use crate::cli::photos::{self, ScanStatCollector};
+use crate::cli::{self, thumbs};
+use crate::progresslog::ProgressLogger;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use photo_archive::library::LibraryFiles;
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The number of finished jobs that are remembered, so that clients can still see how they ended.
+const MAX_FINISHED_JOBS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(transparent)]
+pub struct JobId(pub u64);
+
+/// The work that is done by a job, along with its options.
+#[derive(Debug, Clone, Copy)]
+pub enum JobTask {
+    /// Scan all available roots of the library, like `photoctl photos scan`.
+    Scan { rescan: bool },
+    /// Generate missing thumbnails, like `photoctl thumbnails generate`.
+    Thumbnails { regenerate: bool, retry_failed: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Scan,
+    Thumbnails,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// How a job ended.
+struct JobOutcome {
+    finished: DateTime<Utc>,
+    state: JobState,
+    error: Option<String>,
+}
+
+/// A job that is running or has finished.
+pub struct Job {
+    id: JobId,
+    kind: JobKind,
+    started: DateTime<Utc>,
+    /// The progress of the job. For thumbnails, `added` counts the generated thumbnails.
+    stats: ScanStatCollector,
+    cancelled: Arc<AtomicBool>,
+    outcome: Mutex<Option<JobOutcome>>,
+}
+
+impl Job {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn kind(&self) -> JobKind {
+        self.kind
+    }
+
+    pub fn started(&self) -> DateTime<Utc> {
+        self.started
+    }
+
+    pub fn stats(&self) -> &ScanStatCollector {
+        &self.stats
+    }
+
+    pub fn state(&self) -> JobState {
+        lock(&self.outcome)
+            .as_ref()
+            .map_or(JobState::Running, |outcome| outcome.state)
+    }
+
+    pub fn finished(&self) -> Option<DateTime<Utc>> {
+        lock(&self.outcome).as_ref().map(|outcome| outcome.finished)
+    }
+
+    /// The reason why the job failed.
+    pub fn error(&self) -> Option<String> {
+        lock(&self.outcome)
+            .as_ref()
+            .and_then(|outcome| outcome.error.clone())
+    }
+
+    /// Ask the job to stop as soon as possible. The changes it made so far are kept.
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(&self, result: std::thread::Result<Result<(), anyhow::Error>>) {
+        let (state, error) = match result {
+            Ok(Ok(())) => (JobState::Completed, None),
+            Ok(Err(_)) if self.cancelled.load(Ordering::SeqCst) => (JobState::Cancelled, None),
+            Ok(Err(err)) => (JobState::Failed, Some(format!("{:#}", err))),
+            Err(_) => (JobState::Failed, Some("The job crashed".to_owned())),
+        };
+        match &error {
+            Some(error) => error!("Job {} failed: {}", self.id.0, error),
+            None => info!("Job {} finished: {:?}", self.id.0, state),
+        }
+        *lock(&self.outcome) = Some(JobOutcome {
+            finished: Utc::now(),
+            state,
+            error,
+        });
+    }
+}
+
+/// Runs the jobs of the web server on their own threads.
+///
+/// All jobs write to the photo database, so only one of them can run at a time.
+#[derive(Clone)]
+pub struct JobRunner {
+    library: LibraryFiles,
+    progress_logger: ProgressLogger,
+    state: Arc<Mutex<RunnerState>>,
+}
+
+struct RunnerState {
+    next_id: u64,
+    /// The recently started jobs from oldest to newest, of which only the newest can still be running.
+    jobs: Vec<Arc<Job>>,
+}
+
+impl JobRunner {
+    pub fn new(library: LibraryFiles, progress_logger: ProgressLogger) -> Self {
+        Self {
+            library,
+            progress_logger,
+            state: Arc::new(Mutex::new(RunnerState {
+                next_id: 1,
+                jobs: Vec::new(),
+            })),
+        }
+    }
+
+    /// Start a job, unless another one is still running, whose id is returned as error in that case.
+    pub fn start(&self, task: JobTask) -> Result<Arc<Job>, JobId> {
+        let mut state = lock(&self.state);
+        if let Some(running) = state.jobs.iter().find(|job| job.state() == JobState::Running) {
+            return Err(running.id);
+        }
+
+        let job = Arc::new(Job {
+            id: JobId(state.next_id),
+            kind: match task {
+                JobTask::Scan { .. } => JobKind::Scan,
+                JobTask::Thumbnails { .. } => JobKind::Thumbnails,
+            },
+            started: Utc::now(),
+            stats: ScanStatCollector::new(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            outcome: Mutex::new(None),
+        });
+        state.next_id += 1;
+        state.jobs.push(job.clone());
+        if state.jobs.len() > MAX_FINISHED_JOBS + 1 {
+            state.jobs.remove(0);
+        }
+        drop(state);
+
+        info!("Starting job {}: {:?}", job.id.0, task);
+        let mut context = cli::AppContext::with_interruption_flag(
+            self.progress_logger.clone(),
+            job.cancelled.clone(),
+        );
+        let library = self.library.clone();
+        let thread_job = job.clone();
+        std::thread::spawn(move || {
+            let job = thread_job;
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| match task {
+                JobTask::Scan { rescan } => {
+                    let paths = photos::default_scan_paths(&library);
+                    photos::scan_with_stats(&mut context, &library, &job.stats, rescan, &paths)
+                }
+                JobTask::Thumbnails {
+                    regenerate,
+                    retry_failed,
+                } => thumbs::generate_with_stats(
+                    &mut context,
+                    &library,
+                    &job.stats,
+                    regenerate,
+                    retry_failed,
+                ),
+            }));
+            job.finish(result);
+        });
+
+        Ok(job)
+    }
+
+    /// Look up a running or recently finished job.
+    pub fn get(&self, id: JobId) -> Option<Arc<Job>> {
+        lock(&self.state).jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    /// The running job and the recently finished ones, from oldest to newest.
+    pub fn jobs(&self) -> Vec<Arc<Job>> {
+        lock(&self.state).jobs.clone()
+    }
+
+    /// Cancel a job, returning it unless it doesn't exist. Finished jobs are left unchanged.
+    pub fn cancel(&self, id: JobId) -> Option<Arc<Job>> {
+        let job = self.get(id)?;
+        if job.state() == JobState::Running {
+            info!("Cancelling job {}", id.0);
+            job.cancel();
+        }
+        Some(job)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().expect("Job mutex was poisoned")
+}
@@ -0,0 +1,157 @@
+//! Throttling of login attempts, which makes guessing passwords impractical.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The number of failed attempts after which clients have to wait before trying again.
+const FREE_ATTEMPTS: u32 = 3;
+
+/// The time to wait after the last free attempt failed, which doubles with every further failure.
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest time clients have to wait between attempts.
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Failed attempts are forgotten once a client hasn't tried to log in for this long.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Keeps track of failed login attempts per client address and lets clients wait
+/// exponentially longer between attempts the more of them failed.
+#[derive(Default)]
+pub struct LoginThrottle {
+    clients: Mutex<HashMap<IpAddr, Attempts>>,
+}
+
+struct Attempts {
+    failed: u32,
+    last: Instant,
+}
+
+impl Attempts {
+    /// How long the client has to wait after its last attempt.
+    fn delay(&self) -> Duration {
+        match self.failed.checked_sub(FREE_ATTEMPTS) {
+            Some(excess) => INITIAL_DELAY
+                .saturating_mul(2u32.saturating_pow(excess))
+                .min(MAX_DELAY),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+impl LoginThrottle {
+    /// Start a login attempt of a client. Returns how long the client still has to wait
+    /// if it is not allowed to try yet.
+    ///
+    /// The attempt counts as failed until `succeeded` is called, so that concurrent attempts
+    /// are throttled as well while their passwords are still being verified.
+    pub fn begin_attempt(&self, client: IpAddr) -> Result<(), Duration> {
+        self.begin_attempt_at(client, Instant::now())
+    }
+
+    /// Forget the failed attempts of a client after it logged in successfully.
+    pub fn succeeded(&self, client: IpAddr) {
+        self.lock().remove(&client);
+    }
+
+    fn begin_attempt_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.lock();
+        if !clients.contains_key(&client) {
+            // Only clients that are new take up more memory
+            clients.retain(|_, attempts| now.duration_since(attempts.last) < FORGET_AFTER);
+        }
+        let attempts = clients.entry(client).or_insert(Attempts {
+            failed: 0,
+            last: now,
+        });
+        let elapsed = now.duration_since(attempts.last);
+        if elapsed >= FORGET_AFTER {
+            attempts.failed = 0;
+        }
+        let delay = attempts.delay();
+        if elapsed < delay {
+            return Err(delay - elapsed);
+        }
+        attempts.failed += 1;
+        attempts.last = now;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<IpAddr, Attempts>> {
+        // The map stays consistent even if a thread panicked while holding the lock
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+    const OTHER_CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED);
+
+    #[test]
+    fn delay_grows_with_failed_attempts() {
+        let throttle = LoginThrottle::default();
+        let start = Instant::now();
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(throttle.begin_attempt_at(CLIENT, start), Ok(()));
+        }
+        assert_eq!(throttle.begin_attempt_at(CLIENT, start), Err(INITIAL_DELAY));
+        // Other clients are not affected
+        assert_eq!(throttle.begin_attempt_at(OTHER_CLIENT, start), Ok(()));
+
+        let next = start + INITIAL_DELAY;
+        assert_eq!(throttle.begin_attempt_at(CLIENT, next), Ok(()));
+        assert_eq!(
+            throttle.begin_attempt_at(CLIENT, next + INITIAL_DELAY),
+            Err(INITIAL_DELAY)
+        );
+        assert_eq!(
+            throttle.begin_attempt_at(CLIENT, next + 2 * INITIAL_DELAY),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn delay_is_limited() {
+        let throttle = LoginThrottle::default();
+        let mut now = Instant::now();
+        for _ in 0..50 {
+            if let Err(delay) = throttle.begin_attempt_at(CLIENT, now) {
+                assert!(delay <= MAX_DELAY);
+                now += delay;
+                throttle.begin_attempt_at(CLIENT, now).unwrap();
+            }
+        }
+        assert_eq!(throttle.begin_attempt_at(CLIENT, now), Err(MAX_DELAY));
+    }
+
+    #[test]
+    fn success_and_time_reset_the_delay() {
+        let throttle = LoginThrottle::default();
+        let start = Instant::now();
+        for _ in 0..FREE_ATTEMPTS {
+            throttle.begin_attempt_at(CLIENT, start).unwrap();
+        }
+        throttle.succeeded(CLIENT);
+        assert_eq!(throttle.begin_attempt_at(CLIENT, start), Ok(()));
+
+        for _ in 1..FREE_ATTEMPTS {
+            throttle.begin_attempt_at(CLIENT, start).unwrap();
+        }
+        assert!(throttle.begin_attempt_at(CLIENT, start).is_err());
+        assert_eq!(
+            throttle.begin_attempt_at(CLIENT, start + FORGET_AFTER),
+            Ok(())
+        );
+        assert_eq!(
+            throttle.begin_attempt_at(CLIENT, start + FORGET_AFTER),
+            Ok(())
+        );
+    }
+}
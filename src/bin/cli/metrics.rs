@@ -0,0 +1,127 @@
+//! Metrics of the web server, which are reported in the text format of Prometheus.
+
+use actix_web::http::StatusCode;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use std::time::Duration;
+
+/// The content type of the metrics.
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "photoarchive_http_requests_total",
+        "Number of HTTP requests by route, method and status code",
+        &["route", "method", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "photoarchive_http_request_duration_seconds",
+        "Time until the response of HTTP requests starts, excluding streaming the body",
+        &["route", "method"]
+    )
+    .unwrap();
+    static ref HTTP_NOT_MODIFIED: IntCounterVec = register_int_counter_vec!(
+        "photoarchive_http_not_modified_total",
+        "Number of HTTP requests answered with 304 Not Modified, since the client has the current version",
+        &["route"]
+    )
+    .unwrap();
+    static ref RENDER_CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "photoarchive_render_cache_lookups_total",
+        "Number of lookups of resized photos in the disk cache by result (hit or miss)",
+        &["result"]
+    )
+    .unwrap();
+    static ref THUMBNAIL_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "photoarchive_thumbnail_lookups_total",
+        "Number of requests for thumbnails by result (hit if the photo has one, miss otherwise)",
+        &["result"]
+    )
+    .unwrap();
+    static ref DB_WRITE_LOCK_WAIT: Histogram = register_histogram!(
+        "photoarchive_db_write_lock_wait_seconds",
+        "Time that changes to the photo database waited for the connection, which allows one writer at a time",
+        vec![0.0001, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+    static ref PHOTOS: IntGauge =
+        register_int_gauge!("photoarchive_photos", "Number of photos in the library").unwrap();
+    static ref THUMBNAILS: IntGaugeVec = register_int_gauge_vec!(
+        "photoarchive_thumbnails",
+        "Number of thumbnails by state (generated or failed)",
+        &["state"]
+    )
+    .unwrap();
+    static ref ROOT_ONLINE: IntGaugeVec = register_int_gauge_vec!(
+        "photoarchive_root_online",
+        "Whether a root directory of the library is available (1) or offline (0)",
+        &["root"]
+    )
+    .unwrap();
+}
+
+/// Record a request handled by the web server. The route is the pattern of the resource,
+/// such as `/api/v1/photos/{id}`, so that the number of time series stays small.
+pub fn record_request(route: &str, method: &str, status: StatusCode, duration: Duration) {
+    HTTP_REQUESTS
+        .with_label_values(&[route, method, status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[route, method])
+        .observe(duration.as_secs_f64());
+    if status == StatusCode::NOT_MODIFIED {
+        HTTP_NOT_MODIFIED.with_label_values(&[route]).inc();
+    }
+}
+
+/// Record whether a resized photo was found in the disk cache.
+pub fn record_render_cache_lookup(hit: bool) {
+    RENDER_CACHE_LOOKUPS
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+/// Record whether a requested thumbnail exists in the photo database.
+pub fn record_thumbnail_lookup(hit: bool) {
+    THUMBNAIL_LOOKUPS
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+/// Record how long a change to the photo database waited for the connection.
+pub fn record_db_write_lock_wait(duration: Duration) {
+    DB_WRITE_LOCK_WAIT.observe(duration.as_secs_f64());
+}
+
+/// The state of the library at the time the metrics are reported.
+pub struct LibraryTotals<'a> {
+    pub photos: u32,
+    pub thumbnails: u32,
+    pub failed_thumbnails: u32,
+    /// The name of each root and whether it is online.
+    pub roots: Vec<(&'a str, bool)>,
+}
+
+/// Report all metrics in the text format of Prometheus.
+pub fn encode(totals: &LibraryTotals) -> Result<String, anyhow::Error> {
+    PHOTOS.set(totals.photos.into());
+    // The totals are counted separately, so thumbnails may have failed in between
+    THUMBNAILS
+        .with_label_values(&["generated"])
+        .set(totals.thumbnails.saturating_sub(totals.failed_thumbnails).into());
+    THUMBNAILS
+        .with_label_values(&["failed"])
+        .set(totals.failed_thumbnails.into());
+    for (root, online) in totals.roots.iter() {
+        ROOT_ONLINE.with_label_values(&[*root]).set(*online as i64);
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
@@ -1,17 +1,33 @@
 //! General CLI functions.
 use photo_archive::clone;
-use photo_archive::library::{LibraryFiles, PhotoDatabase};
+use photo_archive::database;
+use photo_archive::library::{LibraryFiles, PhotoDatabase, PhotoId, PhotoPath};
 
 use crate::progresslog::ProgressLogger;
-use anyhow::bail;
+use anyhow::{bail, format_err};
 use log::{info, warn};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+pub mod albums;
 pub mod browse;
+pub mod db_pool;
+pub mod events;
+pub mod export;
+pub mod jobs;
+pub mod login_throttle;
+pub mod metrics;
 pub mod photos;
+pub mod render_cache;
+pub mod root_status;
+pub mod roots;
+pub mod search;
+pub mod shares;
+pub mod tags;
 pub mod thumbs;
+pub mod tls;
+pub mod users;
 
 /// Contains things that are relevant curing the whole execution of the app,
 /// mainly related to the CLI.
@@ -45,6 +61,18 @@ impl AppContext {
         }
     }
 
+    /// Create an application context that is interrupted by setting the given flag
+    /// instead of Ctrl+C, e.g. for cancelling background jobs of the web server.
+    pub fn with_interruption_flag(
+        progress_logger: ProgressLogger,
+        interrupted: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            interrupted,
+            progress_logger,
+        }
+    }
+
     /// Check whether the process has received an interruption signal (SIGINT on linux),
     /// and fail if that is the case.
     pub fn check_interrupted(&self) -> std::io::Result<()> {
@@ -60,6 +88,43 @@ impl AppContext {
     }
 }
 
+/// Look up a photo given on the command line, either by its id or by the path of its file.
+pub fn resolve_photo(
+    library: &LibraryFiles,
+    photo_db: &PhotoDatabase,
+    photo: &str,
+) -> Result<PhotoId, anyhow::Error> {
+    if let Ok(id) = photo.parse() {
+        let id = PhotoId(id);
+        if photo_db.get_photo(id)?.is_some() {
+            return Ok(id);
+        }
+        bail!("No photo with id {}", id.0);
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let full_path = photo_archive::util::normalize_path(&current_dir, photo.as_ref());
+    let root = library
+        .find_root(&full_path)
+        .ok_or_else(|| format_err!("{} is not part of the library", photo))?;
+    let path = PhotoPath::from_absolute(root, &full_path)?;
+    photo_db
+        .query_photo_id_by_path(path.root_id, &path.relative_path)?
+        .ok_or_else(|| format_err!("{} has not been scanned yet", photo))
+}
+
+/// Look up several photos given on the command line, failing if any of them cannot be found.
+pub fn resolve_photos(
+    library: &LibraryFiles,
+    photo_db: &PhotoDatabase,
+    photos: &[String],
+) -> Result<Vec<PhotoId>, anyhow::Error> {
+    photos
+        .iter()
+        .map(|photo| resolve_photo(library, photo_db, photo))
+        .collect()
+}
+
 /// Generate the database files.
 /// If overwrite is true, the old database files are renamed and a new database is created.
 pub fn init(files: &LibraryFiles, overwrite: bool) -> Result<(), anyhow::Error> {
@@ -95,9 +160,15 @@ pub fn status(library_files: &LibraryFiles) -> Result<(), anyhow::Error> {
             if found { "FOUND" } else { "NOT FOUND" },
         );
     };
-    print_status("Root", &library_files.root_dir, library_files.root_exists());
-
-    // TODO: open databases for status as readonly
+    print_status("Library", &library_files.root_dir, library_files.root_exists());
+    for root in library_files.roots.iter() {
+        println!(
+            "Root {}: {} ({})",
+            root.name,
+            root.path.to_string_lossy(),
+            if root.is_available() { "ONLINE" } else { "OFFLINE" },
+        );
+    }
 
     print_status(
         "Photo database",
@@ -105,7 +176,19 @@ pub fn status(library_files: &LibraryFiles) -> Result<(), anyhow::Error> {
         library_files.photo_db_exists(),
     );
     if library_files.photo_db_exists() {
-        let db = PhotoDatabase::open_or_create(&library_files.photo_db_file)?;
+        let db = match PhotoDatabase::open_read_only(&library_files.photo_db_file) {
+            Ok(db) => db,
+            // The counts are only known for the current schema
+            Err(err) if matches!(
+                err.downcast_ref(),
+                Some(database::Error::SchemaOutdated { .. } | database::Error::SchemaTooNew { .. })
+            ) =>
+            {
+                println!("  {}", err);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
         println!("  Photo count: {}", db.query_photo_count()?);
         let thumbnail_rows = db.query_thumbnail_row_count()?;
         let thumbnail_failed = db.query_thumbnail_failed_count()?;
@@ -1,37 +1,61 @@
 //! CLI functions specific to the `photos` subcommand.
 
 use photo_archive::formats::{ImageFormat, JpegFormat};
-use photo_archive::library::{LibraryFiles, PhotoDatabase, PhotoId, PhotoPath};
+use photo_archive::library::{
+    ColorLabel, Flag, LibraryFiles, Photo, PhotoDatabase, PhotoFilter, PhotoId, PhotoPath,
+    TimelineGranularity, MAX_RATING,
+};
 
-use anyhow::format_err;
-use log::{error, info, trace, warn};
+use anyhow::{bail, format_err};
+use log::{debug, error, info, trace, warn};
 use rayon::prelude::*;
+use std::io;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 
 use crate::cli;
 
-/// List all the photos in the database/
-pub fn list(context: &mut cli::AppContext, library: &LibraryFiles) -> Result<(), anyhow::Error> {
-    use std::borrow::Cow;
+/// List all the photos in the database matching the filter.
+pub fn list(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    filter: &PhotoFilter,
+) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_read_only(&library.photo_db_file)?;
 
-    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let photos = photo_db.query_filtered_photos(filter)?;
 
-    let photos = photo_db.query_all_photos()?;
+    print_photos(context, library, &photos)
+}
+
+/// Print a table of photos.
+pub fn print_photos(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    photos: &[Photo],
+) -> Result<(), anyhow::Error> {
+    use std::borrow::Cow;
 
     println!("total {}", photos.len());
-    println!("ID\tCreated\tSHA-256\tRelative path");
+    println!("ID\tCreated\tSHA-256\tRating\tRoot\tRelative path");
     for photo in photos.iter() {
         context.check_interrupted()?;
+        let root_name = library
+            .get_root(photo.root_id)
+            .map_or(Cow::Owned(format!("#{}", photo.root_id.0)), |root| {
+                Cow::Borrowed(root.name.as_str())
+            });
         println!(
-            "{}\t{}\t{:.8}..\t{}",
+            "{}\t{}\t{:.8}..\t{}\t{}\t{}",
             photo.id.0,
             photo
                 .info
                 .created
                 .map_or(Cow::Borrowed("-"), |ts| Cow::Owned(ts.to_rfc3339())),
             photo.info.file_hash,
+            format_rating(photo),
+            root_name,
             photo.relative_path,
         );
     }
@@ -39,8 +63,112 @@ pub fn list(context: &mut cli::AppContext, library: &LibraryFiles) -> Result<(),
     Ok(())
 }
 
+/// Print the number of photos per period as histogram.
+pub fn timeline(
+    library: &LibraryFiles,
+    filter: &PhotoFilter,
+    granularity: TimelineGranularity,
+) -> Result<(), anyhow::Error> {
+    const MAX_BAR_WIDTH: u32 = 50;
+
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let buckets = photo_db.query_timeline(filter, granularity)?;
+    let max_count = buckets.iter().map(|bucket| bucket.photo_count).max().unwrap_or(0);
+
+    println!("Period\tPhotos\tOffset");
+    for bucket in buckets.iter() {
+        // Round up so that every non-empty period gets a visible bar
+        let bar_width = (bucket.photo_count * MAX_BAR_WIDTH).div_ceil(max_count);
+        println!(
+            "{}\t{}\t{}\t{}",
+            bucket.period.as_deref().unwrap_or("undated"),
+            bucket.photo_count,
+            bucket.offset,
+            "#".repeat(bar_width as usize),
+        );
+    }
+
+    Ok(())
+}
+
+/// Set the title and/or comment of a photo. Empty strings remove the title or comment.
+pub fn annotate(
+    library: &LibraryFiles,
+    photo: &str,
+    title: Option<String>,
+    comment: Option<String>,
+) -> Result<(), anyhow::Error> {
+    if title.is_none() && comment.is_none() {
+        bail!("Neither a title nor a comment was given");
+    }
+
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let photo_id = cli::resolve_photo(library, &photo_db, photo)?;
+
+    if let Some(title) = title {
+        photo_db.set_photo_title(photo_id, Some(title.as_str()).filter(|t| !t.is_empty()))?;
+    }
+    if let Some(comment) = comment {
+        photo_db.set_photo_comment(photo_id, Some(comment.as_str()).filter(|c| !c.is_empty()))?;
+    }
+    info!("Annotated photo {}", photo_id.0);
+
+    Ok(())
+}
+
+/// Render the rating, flag and color label of a photo in a compact way, e.g. `***  pick red`.
+fn format_rating(photo: &Photo) -> String {
+    let mut rating = format!(
+        "{:width$}",
+        "*".repeat(photo.rating.into()),
+        width = MAX_RATING.into()
+    );
+    if let Some(flag) = photo.flag {
+        rating.push(' ');
+        rating.push_str(flag.as_str());
+    }
+    if let Some(color_label) = photo.color_label {
+        rating.push(' ');
+        rating.push_str(color_label.as_str());
+    }
+    rating
+}
+
+/// Change the rating, flag and/or color label of photos.
+pub fn rate(
+    library: &LibraryFiles,
+    photos: &[String],
+    rating: Option<u8>,
+    flag: Option<Option<Flag>>,
+    color_label: Option<Option<ColorLabel>>,
+) -> Result<(), anyhow::Error> {
+    if rating.is_none() && flag.is_none() && color_label.is_none() {
+        bail!("Neither a rating, nor a flag, nor a label was given");
+    }
+
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let photo_ids = cli::resolve_photos(library, &photo_db, photos)?;
+
+    for photo_id in photo_ids.iter() {
+        if let Some(rating) = rating {
+            photo_db.set_photo_rating(*photo_id, rating)?;
+        }
+        if let Some(flag) = flag {
+            photo_db.set_photo_flag(*photo_id, flag)?;
+        }
+        if let Some(color_label) = color_label {
+            photo_db.set_photo_color_label(*photo_id, color_label)?;
+        }
+    }
+    info!("Rated {} photos", photo_ids.len());
+
+    Ok(())
+}
+
 /// Keep track of some statistics while scanning the photo library.
-struct ScanStatCollector {
+/// The counters are also used for reporting the progress of generating thumbnails.
+#[derive(Default)]
+pub struct ScanStatCollector {
     /// The total number of photo files that were seen during collection
     total: AtomicUsize,
     /// The number of photo files that were skipped because they already exist in the database
@@ -73,18 +201,50 @@ impl ScanStatCollector {
     pub fn failed(&self) -> usize { self.failed.load(Ordering::SeqCst) }
 }
 
+/// The paths that are scanned by default, which are all roots of the library that are available.
+pub fn default_scan_paths(library: &LibraryFiles) -> Vec<PathBuf> {
+    library
+        .roots
+        .iter()
+        .filter_map(|root| {
+            if root.is_available() {
+                Some(root.path.clone())
+            } else {
+                warn!(
+                    "Skipping offline root {} ({})",
+                    root.name,
+                    root.path.to_string_lossy()
+                );
+                None
+            }
+        })
+        .collect()
+}
+
 /// Scan the photo library or subtrees of it for new and updated photos, optionally in parallel.
 pub fn scan(
     context: &mut cli::AppContext,
     library: &LibraryFiles,
     rescan: bool,
     paths: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    scan_with_stats(context, library, &ScanStatCollector::new(), rescan, paths)
+}
+
+/// Scan the photo library while updating the given statistics, e.g. for reporting the progress elsewhere.
+pub fn scan_with_stats(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    stats: &ScanStatCollector,
+    rescan: bool,
+    paths: &[PathBuf],
 ) -> Result<(), anyhow::Error> {
     let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
-    let mut stats = ScanStatCollector::new();
+    let pruned = cli::events::prune_old_events(&photo_db)?;
+    debug!("Removed {} old photo events", pruned);
 
     // STEP 1 - Collect files
-    let files_to_scan = scan_collect(context, library, &photo_db, &mut stats, rescan, paths)?;
+    let files_to_scan = scan_collect(context, library, &photo_db, stats, rescan, paths)?;
 
     info!(
         "Collected {} files ({} skipped, {} failed)",
@@ -115,12 +275,21 @@ pub fn scan(
                         synced_photo_db
                             .lock()
                             .map_err(|_| format_err!("Database mutex was poisoned"))?
-                            .update_photo(existing_id, &scan_job.path.relative_path, &info)?;
+                            .update_photo(
+                                existing_id,
+                                scan_job.path.root_id,
+                                &scan_job.path.relative_path,
+                                &info,
+                            )?;
                     } else {
                         synced_photo_db
                             .lock()
                             .map_err(|_| format_err!("Database mutex was poisoned"))?
-                            .insert_photo(&scan_job.path.relative_path, &info)?;
+                            .insert_photo(
+                                scan_job.path.root_id,
+                                &scan_job.path.relative_path,
+                                &info,
+                            )?;
                     };
                     stats.inc_added()
                 }
@@ -164,7 +333,7 @@ fn scan_collect(
     context: &mut cli::AppContext,
     library: &LibraryFiles,
     photo_db: &PhotoDatabase,
-    stats: &mut ScanStatCollector,
+    stats: &ScanStatCollector,
     rescan: bool,
     paths: &[PathBuf],
 ) -> Result<Vec<ScanJob>, anyhow::Error> {
@@ -210,9 +379,15 @@ fn scan_collect(
             stats.inc_total();
             context.check_interrupted()?;
 
-            let scan_job = match PhotoPath::from_absolute(&library.root_dir, &filename) {
+            let photo_path = library
+                .find_root(&filename)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+                .and_then(|root| PhotoPath::from_absolute(root, &filename));
+
+            let scan_job = match photo_path {
                 Ok(path) => {
-                    let existing = photo_db.query_photo_id_by_path(&path.relative_path)?;
+                    let existing =
+                        photo_db.query_photo_id_by_path(path.root_id, &path.relative_path)?;
                     if rescan || existing.is_none() {
                         Some(ScanJob {
                             existing_id: existing,
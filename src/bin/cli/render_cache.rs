@@ -0,0 +1,142 @@
+//! Disk cache for the photos rendered by the web server.
+
+use log::{debug, warn};
+use lru::LruCache;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// Extension of files that are still being written.
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Stores rendered photos as files in a directory, removing the least recently used files
+/// once their total size exceeds a limit.
+///
+/// Files that already exist in the directory are kept when opening the cache. Their
+/// order is based on their modification time, since accesses are only tracked in memory.
+pub struct RenderCache {
+    directory: PathBuf,
+    max_size: u64,
+    state: Mutex<CacheState>,
+    /// Used for generating unique names of temporary files.
+    temp_counter: AtomicUsize,
+}
+
+struct CacheState {
+    /// The size of each file in the cache by its file name.
+    entries: LruCache<String, u64>,
+    total_size: u64,
+}
+
+impl RenderCache {
+    /// Open the cache in the given directory, which is created if it doesn't exist yet.
+    pub fn open(directory: PathBuf, max_size: u64) -> io::Result<RenderCache> {
+        std::fs::create_dir_all(&directory)?;
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            if path.extension() == Some(OsStr::new(TEMP_EXTENSION)) {
+                // Left over from an interrupted write
+                std::fs::remove_file(&path)?;
+            } else if let Some(name) = entry.file_name().to_str() {
+                files.push((metadata.modified()?, name.to_owned(), metadata.len()));
+            }
+        }
+        files.sort();
+
+        let mut state = CacheState {
+            entries: LruCache::unbounded(),
+            total_size: 0,
+        };
+        for (_, name, size) in files {
+            state.entries.put(name, size);
+            state.total_size += size;
+        }
+        let cache = RenderCache {
+            directory,
+            max_size,
+            state: Mutex::new(state),
+            temp_counter: AtomicUsize::new(0),
+        };
+        cache.evict(&mut cache.lock_state());
+        Ok(cache)
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Look up a file in the cache, marking it as recently used.
+    pub fn get(&self, name: &str) -> Option<PathBuf> {
+        let mut state = self.lock_state();
+        state.entries.get(name)?;
+        let path = self.directory.join(name);
+        if path.is_file() {
+            Some(path)
+        } else {
+            // Someone else removed it
+            if let Some(size) = state.entries.pop(name) {
+                state.total_size -= size;
+            }
+            None
+        }
+    }
+
+    /// Store a file in the cache, removing the least recently used files if the cache is full.
+    /// Files that are larger than the whole cache are not stored.
+    pub fn insert(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let size = contents.len() as u64;
+        if size > self.max_size {
+            debug!("Not caching {} with {} bytes", name, size);
+            return Ok(());
+        }
+
+        // Write to a temporary file first, so that incomplete files are never served
+        let temp_path = self.directory.join(format!(
+            "{}.{}.{}",
+            name,
+            self.temp_counter.fetch_add(1, Ordering::Relaxed),
+            TEMP_EXTENSION
+        ));
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, self.directory.join(name))?;
+
+        let mut state = self.lock_state();
+        if let Some(previous_size) = state.entries.put(name.to_owned(), size) {
+            state.total_size -= previous_size;
+        }
+        state.total_size += size;
+        self.evict(&mut state);
+        Ok(())
+    }
+
+    /// Remove the least recently used files until the cache is no longer too large.
+    fn evict(&self, state: &mut CacheState) {
+        while state.total_size > self.max_size {
+            let (name, size) = match state.entries.pop_lru() {
+                Some(entry) => entry,
+                None => break,
+            };
+            state.total_size -= size;
+            if let Err(err) = std::fs::remove_file(self.directory.join(&name)) {
+                warn!("Failed to remove {} from the render cache: {}", name, err);
+            }
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, CacheState> {
+        if let Ok(guard) = self.state.lock() {
+            guard
+        } else {
+            panic!("Render cache mutex was poisoned")
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Availability of the root directories for the async handlers of the web server.
+
+use actix_web::web;
+use photo_archive::library::{LibraryFiles, Photo, RootId};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long the result of checking the roots is reused.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Checks which roots are online on the blocking thread pool, since accessing a root on an
+/// unreachable network drive can take a long time. The result is reused for a few seconds,
+/// so that listing photos doesn't touch the file system on every request.
+#[derive(Clone)]
+pub struct RootStatus {
+    library: LibraryFiles,
+    last_check: Arc<Mutex<Option<Check>>>,
+}
+
+struct Check {
+    time: Instant,
+    available_roots: Arc<HashSet<RootId>>,
+}
+
+impl RootStatus {
+    pub fn new(library: LibraryFiles) -> RootStatus {
+        RootStatus {
+            library,
+            last_check: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The ids of the roots that are currently online.
+    pub async fn available_roots(&self) -> Result<Arc<HashSet<RootId>>, anyhow::Error> {
+        // Concurrent requests wait for a running check instead of starting their own
+        let mut last_check = self.last_check.lock().await;
+        if let Some(ref check) = *last_check {
+            if check.time.elapsed() < CHECK_INTERVAL {
+                return Ok(check.available_roots.clone());
+            }
+        }
+        let library = self.library.clone();
+        let available_roots = Arc::new(web::block(move || library.available_roots()).await?);
+        *last_check = Some(Check {
+            time: Instant::now(),
+            available_roots: available_roots.clone(),
+        });
+        Ok(available_roots)
+    }
+
+    /// Check whether the root directory containing a photo is currently online.
+    pub async fn is_photo_available(&self, photo: &Photo) -> Result<bool, anyhow::Error> {
+        Ok(self.available_roots().await?.contains(&photo.root_id))
+    }
+}
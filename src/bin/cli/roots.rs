@@ -0,0 +1,46 @@
+//! CLI functions specific to the `roots` subcommand.
+
+use photo_archive::library::{LibraryFiles, PhotoDatabase};
+
+use anyhow::{bail, format_err};
+use log::info;
+use std::path::Path;
+
+/// List all root directories of the library.
+pub fn list(library: &LibraryFiles) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    println!("ID\tName\tPhotos\tState\tPath");
+    for root in library.roots.iter() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            root.id.0,
+            root.name,
+            photo_db.query_photo_count_by_root(root.id)?,
+            if root.is_available() { "online" } else { "offline" },
+            root.path.to_string_lossy(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Register an additional root directory in which photos can be stored.
+pub fn add(library: &LibraryFiles, name: &str, path: &Path) -> Result<(), anyhow::Error> {
+    if library.get_root_by_name(name).is_some() {
+        bail!("A root named {} already exists", name);
+    }
+    if !path.is_dir() {
+        bail!("Root directory {} not found", path.to_string_lossy());
+    }
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| format_err!("non-UTF-8 representable path not supported"))?;
+
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let root_id = photo_db.insert_root(name, path_str)?;
+
+    info!("Added root {} with id {}", name, root_id.0);
+
+    Ok(())
+}
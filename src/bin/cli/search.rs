@@ -0,0 +1,18 @@
+//! CLI implementation for the search subcommand.
+
+use photo_archive::library::{LibraryFiles, PhotoDatabase};
+
+use crate::cli;
+
+/// Search photos by path, title, comment and tags, showing the most relevant photos first.
+pub fn search(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    query: &str,
+) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    let photos = photo_db.search_photos(query)?;
+
+    cli::photos::print_photos(context, library, &photos)
+}
@@ -0,0 +1,123 @@
+//! CLI functions specific to the `shares` subcommand.
+
+use photo_archive::library::auth::AccessToken;
+use photo_archive::library::{LibraryFiles, PhotoDatabase, ShareId, ShareScope};
+
+use anyhow::{bail, format_err};
+use chrono::Datelike;
+use log::info;
+
+/// Parse how long a share link is valid, given as number of hours (`12h`), days (`30d`) or weeks (`2w`).
+pub fn parse_validity(value: &str) -> Result<chrono::Duration, anyhow::Error> {
+    let error = || format_err!("Invalid duration {}, expected e.g. 12h, 30d or 2w", value);
+    let unit_start = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+    let count: i64 = value[..unit_start].parse().map_err(|_| error())?;
+    let validity = match &value[unit_start..] {
+        "h" => chrono::Duration::try_hours(count),
+        "d" => chrono::Duration::try_days(count),
+        "w" => chrono::Duration::try_weeks(count),
+        _ => None,
+    };
+    validity.ok_or_else(error)
+}
+
+/// Create a share link and print its path on the web server.
+pub fn create(
+    library: &LibraryFiles,
+    scope: &ShareScope,
+    validity: chrono::Duration,
+) -> Result<(), anyhow::Error> {
+    if validity <= chrono::Duration::zero() {
+        bail!("Share links must be valid for some time");
+    }
+    // Times are stored as RFC 3339 strings, which are limited to four-digit years
+    let expires = chrono::Utc::now()
+        .checked_add_signed(validity)
+        .filter(|expires| expires.year() <= 9999)
+        .ok_or_else(|| format_err!("Invalid duration, share links cannot be valid for that long"))?;
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let description = match scope {
+        ShareScope::Album(album_id) => {
+            let album = photo_db
+                .get_album(*album_id)?
+                .ok_or_else(|| format_err!("No album with id {}", album_id.0))?;
+            format!("album {}", album.name)
+        }
+        ShareScope::Photos(filter) => {
+            format!("{} photos", photo_db.query_filtered_photo_count(filter)?)
+        }
+    };
+
+    let token = AccessToken::generate();
+    let id = photo_db.insert_share(&token, scope, expires)?;
+    info!(
+        "Created share link {} for {}, valid until {}",
+        id.0,
+        description,
+        expires.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+    );
+    println!("/s/{}", token.as_str());
+
+    Ok(())
+}
+
+/// List all share links.
+pub fn list(library: &LibraryFiles) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let now = chrono::Utc::now();
+
+    println!("ID\tCreated\tExpires\tScope");
+    for share in photo_db.query_shares()? {
+        let scope = match share.scope {
+            ShareScope::Album(album_id) => format!("album {}", album_id.0),
+            ShareScope::Photos(filter) => format!(
+                "taken {}..{}",
+                filter.from.map_or(String::new(), |date| date.to_string()),
+                filter.to.map_or(String::new(), |date| date.to_string())
+            ),
+        };
+        let expires = if share.expires <= now {
+            "expired".to_owned()
+        } else {
+            share.expires.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+        };
+        println!(
+            "{}\t{}\t{}\t{}",
+            share.id.0,
+            share.created.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            expires,
+            scope
+        );
+    }
+
+    Ok(())
+}
+
+/// Revoke a share link.
+pub fn remove(library: &LibraryFiles, share_id: ShareId) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    if !photo_db.remove_share(share_id)? {
+        bail!("No share link with id {}", share_id.0);
+    }
+    info!("Removed share link {}", share_id.0);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_validities() {
+        assert_eq!(parse_validity("12h").unwrap(), chrono::Duration::hours(12));
+        assert_eq!(parse_validity("30d").unwrap(), chrono::Duration::days(30));
+        assert_eq!(parse_validity("2w").unwrap(), chrono::Duration::weeks(2));
+        assert!(parse_validity("30").is_err());
+        assert!(parse_validity("d").is_err());
+        assert!(parse_validity("3m").is_err());
+        // Too long durations are rejected instead of overflowing
+        assert!(parse_validity(&format!("{}w", i64::MAX)).is_err());
+        assert!(parse_validity("9999999999999999w").is_err());
+    }
+}
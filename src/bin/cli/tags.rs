@@ -0,0 +1,110 @@
+//! CLI functions specific to the `tags` subcommand.
+
+use photo_archive::library::{LibraryFiles, PhotoDatabase, TagName};
+
+use anyhow::{bail, format_err};
+use log::info;
+
+use crate::cli;
+
+/// List all tag kinds.
+pub fn kinds(library: &LibraryFiles) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    println!("ID\tName");
+    for kind in photo_db.query_tag_kinds()? {
+        println!("{}\t{}", kind.id.0, kind.name);
+    }
+
+    Ok(())
+}
+
+/// Create a new tag kind.
+pub fn add_kind(library: &LibraryFiles, kind: &str) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    if photo_db.query_tag_kind_id(kind)?.is_some() {
+        bail!("Tag kind {} already exists", kind);
+    }
+    let id = photo_db.insert_tag_kind(kind)?;
+    info!("Added tag kind {} with id {}", kind, id.0);
+
+    Ok(())
+}
+
+/// Assign a tag to the given photos, or remove it from them if `assign` is false.
+pub fn assign(
+    library: &LibraryFiles,
+    tag: &TagName,
+    photos: &[String],
+    assign: bool,
+) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    // Resolve all photos first so that nothing is changed when one of them is invalid
+    let photo_ids = cli::resolve_photos(library, &photo_db, photos)?;
+
+    let mut changed = 0;
+    if assign {
+        let tag_id = photo_db.get_or_insert_tag(tag)?;
+        for photo_id in photo_ids {
+            if photo_db.assign_tag(tag_id, photo_id)? {
+                changed += 1;
+            }
+        }
+        info!("Tagged {} photos with {}", changed, tag);
+    } else {
+        let tag_id = photo_db
+            .query_tag_id(tag)?
+            .ok_or_else(|| format_err!("Tag {} not found", tag))?;
+        for photo_id in photo_ids {
+            if photo_db.unassign_tag(tag_id, photo_id)? {
+                changed += 1;
+            }
+        }
+        info!("Removed tag {} from {} photos", tag, changed);
+    }
+
+    Ok(())
+}
+
+/// Rename either a tag value (when given as `<kind>=<value>`) or a tag kind.
+pub fn rename(library: &LibraryFiles, tag: &str, new_name: &str) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    if tag.contains('=') {
+        let tag: TagName = tag.parse()?;
+        let tag_id = photo_db
+            .query_tag_id(&tag)?
+            .ok_or_else(|| format_err!("Tag {} not found", tag))?;
+        photo_db.rename_tag(tag_id, new_name)?;
+        info!("Renamed tag {} to {}={}", tag, tag.kind, new_name);
+    } else {
+        let kind_id = photo_db
+            .query_tag_kind_id(tag)?
+            .ok_or_else(|| format_err!("Tag kind {} not found", tag))?;
+        photo_db.rename_tag_kind(kind_id, new_name)?;
+        info!("Renamed tag kind {} to {}", tag, new_name);
+    }
+
+    Ok(())
+}
+
+/// List all tags, or the tags of a single photo.
+pub fn list(library: &LibraryFiles, photo: Option<&str>) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    if let Some(photo) = photo {
+        let photo_id = cli::resolve_photo(library, &photo_db, photo)?;
+        for tag in photo_db.query_photo_tags(photo_id)? {
+            println!("{}", tag);
+        }
+    } else {
+        println!("ID\tPhotos\tTag");
+        for tag in photo_db.query_tags()? {
+            println!("{}\t{}\t{}", tag.id.0, tag.photo_count, tag.name);
+        }
+    }
+
+    Ok(())
+}
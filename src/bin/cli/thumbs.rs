@@ -1,19 +1,19 @@
 //! CLI implementation for the thumbs subcommand.
 
 use crate::cli;
+use crate::cli::photos::ScanStatCollector;
 use anyhow::format_err;
-use log::info;
+use log::{debug, info, warn};
 use photo_archive::formats;
 use photo_archive::library::{LibraryFiles, PhotoDatabase, ThumbnailState};
 use rayon::prelude::*;
-use std::path::Path;
 use std::sync::Mutex;
 
 /// List all thumbnails and show statistics.
 pub fn list(context: &mut cli::AppContext, library: &LibraryFiles, errors: bool) -> Result<(), anyhow::Error> {
     use std::fmt::Write;
 
-    let db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let db = PhotoDatabase::open_read_only(&library.photo_db_file)?;
     context.check_interrupted()?;
 
     let infos = db.query_thumbnail_infos()?;
@@ -26,7 +26,7 @@ pub fn list(context: &mut cli::AppContext, library: &LibraryFiles, errors: bool)
         line.clear();
         context.check_interrupted()?;
 
-        if errors && ! info.error.is_some() {
+        if errors && info.error.is_none() {
             // TODO: filter in the database instead
             continue;
         }
@@ -70,8 +70,22 @@ pub fn generate(
     library: &LibraryFiles,
     regenerate: bool,
     retry_failed: bool,
+) -> Result<(), anyhow::Error> {
+    generate_with_stats(context, library, &ScanStatCollector::new(), regenerate, retry_failed)
+}
+
+/// Generate thumbnail images while updating the given statistics, e.g. for reporting the progress elsewhere.
+/// Photos that already have a thumbnail or are offline count as skipped.
+pub fn generate_with_stats(
+    context: &mut cli::AppContext,
+    library: &LibraryFiles,
+    stats: &ScanStatCollector,
+    regenerate: bool,
+    retry_failed: bool,
 ) -> Result<(), anyhow::Error> {
     let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let pruned = cli::events::prune_old_events(&photo_db)?;
+    debug!("Removed {} old photo events", pruned);
 
     let all_photos = photo_db.query_all_photo_ids()?;
 
@@ -81,24 +95,37 @@ pub fn generate(
 
     // compute the set of photos for which thumbnails need to be generated
     let mut photo_queue = Vec::new();
+    let mut unavailable = 0;
     for photo in photo_db.query_all_photos()? {
         progress_bar.sender().inc_progress(1);
         if context.check_interrupted().is_err() {
             // Don't return yet so that we can clean up the progress bar
             break;
         }
+        stats.inc_total();
+        if !library.is_photo_available(&photo) {
+            unavailable += 1;
+            stats.inc_skipped();
+            continue;
+        }
         let state = photo_db.query_thumbnail_state(photo.id)?;
         if state == ThumbnailState::Absent
             || (state == ThumbnailState::Present && regenerate)
             || (state == ThumbnailState::Error && retry_failed)
         {
             photo_queue.push(photo);
+        } else {
+            stats.inc_skipped();
         }
     }
 
     drop(progress_bar);
     context.check_interrupted()?;
 
+    if unavailable > 0 {
+        warn!("Skipping {} photos in offline roots", unavailable);
+    }
+
     info!(
         "Generating thumbnail images for {} photos",
         photo_queue.len()
@@ -115,11 +142,18 @@ pub fn generate(
 
             progress_bar.sender().inc_progress(1);
 
-            let full_path = library.root_dir.join(Path::new(&photo.relative_path));
+            let full_path = library
+                .get_full_path(&photo)
+                .ok_or_else(|| format_err!("Photo {} has no root", photo.id.0))?;
             // TODO: add option for thumbnail size
             let thumbnail_size = 400;
             let thumbnail_result = formats::Thumbnail::generate(&full_path, thumbnail_size)
                 .map_err(|e| format!("{}", e));
+            if thumbnail_result.is_ok() {
+                stats.inc_added();
+            } else {
+                stats.inc_failed();
+            }
             synced_photo_db
                 .lock()
                 .map_err(|_| format_err!("Database mutex was poisoned"))?
@@ -130,7 +164,11 @@ pub fn generate(
     drop(progress_bar);
     context.check_interrupted()?;
 
-    info!("Thumbnail image generation done");
+    info!(
+        "Thumbnail image generation done ({} generated, {} failed)",
+        stats.added(),
+        stats.failed()
+    );
 
     Ok(())
 }
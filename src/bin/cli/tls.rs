@@ -0,0 +1,117 @@
+//! Serving the web interface over HTTPS.
+
+use anyhow::{bail, format_err, Context};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// The PEM files containing the certificate and private key of the web server.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    /// The certificate chain, starting with the certificate of the server.
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+}
+
+/// Create the TLS configuration of the web server.
+///
+/// On Unix, the certificate and key are read again when the process receives SIGHUP,
+/// so that renewed certificates can be used without restarting the server.
+/// This requires running inside of the actix runtime.
+pub fn server_config(files: TlsFiles) -> Result<rustls::ServerConfig, anyhow::Error> {
+    let resolver = Arc::new(ReloadableCertificate::load(files)?);
+    #[cfg(unix)]
+    reload_on_hangup(resolver.clone())?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_cert_resolver(resolver);
+    Ok(config)
+}
+
+/// Provides the certificate that was loaded most recently to new connections.
+#[derive(Debug)]
+struct ReloadableCertificate {
+    files: TlsFiles,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    fn load(files: TlsFiles) -> Result<Self, anyhow::Error> {
+        let current = RwLock::new(Arc::new(load_certified_key(&files)?));
+        Ok(Self { files, current })
+    }
+
+    /// Read the files again. The previous certificate stays in use if they are invalid.
+    fn reload(&self) -> Result<(), anyhow::Error> {
+        let key = Arc::new(load_certified_key(&self.files)?);
+        match self.current.write() {
+            Ok(mut current) => *current = key,
+            Err(_) => panic!("Certificate lock was poisoned"),
+        }
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+fn load_certified_key(files: &TlsFiles) -> Result<CertifiedKey, anyhow::Error> {
+    let open = |path: &PathBuf| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Cannot open {}", path.display()))
+    };
+
+    let certificates = rustls_pemfile::certs(&mut open(&files.certificate)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate file {}", files.certificate.display()))?;
+    if certificates.is_empty() {
+        bail!("No certificates found in {}", files.certificate.display());
+    }
+    let private_key = rustls_pemfile::private_key(&mut open(&files.private_key)?)
+        .with_context(|| format!("Invalid private key file {}", files.private_key.display()))?
+        .ok_or_else(|| format_err!("No private key found in {}", files.private_key.display()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&private_key)
+        .with_context(|| format!("Unsupported private key in {}", files.private_key.display()))?;
+
+    let certified_key = CertifiedKey::new(certificates, signing_key);
+    match certified_key.keys_match() {
+        // Not all kinds of keys allow checking this, in which case the handshake fails later on
+        Ok(()) | Err(rustls::Error::InconsistentKeys(rustls::InconsistentKeys::Unknown)) => {
+            Ok(certified_key)
+        }
+        Err(err) => Err(format_err!(
+            "The private key {} doesn't belong to the certificate {}: {}",
+            files.private_key.display(),
+            files.certificate.display(),
+            err
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn reload_on_hangup(resolver: Arc<ReloadableCertificate>) -> Result<(), anyhow::Error> {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    use log::{error, info};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    actix_rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match resolver.reload() {
+                Ok(()) => info!("Reloaded TLS certificate {}", resolver.files.certificate.display()),
+                Err(err) => error!("Failed to reload TLS certificate, keeping the previous one: {:#}", err),
+            }
+        }
+    });
+    Ok(())
+}
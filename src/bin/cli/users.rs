@@ -0,0 +1,76 @@
+//! CLI functions specific to the `users` subcommand.
+
+use photo_archive::library::{auth, LibraryFiles, PhotoDatabase};
+
+use anyhow::bail;
+use log::info;
+use std::io;
+
+/// List all users of the web server.
+pub fn list(library: &LibraryFiles) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+
+    println!("ID\tName");
+    for user in photo_db.query_users()? {
+        println!("{}\t{}", user.id.0, user.name);
+    }
+
+    Ok(())
+}
+
+/// Create a user with a new password.
+pub fn add(library: &LibraryFiles, name: &str, password_stdin: bool) -> Result<(), anyhow::Error> {
+    if name.trim().is_empty() {
+        bail!("User names must not be empty");
+    }
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    let password = read_password(name, password_stdin)?;
+
+    let id = photo_db.insert_user(name, &auth::hash_password(&password)?)?;
+    info!("Created user {} with id {}", name, id.0);
+
+    Ok(())
+}
+
+/// Change the password of a user.
+pub fn passwd(library: &LibraryFiles, name: &str, password_stdin: bool) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    if photo_db.query_user_credentials(name)?.is_none() {
+        bail!("User {} not found", name);
+    }
+    let password = read_password(name, password_stdin)?;
+
+    photo_db.set_user_password_hash(name, &auth::hash_password(&password)?)?;
+    info!("Changed the password of user {}", name);
+
+    Ok(())
+}
+
+/// Delete a user, which also ends all of their sessions.
+pub fn remove(library: &LibraryFiles, name: &str) -> Result<(), anyhow::Error> {
+    let photo_db = PhotoDatabase::open_or_create(&library.photo_db_file)?;
+    if !photo_db.remove_user(name)? {
+        bail!("User {} not found", name);
+    }
+    info!("Removed user {}", name);
+
+    Ok(())
+}
+
+/// Read a new password, either interactively with confirmation or from standard input.
+fn read_password(name: &str, from_stdin: bool) -> Result<String, anyhow::Error> {
+    let password = if from_stdin {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(&['\r', '\n'][..]).to_owned()
+    } else {
+        dialoguer::Password::new()
+            .with_prompt(format!("Password for {}", name))
+            .with_confirmation("Repeat password", "The passwords don't match")
+            .interact()?
+    };
+    if password.is_empty() {
+        bail!("Passwords must not be empty");
+    }
+    Ok(password)
+}
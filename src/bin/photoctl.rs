@@ -1,6 +1,9 @@
-use photo_archive::library::LibraryFiles;
+use photo_archive::library::{
+    AlbumId, ColorLabel, Flag, LibraryFiles, PhotoDatabase, PhotoFilter, PhotoQuery, ShareId,
+    ShareScope, TagName, TimelineGranularity,
+};
+use photo_archive::util::normalize_path;
 
-use directories;
 use log::{debug, error, info, warn};
 use std::io;
 use std::path::PathBuf;
@@ -12,8 +15,11 @@ mod progresslog;
 #[derive(Debug, StructOpt)]
 #[structopt(about = "photoctl - command line photo library manager")]
 struct GlobalOpts {
-    #[structopt(short, long, parse(from_os_str))]
-    /// The root directory of the photo library to be used, if it is not the user's photo directory.
+    #[structopt(short, long, env = "PHOTO_ARCHIVE_ROOT", parse(from_os_str))]
+    /// The root directory of the photo library to be used.
+    ///
+    /// If it is not specified, the nearest ancestor of the current directory that contains
+    /// a photo database is used. Outside of a library, the user's photo directory is used.
     photo_root: Option<PathBuf>,
 
     /// How verbose should the log output be. Valid values are `error`, `warn`, `info`, `debug`, `trace` and `off`.
@@ -34,16 +40,53 @@ enum Command {
     },
     /// Display statistics and photodb information about the photo library.
     Status,
+    /// Manage the root directories where the photos of the library are stored
+    Roots {
+        #[structopt(subcommand)]
+        command: RootsCommand,
+    },
     /// Operate on the photo database
     Photos {
         #[structopt(subcommand)]
         command: PhotosCommand,
     },
+    /// Search photos by path, title, comment and tags
+    Search {
+        /// The words to search for. Words are matched as prefixes, e.g. `beach` also finds `beaches`.
+        #[structopt(required = true)]
+        query: Vec<String>,
+    },
+    /// Manage tags and tag kinds
+    Tags {
+        #[structopt(subcommand)]
+        command: TagsCommand,
+    },
+    /// Manage albums
+    Albums {
+        #[structopt(subcommand)]
+        command: AlbumsCommand,
+    },
+    /// Manage the accounts for logging in to the web server
+    Users {
+        #[structopt(subcommand)]
+        command: UsersCommand,
+    },
+    /// Manage links for viewing photos without logging in
+    #[structopt(alias = "share")]
+    Shares {
+        #[structopt(subcommand)]
+        command: SharesCommand,
+    },
     /// Operate on the thumbnail database
     Thumbnails {
         #[structopt(subcommand)]
         command: ThumbnailsCommand,
     },
+    /// Copy photos out of the library
+    Export {
+        #[structopt(subcommand)]
+        command: ExportCommand,
+    },
     /// Generate shell completion values.
     Completion {
         /// The shell for which the completions should be generated.
@@ -62,13 +105,101 @@ enum Command {
         /// frontend without recompiling the Rust part of the application.
         #[structopt(short, long, parse(from_os_str))]
         web_root: Option<PathBuf>,
+
+        /// Allow access without logging in, e.g. for development on localhost.
+        #[structopt(long)]
+        no_auth: bool,
+
+        /// A secret that clients can send as bearer token instead of logging in, e.g. for scripts.
+        #[structopt(long, env = "PHOTO_ARCHIVE_API_TOKEN", hide_env_values = true)]
+        api_token: Option<String>,
+
+        /// Serve HTTPS using the certificate chain in the given PEM file instead of plain HTTP.
+        ///
+        /// On Unix, the certificate and key are reloaded when photoctl receives SIGHUP,
+        /// e.g. after the certificate was renewed.
+        #[structopt(long, env = "PHOTO_ARCHIVE_TLS_CERT", parse(from_os_str), requires = "tls-key")]
+        tls_cert: Option<PathBuf>,
+
+        /// The PEM file containing the private key of the certificate given by `--tls-cert`.
+        #[structopt(long, env = "PHOTO_ARCHIVE_TLS_KEY", parse(from_os_str), requires = "tls-cert")]
+        tls_key: Option<PathBuf>,
+
+        /// The directory in which photos that were resized for display are cached.
+        ///
+        /// If it is not specified, a directory in the cache directory of the user is used.
+        #[structopt(long, env = "PHOTO_ARCHIVE_RENDER_CACHE_DIR", parse(from_os_str))]
+        render_cache_dir: Option<PathBuf>,
+
+        /// The maximum size of the cache of resized photos in MiB.
+        /// The least recently used photos are removed from the cache when it is full.
+        #[structopt(long, env = "PHOTO_ARCHIVE_RENDER_CACHE_SIZE", default_value = "1024")]
+        render_cache_size: u64,
+
+        /// Serve the library without changing it, e.g. from a backup or a read-only mount.
+        ///
+        /// Editing photos and albums, logging in and background jobs are rejected, so clients
+        /// have to use the API token unless authentication is disabled.
+        #[structopt(long)]
+        read_only: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command only reads the photo database, so that it is opened read-only.
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Status
+                | Command::Photos { command: PhotosCommand::List { .. } }
+                | Command::Thumbnails { command: ThumbnailsCommand::List { .. } }
+                | Command::Browse { read_only: true, .. }
+        )
+    }
+
+    /// Whether the command needs the roots from an existing photo database. Commands that don't
+    /// must work without opening it, e.g. `init --overwrite` backs up the database unchanged.
+    fn needs_photo_db(&self) -> bool {
+        !matches!(self, Command::Init { .. } | Command::Completion { .. })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum RootsCommand {
+    /// List all root directories of the library
+    List,
+    /// Add a root directory to the library, e.g. on an external drive
+    Add {
+        /// The name under which the root is known.
+        name: String,
+        /// The directory where the photos of the root are stored.
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
     },
 }
 
 #[derive(Debug, StructOpt)]
 enum PhotosCommand {
     /// List all photos in the database
-    List,
+    List {
+        /// Only list photos with at least the given star rating.
+        #[structopt(short, long)]
+        min_rating: Option<u8>,
+        /// Only list photos matching the query, e.g. `taken:2019-06..2019-08 tag:people=Anna hour:18..21`.
+        /// Supported keys are taken, camera, tag, hour, month, rating, flag and label;
+        /// plain words are searched in paths, titles, comments and tags, and `-` negates a term.
+        #[structopt(short, long, allow_hyphen_values = true)]
+        query: Option<PhotoQuery>,
+    },
+    /// Show the number of photos per year, month or day as histogram.
+    Timeline {
+        /// The length of the periods: year, month or day.
+        #[structopt(short, long, default_value = "month")]
+        granularity: TimelineGranularity,
+        /// Only count photos matching the query, see `photos list --query`.
+        #[structopt(short, long, allow_hyphen_values = true)]
+        query: Option<PhotoQuery>,
+    },
     /// Scan the library for new and updated photos.
     Scan {
         /// Also scan files that alrady exist in the database
@@ -79,6 +210,190 @@ enum PhotosCommand {
         #[structopt(parse(from_os_str))]
         paths: Vec<PathBuf>,
     },
+    /// Set the title or comment of a photo. An empty value removes the title or comment.
+    Annotate {
+        /// The photo to annotate, given either as photo id or as file path.
+        photo: String,
+        /// A short caption for the photo.
+        #[structopt(short, long)]
+        title: Option<String>,
+        /// A longer free-text description of the photo.
+        #[structopt(short, long)]
+        comment: Option<String>,
+    },
+    /// Rate, flag or label photos for culling.
+    Rate {
+        /// The photos to rate, given either as photo ids or as file paths.
+        #[structopt(required = true)]
+        photos: Vec<String>,
+        /// The star rating from 0 to 5.
+        #[structopt(short, long)]
+        rating: Option<u8>,
+        /// Mark the photos as `pick` or `reject`, or remove the flag with `none`.
+        #[structopt(short, long)]
+        flag: Option<String>,
+        /// One of `red`, `yellow`, `green`, `blue` and `purple`, or `none` for removing the label.
+        #[structopt(short, long)]
+        label: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TagsCommand {
+    /// List all tag kinds
+    Kinds,
+    /// Add a new tag kind
+    Add {
+        /// The name of the tag kind, e.g. `people`.
+        kind: String,
+    },
+    /// Assign a tag to photos. The tag is created if it doesn't exist yet.
+    Assign {
+        /// The tag to assign, written as `<kind>=<value>`, e.g. `people=Anna`.
+        tag: TagName,
+        /// The photos to tag, given either as photo ids or as file paths.
+        #[structopt(required = true)]
+        photos: Vec<String>,
+    },
+    /// Remove a tag from photos.
+    Unassign {
+        /// The tag to remove, written as `<kind>=<value>`.
+        tag: TagName,
+        /// The photos to untag, given either as photo ids or as file paths.
+        #[structopt(required = true)]
+        photos: Vec<String>,
+    },
+    /// Rename a tag value or, if no value is given, a tag kind.
+    ///
+    /// Renaming a tag to an already existing value merges both tags.
+    Rename {
+        /// The tag written as `<kind>=<value>`, or the name of a tag kind.
+        tag: String,
+        /// The new value of the tag or the new name of the tag kind.
+        new_name: String,
+    },
+    /// List all tags, or the tags of a single photo.
+    List {
+        /// Only list the tags of the given photo, either a photo id or a file path.
+        #[structopt(short, long)]
+        photo: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum AlbumsCommand {
+    /// Create a new album.
+    ///
+    /// When any of the filter options is given, a smart album is created whose photos
+    /// are all photos matching the filter at the time of viewing the album.
+    /// Otherwise, an empty album is created.
+    Create {
+        /// The name of the album.
+        name: String,
+        /// A longer description of the album.
+        #[structopt(short, long, default_value = "")]
+        description: String,
+        /// Only include photos with the given tag, written as `<kind>=<value>`. Can be repeated.
+        #[structopt(short, long = "tag", number_of_values = 1)]
+        tags: Vec<TagName>,
+        /// Only include photos taken on or after the given date (YYYY-MM-DD).
+        #[structopt(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Only include photos taken on or before the given date (YYYY-MM-DD).
+        #[structopt(long)]
+        to: Option<chrono::NaiveDate>,
+        /// Only include photos taken with the given camera model.
+        #[structopt(long)]
+        camera: Option<String>,
+        /// Only include photos with at least the given star rating.
+        #[structopt(long)]
+        min_rating: Option<u8>,
+        /// Only include photos matching the query, see `photos list --query`.
+        #[structopt(short, long, allow_hyphen_values = true)]
+        query: Option<PhotoQuery>,
+    },
+    /// Append photos to the end of an album
+    Add {
+        /// The id of the album.
+        album: i64,
+        /// The photos to add, given either as photo ids or as file paths.
+        #[structopt(required = true)]
+        photos: Vec<String>,
+    },
+    /// Remove photos from an album
+    Remove {
+        /// The id of the album.
+        album: i64,
+        /// The photos to remove, given either as photo ids or as file paths.
+        #[structopt(required = true)]
+        photos: Vec<String>,
+    },
+    /// Move a photo to a different position in an album
+    Reorder {
+        /// The id of the album.
+        album: i64,
+        /// The photo to move, given either as photo id or as file path.
+        photo: String,
+        /// The new position of the photo, starting at 1.
+        position: usize,
+    },
+    /// List all albums
+    List,
+    /// Show the photos of an album in order
+    Show {
+        /// The id of the album.
+        album: i64,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum UsersCommand {
+    /// List all users
+    List,
+    /// Create a user. The password is prompted for.
+    Add {
+        name: String,
+        /// Read the password from the first line of standard input instead of prompting for it.
+        #[structopt(long)]
+        password_stdin: bool,
+    },
+    /// Change the password of a user, which also logs out all of their sessions
+    Passwd {
+        name: String,
+        /// Read the password from the first line of standard input instead of prompting for it.
+        #[structopt(long)]
+        password_stdin: bool,
+    },
+    /// Delete a user
+    Remove { name: String },
+}
+
+#[derive(Debug, StructOpt)]
+enum SharesCommand {
+    /// Create a share link for an album or for the photos taken in a range of dates.
+    ///
+    /// Everyone who knows the printed link can view the shared photos, but nothing else of the library.
+    Create {
+        /// The id of the album to share.
+        #[structopt(long, required_unless_one = &["from", "to"], conflicts_with_all = &["from", "to"])]
+        album: Option<i64>,
+        /// Share the photos taken on or after the given date (YYYY-MM-DD).
+        #[structopt(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Share the photos taken on or before the given date (YYYY-MM-DD).
+        #[structopt(long)]
+        to: Option<chrono::NaiveDate>,
+        /// How long the link is valid, e.g. 12h, 30d or 2w.
+        #[structopt(long, default_value = "30d", parse(try_from_str = cli::shares::parse_validity))]
+        expires: chrono::Duration,
+    },
+    /// List all share links
+    List,
+    /// Revoke a share link
+    Remove {
+        /// The id of the share link.
+        share: i64,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -101,6 +416,27 @@ enum ThumbnailsCommand {
     }
 }
 
+#[derive(Debug, StructOpt)]
+enum ExportCommand {
+    /// Write the original files of photos to a ZIP archive.
+    ///
+    /// The files are named after their path relative to their root directory.
+    Zip {
+        /// The archive to create, or `-` for writing it to stdout.
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// The photos to export, given either as photo ids or as file paths.
+        #[structopt(required_unless_one = &["album", "query"], conflicts_with_all = &["album", "query"])]
+        photos: Vec<String>,
+        /// Export the photos of the album with the given id.
+        #[structopt(short, long, conflicts_with = "query")]
+        album: Option<i64>,
+        /// Export the photos matching the query, see `photos list --query`.
+        #[structopt(short, long, allow_hyphen_values = true)]
+        query: Option<PhotoQuery>,
+    },
+}
+
 fn main() {
     let opts = GlobalOpts::from_args();
 
@@ -126,34 +462,72 @@ fn main() {
 /// In case of a anyhow, it returns an error.
 /// Exit is not called and a Ctrl+C handler is installed.
 fn run(opts: GlobalOpts, context: &mut cli::AppContext) -> Result<(), anyhow::Error> {
-    let photo_root = opts.photo_root.clone().unwrap_or_else(|| {
-        let user_dirs = directories::UserDirs::new().expect("Cannot access user directories");
-        let photo_path = user_dirs
-            .picture_dir()
-            .expect("Picture directory not found");
-        PathBuf::from(photo_path)
-    });
-
-    let library_files = LibraryFiles::new(&photo_root);
+    let current_dir = std::env::current_dir()?;
+    let mut library_files = match opts.photo_root {
+        Some(ref photo_root) => LibraryFiles::new(&normalize_path(&current_dir, photo_root)),
+        None => LibraryFiles::discover(&current_dir).unwrap_or_else(|| {
+            let user_dirs = directories::UserDirs::new().expect("Cannot access user directories");
+            let photo_path = user_dirs
+                .picture_dir()
+                .expect("Picture directory not found");
+            LibraryFiles::new(photo_path)
+        }),
+    };
+
     info!(
         "Using library: {}",
         library_files.root_dir.to_string_lossy()
     );
 
+    if library_files.photo_db_exists() && opts.command.needs_photo_db() {
+        if opts.command.is_read_only() {
+            match PhotoDatabase::open_read_only(&library_files.photo_db_file) {
+                Ok(photo_db) => library_files.load_roots(&photo_db)?,
+                // The status reports why the database cannot be read
+                Err(_) if matches!(opts.command, Command::Status) => {}
+                Err(err) => return Err(err),
+            }
+        } else {
+            let photo_db = PhotoDatabase::open_or_create(&library_files.photo_db_file)?;
+            library_files.load_roots(&photo_db)?;
+        }
+    }
+
     match opts.command {
         Command::Init { overwrite } => cli::init(&library_files, overwrite),
         Command::Status => cli::status(&library_files),
+        Command::Roots { command } => match command {
+            RootsCommand::List => cli::roots::list(&library_files),
+            RootsCommand::Add { name, path } => {
+                cli::roots::add(&library_files, &name, &normalize_path(&current_dir, &path))
+            }
+        },
         Command::Photos { command } => match command {
-            PhotosCommand::List => cli::photos::list(context, &library_files),
+            PhotosCommand::List { min_rating, query } => {
+                let filter = PhotoFilter {
+                    min_rating,
+                    query,
+                    ..PhotoFilter::default()
+                };
+                cli::photos::list(context, &library_files, &filter)
+            }
+            PhotosCommand::Timeline { granularity, query } => {
+                let filter = PhotoFilter {
+                    query,
+                    ..PhotoFilter::default()
+                };
+                cli::photos::timeline(&library_files, &filter, granularity)
+            }
             PhotosCommand::Scan { rescan, paths } => {
                 let paths_to_scan: Vec<PathBuf> = if paths.is_empty() {
-                    vec![library_files.root_dir.clone()]
+                    cli::photos::default_scan_paths(&library_files)
                 } else {
                     paths
                         .iter()
+                        .map(|path| normalize_path(&current_dir, path))
                         .filter_map(|path| {
-                            if path.strip_prefix(&library_files.root_dir).is_ok() {
-                                Some(path.clone())
+                            if library_files.find_root(&path).is_some() {
+                                Some(path)
                             } else {
                                 warn!("Ignoring non-library path {}", path.to_string_lossy());
                                 None
@@ -170,6 +544,103 @@ fn run(opts: GlobalOpts, context: &mut cli::AppContext) -> Result<(), anyhow::Er
                 }
                 cli::photos::scan(context, &library_files, rescan, &paths_to_scan)
             }
+            PhotosCommand::Annotate {
+                photo,
+                title,
+                comment,
+            } => cli::photos::annotate(&library_files, &photo, title, comment),
+            PhotosCommand::Rate {
+                photos,
+                rating,
+                flag,
+                label,
+            } => {
+                let flag = flag.as_deref().map(Flag::parse_optional).transpose()?;
+                let label = label.as_deref().map(ColorLabel::parse_optional).transpose()?;
+                cli::photos::rate(&library_files, &photos, rating, flag, label)
+            }
+        },
+        Command::Search { query } => cli::search::search(context, &library_files, &query.join(" ")),
+        Command::Tags { command } => match command {
+            TagsCommand::Kinds => cli::tags::kinds(&library_files),
+            TagsCommand::Add { kind } => cli::tags::add_kind(&library_files, &kind),
+            TagsCommand::Assign { tag, photos } => {
+                cli::tags::assign(&library_files, &tag, &photos, true)
+            }
+            TagsCommand::Unassign { tag, photos } => {
+                cli::tags::assign(&library_files, &tag, &photos, false)
+            }
+            TagsCommand::Rename { tag, new_name } => {
+                cli::tags::rename(&library_files, &tag, &new_name)
+            }
+            TagsCommand::List { photo } => cli::tags::list(&library_files, photo.as_deref()),
+        },
+        Command::Albums { command } => match command {
+            AlbumsCommand::Create {
+                name,
+                description,
+                tags,
+                from,
+                to,
+                camera,
+                min_rating,
+                query,
+            } => {
+                let filter = PhotoFilter {
+                    tags,
+                    from,
+                    to,
+                    camera,
+                    min_rating,
+                    query,
+                };
+                cli::albums::create(&library_files, &name, &description, &filter)
+            }
+            AlbumsCommand::Add { album, photos } => {
+                cli::albums::add(&library_files, AlbumId(album), &photos)
+            }
+            AlbumsCommand::Remove { album, photos } => {
+                cli::albums::remove(&library_files, AlbumId(album), &photos)
+            }
+            AlbumsCommand::Reorder {
+                album,
+                photo,
+                position,
+            } => cli::albums::reorder(&library_files, AlbumId(album), &photo, position),
+            AlbumsCommand::List => cli::albums::list(&library_files),
+            AlbumsCommand::Show { album } => cli::albums::show(&library_files, AlbumId(album)),
+        },
+        Command::Users { command } => match command {
+            UsersCommand::List => cli::users::list(&library_files),
+            UsersCommand::Add {
+                name,
+                password_stdin,
+            } => cli::users::add(&library_files, &name, password_stdin),
+            UsersCommand::Passwd {
+                name,
+                password_stdin,
+            } => cli::users::passwd(&library_files, &name, password_stdin),
+            UsersCommand::Remove { name } => cli::users::remove(&library_files, &name),
+        },
+        Command::Shares { command } => match command {
+            SharesCommand::Create {
+                album,
+                from,
+                to,
+                expires,
+            } => {
+                let scope = match album {
+                    Some(album) => ShareScope::Album(AlbumId(album)),
+                    None => ShareScope::Photos(PhotoFilter {
+                        from,
+                        to,
+                        ..PhotoFilter::default()
+                    }),
+                };
+                cli::shares::create(&library_files, &scope, expires)
+            }
+            SharesCommand::List => cli::shares::list(&library_files),
+            SharesCommand::Remove { share } => cli::shares::remove(&library_files, ShareId(share)),
         },
         Command::Thumbnails { command } => match command {
             ThumbnailsCommand::Generate {
@@ -179,6 +650,24 @@ fn run(opts: GlobalOpts, context: &mut cli::AppContext) -> Result<(), anyhow::Er
             ThumbnailsCommand::Delete => cli::thumbs::delete(context, &library_files),
             ThumbnailsCommand::List { errors } => cli::thumbs::list(context, &library_files, errors),
         },
+        Command::Export { command } => match command {
+            ExportCommand::Zip {
+                output,
+                photos,
+                album,
+                query,
+            } => {
+                let selection = cli::export::ExportSelection {
+                    photos,
+                    album: album.map(AlbumId),
+                    filter: query.map(|query| PhotoFilter {
+                        query: Some(query),
+                        ..PhotoFilter::default()
+                    }),
+                };
+                cli::export::zip(context, &library_files, &selection, &output)
+            }
+        },
         Command::Completion { shell } => {
             GlobalOpts::clap().gen_completions_to(
                 "photoctl",
@@ -187,8 +676,42 @@ fn run(opts: GlobalOpts, context: &mut cli::AppContext) -> Result<(), anyhow::Er
             );
             Ok(())
         }
-        Command::Browse { bind, web_root } => {
-            actix_rt::System::new().block_on(cli::browse::browse(context, &library_files, &bind, web_root))
-        },
+        Command::Browse {
+            bind,
+            web_root,
+            no_auth,
+            api_token,
+            tls_cert,
+            tls_key,
+            render_cache_dir,
+            render_cache_size,
+            read_only,
+        } => {
+            let render_cache_dir = match render_cache_dir {
+                Some(dir) => dir,
+                None => directories::ProjectDirs::from("", "", "photo-archive")
+                    .ok_or_else(|| {
+                        anyhow::format_err!("Cannot determine the cache directory, use --render-cache-dir")
+                    })?
+                    .cache_dir()
+                    .join("render"),
+            };
+            let options = cli::browse::BrowseOptions {
+                binds: bind,
+                web_root,
+                no_auth,
+                api_token,
+                tls: tls_cert
+                    .zip(tls_key)
+                    .map(|(certificate, private_key)| cli::tls::TlsFiles {
+                        certificate,
+                        private_key,
+                    }),
+                render_cache_dir,
+                render_cache_size: render_cache_size * 1024 * 1024,
+                read_only,
+            };
+            actix_rt::System::new().block_on(cli::browse::browse(context, &library_files, options))
+        }
     }
 }
@@ -84,7 +84,7 @@ pub struct AutoHideProgressBar {
 }
 
 impl AutoHideProgressBar {
-    pub fn sender(&self) -> ProgressSender {
+    pub fn sender(&self) -> ProgressSender<'_> {
         ProgressSender { progress_bar: self }
     }
 }
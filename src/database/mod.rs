@@ -2,7 +2,7 @@
 
 use thiserror::Error;
 use log::{debug, info};
-use rusqlite::{Connection, OptionalExtension, Transaction};
+use rusqlite::{Connection, ErrorCode, OpenFlags, OptionalExtension, Transaction};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
@@ -16,6 +16,26 @@ pub struct Database<S> {
 pub enum Error {
     #[error("Unknown schema {version}")]
     UnknownSchemaVersion { version: Version },
+    #[error("Database {} not found", .path.to_string_lossy())]
+    Missing { path: PathBuf },
+    #[error(
+        "Database {} has the schema {version}, which is newer than the schema {latest} supported by this version",
+        .path.to_string_lossy()
+    )]
+    SchemaTooNew {
+        path: PathBuf,
+        version: Version,
+        latest: Version,
+    },
+    #[error(
+        "Database {} has the outdated schema {version}, which is upgraded to {latest} when opening it for writing",
+        .path.to_string_lossy()
+    )]
+    SchemaOutdated {
+        path: PathBuf,
+        version: Version,
+        latest: Version,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
@@ -47,17 +67,60 @@ where
 {
     pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<Self> {
         debug!("Opening database {}", path.as_ref().to_string_lossy());
+        let conn = Connection::open(&path)?;
+        Self::open_writable(path, conn)
+    }
 
-        let filename = path.as_ref().to_path_buf();
-        let mut conn = Connection::open(path)?;
+    /// Open a database for reading and writing, failing if it doesn't exist instead of creating it.
+    /// Like `open_or_create`, the database is not migrated yet.
+    pub fn open_existing<P: AsRef<Path>>(path: P) -> Result<Self> {
+        debug!("Opening existing database {}", path.as_ref().to_string_lossy());
+        Self::check_exists(path.as_ref())?;
+        let conn = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        Self::open_writable(path, conn)
+    }
 
+    fn open_writable<P: AsRef<Path>>(path: P, mut conn: Connection) -> Result<Self> {
         // set some sensible defaults
         conn.execute("PRAGMA foreign_keys = ON", [])?;
 
         let current_version = Self::init_for_migrations(&mut conn)?;
-        let schema = S::from_version(current_version).ok_or(Error::UnknownSchemaVersion {
-            version: current_version,
-        })?;
+        let schema = Self::schema_for_version(path.as_ref(), current_version)?;
+
+        Ok(Self {
+            conn,
+            schema,
+            filename: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Open an existing database without write access. The database is not migrated,
+    /// so its schema may be older than the latest one.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        debug!("Opening database {} read-only", path.as_ref().to_string_lossy());
+        Self::check_exists(path.as_ref())?;
+
+        let filename = path.as_ref().to_path_buf();
+        let (conn, current_version) = match Self::open_read_only_connection(&filename, false) {
+            // Reading a database in WAL mode requires its `-shm` file, which cannot be created
+            // on read-only media. Nobody can change the database there, so it can be opened
+            // as immutable, which doesn't need that file.
+            Err(err) if is_cannot_open(&err) => {
+                debug!(
+                    "Cannot open database {} read-only ({}), opening it as immutable",
+                    filename.to_string_lossy(),
+                    err
+                );
+                Self::open_read_only_connection(&filename, true)?
+            }
+            result => result?,
+        };
+        let schema = Self::schema_for_version(&filename, current_version)?;
 
         Ok(Self {
             conn,
@@ -66,6 +129,60 @@ where
         })
     }
 
+    /// Open a read-only connection and determine the version of the database.
+    fn open_read_only_connection(
+        path: &Path,
+        immutable: bool,
+    ) -> rusqlite::Result<(Connection, Version)> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI;
+        let conn = if immutable {
+            Connection::open_with_flags(immutable_uri(path), flags)?
+        } else {
+            Connection::open_with_flags(path, flags)?
+        };
+        let version = Version(conn.query_row("SELECT version FROM version", [], |row| row.get(0))?);
+        Ok((conn, version))
+    }
+
+    fn check_exists(path: &Path) -> std::result::Result<(), Error> {
+        if path.is_file() {
+            Ok(())
+        } else {
+            Err(Error::Missing {
+                path: path.to_path_buf(),
+            })
+        }
+    }
+
+    /// Look up the schema of a database, which fails for databases created by newer versions.
+    fn schema_for_version(path: &Path, version: Version) -> std::result::Result<S, Error> {
+        let latest = S::latest().version();
+        if version > latest {
+            return Err(Error::SchemaTooNew {
+                path: path.to_path_buf(),
+                version,
+                latest,
+            });
+        }
+        S::from_version(version).ok_or(Error::UnknownSchemaVersion { version })
+    }
+
+    /// Fail unless the database has the latest schema, e.g. if it cannot be migrated
+    /// because it was opened read-only.
+    pub fn require_latest_schema(&self) -> std::result::Result<(), Error> {
+        let latest = S::latest().version();
+        if self.schema.version() < latest {
+            return Err(Error::SchemaOutdated {
+                path: self.filename.clone(),
+                version: self.schema.version(),
+                latest,
+            });
+        }
+        Ok(())
+    }
+
     pub fn connection(&self) -> &Connection {
         &self.conn
     }
@@ -138,3 +255,110 @@ where
         Ok(())
     }
 }
+
+fn is_cannot_open(err: &rusqlite::Error) -> bool {
+    matches!(err, rusqlite::Error::SqliteFailure(err, _) if err.code == ErrorCode::CannotOpen)
+}
+
+/// The URI for opening a database file as immutable, see <https://sqlite.org/uri.html>.
+fn immutable_uri(path: &Path) -> String {
+    let mut uri = String::from("file:");
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+    uri.push_str("?immutable=1");
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum TestSchema {
+        Empty,
+        Items,
+    }
+
+    impl Schema for TestSchema {
+        fn from_version(version: Version) -> Option<Self> {
+            match version.0 {
+                0 => Some(TestSchema::Empty),
+                1 => Some(TestSchema::Items),
+                _ => None,
+            }
+        }
+
+        fn version(&self) -> Version {
+            match self {
+                TestSchema::Empty => Version(0),
+                TestSchema::Items => Version(1),
+            }
+        }
+
+        fn latest() -> Self {
+            TestSchema::Items
+        }
+
+        fn run_upgrade(&self, tx: &Transaction) -> Result<()> {
+            tx.execute("CREATE TABLE items(name TEXT)", [])?;
+            Ok(())
+        }
+    }
+
+    /// A temporary directory that is removed again afterwards.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_read_only_without_write_ahead_log_files() {
+        // Special characters must be escaped in the URI for opening the database as immutable
+        let dir = TempDir::new("photo-archive-read-only 100%#?");
+        let path = dir.0.join("test.db");
+        {
+            let mut db = Database::<TestSchema>::open_or_create(&path).unwrap();
+            db.upgrade().unwrap();
+            let mode: String = db
+                .connection()
+                .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(mode, "wal");
+            db.connection()
+                .execute("INSERT INTO items(name) VALUES ('photo')", [])
+                .unwrap();
+        }
+
+        // Like on a read-only mount, the `-shm` and `-wal` files don't exist and cannot be created
+        for suffix in ["-shm", "-wal"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            let _ = std::fs::remove_file(&file);
+            std::os::unix::fs::symlink(dir.0.join("missing").join(suffix), &file).unwrap();
+        }
+
+        let db = Database::<TestSchema>::open_read_only(&path).unwrap();
+        assert_eq!(db.schema(), &TestSchema::Items);
+        let count: u32 = db
+            .connection()
+            .query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
@@ -1,7 +1,11 @@
 use super::{ImageFormat, PhotoInfo, Sha256Hash};
 use log::debug;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
+/// Identifies the APP1 segment containing the XMP packet of a JPEG file.
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
 pub struct JpegFormat;
 
 impl ImageFormat for JpegFormat {
@@ -10,13 +14,14 @@ impl ImageFormat for JpegFormat {
     }
 
     fn supported_extension(&self, path: &Path) -> bool {
-        path.extension().map_or(false, |ext| {
+        path.extension().is_some_and(|ext| {
             ext == "jpg" || ext == "JPG" || ext == "jpeg" || ext == "JPEG"
         })
     }
 
     fn read_info(&self, filename: &Path) -> std::io::Result<PhotoInfo> {
-        let created = read_exif_datetime(filename).or_else(|| {
+        let exif = read_exif(filename);
+        let created = exif.as_ref().and_then(read_exif_datetime).or_else(|| {
             filename
                 .metadata()
                 .and_then(|meta| meta.created())
@@ -26,13 +31,21 @@ impl ImageFormat for JpegFormat {
 
         let file_hash = Sha256Hash::hash_file(filename)?;
 
-        Ok(PhotoInfo { created, file_hash })
+        let camera = exif.as_ref().and_then(read_exif_camera);
+        let rating = read_xmp(filename).and_then(|xmp| parse_xmp_rating(&xmp));
+
+        Ok(PhotoInfo {
+            created,
+            file_hash,
+            camera,
+            rating,
+        })
     }
 }
 
-fn read_exif_datetime(filename: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+pub(super) fn read_exif(filename: &Path) -> Option<exif::Exif> {
     let file = std::fs::File::open(filename).ok()?;
-    let exif_reader = exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file))
+    exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file))
         .map(Some)
         .unwrap_or_else(|exif_err| {
             debug!(
@@ -41,8 +54,21 @@ fn read_exif_datetime(filename: &Path) -> Option<chrono::DateTime<chrono::Utc>>
                 exif_err
             );
             None
-        })?;
+        })
+}
 
+fn read_exif_camera(exif_reader: &exif::Exif) -> Option<String> {
+    let model = exif_reader.get_field(exif::Tag::Model, exif::In::PRIMARY)?;
+    match model.value {
+        exif::Value::Ascii(ref ascii) => ascii
+            .first()
+            .map(|bytes| String::from_utf8_lossy(bytes).trim().to_owned())
+            .filter(|model| !model.is_empty()),
+        _ => None,
+    }
+}
+
+fn read_exif_datetime(exif_reader: &exif::Exif) -> Option<chrono::DateTime<chrono::Utc>> {
     let created_exif = exif_reader.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY);
     let digitized_exif = exif_reader.get_field(exif::Tag::DateTimeDigitized, exif::In::PRIMARY);
 
@@ -78,3 +104,52 @@ fn parse_exif_datetime(exif_datetime: &exif::Value) -> Option<chrono::DateTime<c
         .earliest()
         .map(|dt| dt.with_timezone(&chrono::Utc))
 }
+
+/// Extract the XMP packet from the APP1 segment of a JPEG file.
+fn read_xmp(filename: &Path) -> Option<String> {
+    let mut reader = BufReader::new(std::fs::File::open(filename).ok()?);
+
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        // Metadata segments are only allowed before the start of the image data
+        if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 {
+            return None;
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        // The length includes the two length bytes
+        let length = usize::from(u16::from_be_bytes(length)).checked_sub(2)?;
+        let mut segment = vec![0u8; length];
+        reader.read_exact(&mut segment).ok()?;
+
+        if marker[1] == 0xE1 && segment.starts_with(XMP_HEADER) {
+            return Some(String::from_utf8_lossy(&segment[XMP_HEADER.len()..]).into_owned());
+        }
+    }
+}
+
+/// Find the `xmp:Rating` property in an XMP packet.
+/// It can either be given as attribute (`xmp:Rating="3"`) or as element (`<xmp:Rating>3</xmp:Rating>`).
+fn parse_xmp_rating(xmp: &str) -> Option<i8> {
+    let start = xmp.find("xmp:Rating")? + "xmp:Rating".len();
+    let rest = xmp[start..].trim_start();
+    let value = if let Some(attribute) = rest.strip_prefix('=') {
+        let attribute = attribute.trim_start();
+        let quote = attribute.chars().next()?;
+        attribute[quote.len_utf8()..].split(quote).next()?
+    } else {
+        rest.strip_prefix('>')?.split('<').next()?
+    };
+    let rating = value.trim().parse::<f32>().ok()?.round();
+    if (-1.0..=5.0).contains(&rating) {
+        Some(rating as i8)
+    } else {
+        None
+    }
+}
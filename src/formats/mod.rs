@@ -5,8 +5,10 @@ use std::io::Cursor;
 use std::path::Path;
 
 mod jpeg;
+mod render;
 
 pub use jpeg::JpegFormat;
+pub use render::{render_photo, RenderFit, RenderFormat, RenderOptions};
 
 /// Length of a SHA-256 hash in bytes.
 const SHA256_BYTES: usize = 32;
@@ -73,7 +75,7 @@ impl Sha256Hash {
 
 impl fmt::Display for Sha256Hash {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        let num_bytes = formatter.precision().unwrap_or(std::usize::MAX);
+        let num_bytes = formatter.precision().unwrap_or(usize::MAX);
         for b in self.as_bytes().iter().take(num_bytes) {
             write!(formatter, "{:02x}", b)?;
         }
@@ -101,6 +103,11 @@ pub struct PhotoInfo {
     pub created: Option<chrono::DateTime<chrono::Utc>>,
     /// Hash of the whole file of the photo
     pub file_hash: Sha256Hash,
+    /// Model of the camera that took the photo
+    pub camera: Option<String>,
+    /// The rating stored in the XMP metadata of the photo (`xmp:Rating`).
+    /// Ratings range from 0 to 5, with -1 meaning that the photo was rejected.
+    pub rating: Option<i8>,
     // TODO: Also hash the image data of the photo separately,
     // for finding duplicates
     //pub image_data_hash: Sha256Hash,
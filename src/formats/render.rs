@@ -0,0 +1,144 @@
+//! Rendering photos in arbitrary sizes for displaying them.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::Deserialize;
+use std::io::Cursor;
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// Quality of rendered JPEG images.
+const JPEG_QUALITY: u8 = 85;
+
+/// Quality of rendered WebP images.
+const WEBP_QUALITY: f32 = 80.0;
+
+/// How a photo is fitted into the requested size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFit {
+    /// Scale the photo so that it fits into the size, keeping its aspect ratio.
+    #[default]
+    Contain,
+    /// Scale the photo so that it covers the size, cropping the parts that exceed it.
+    Cover,
+}
+
+/// The file format of rendered photos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    #[default]
+    Jpeg,
+    Webp,
+}
+
+impl RenderFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            RenderFormat::Jpeg => "image/jpeg",
+            RenderFormat::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RenderFormat::Jpeg => "jpg",
+            RenderFormat::Webp => "webp",
+        }
+    }
+}
+
+/// The size and format in which a photo is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub fit: RenderFit,
+    pub format: RenderFormat,
+}
+
+impl RenderOptions {
+    /// A string that identifies the rendered image for a given original, e.g. for caching it.
+    /// It changes whenever the rendering changes.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "v1-{}x{}-{:?}.{}",
+            self.width,
+            self.height,
+            self.fit,
+            self.format.extension()
+        )
+    }
+}
+
+/// Render a photo in the given size and format. The photo is rotated according to its EXIF orientation,
+/// and it is never enlarged, so the result can be smaller than requested.
+pub fn render_photo<P: AsRef<Path>>(
+    original_file: P,
+    options: &RenderOptions,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let original_file = original_file.as_ref();
+    let img = image::open(original_file)?;
+    let orientation = super::jpeg::read_exif(original_file)
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1);
+    let img = apply_orientation(img, orientation);
+
+    let (width, height) = (options.width, options.height);
+    let img = match options.fit {
+        RenderFit::Contain if img.width() > width || img.height() > height => {
+            img.resize(width, height, FilterType::CatmullRom)
+        }
+        RenderFit::Contain => img,
+        RenderFit::Cover if img.width() >= width && img.height() >= height => {
+            img.resize_to_fill(width, height, FilterType::CatmullRom)
+        }
+        RenderFit::Cover => {
+            // Only crop small photos to the requested aspect ratio
+            let scale = f64::min(
+                f64::from(img.width()) / f64::from(width),
+                f64::from(img.height()) / f64::from(height),
+            );
+            let crop_width = ((f64::from(width) * scale) as u32).clamp(1, img.width());
+            let crop_height = ((f64::from(height) * scale) as u32).clamp(1, img.height());
+            img.crop_imm(
+                (img.width() - crop_width) / 2,
+                (img.height() - crop_height) / 2,
+                crop_width,
+                crop_height,
+            )
+        }
+    };
+
+    let rgb = img.to_rgb8();
+    let encoded = match options.format {
+        RenderFormat::Jpeg => {
+            let mut jpg = Vec::new();
+            DynamicImage::ImageRgb8(rgb)
+                .write_to(&mut Cursor::new(&mut jpg), image::ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+            jpg
+        }
+        RenderFormat::Webp => webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height())
+            .encode(WEBP_QUALITY)
+            .to_vec(),
+    };
+    Ok(encoded)
+}
+
+/// Transform an image as described by its EXIF orientation, so that it is displayed upright.
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
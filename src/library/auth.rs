@@ -0,0 +1,55 @@
+//! Passwords and access tokens of the users of the web server.
+
+use anyhow::format_err;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+use crate::formats::Sha256Hash;
+
+/// Number of random bytes in an access token.
+const TOKEN_BYTES: usize = 32;
+
+/// Hash a password with Argon2 and a random salt, returning the hash in PHC string format.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| format_err!("Failed to hash password: {}", err))
+}
+
+/// Check a password against a hash produced by `hash_password`.
+pub fn verify_password(password_hash: &str, password: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+/// A secret identifying a login session or a share link. Only its hash is stored in the database,
+/// so that a leaked database doesn't allow taking over sessions or share links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken(String);
+
+impl AccessToken {
+    /// Generate a new random token.
+    pub fn generate() -> Self {
+        let mut bytes = [0; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        AccessToken(hex::encode(bytes))
+    }
+
+    /// Wrap a token that was sent by a client.
+    pub fn new(token: String) -> Self {
+        AccessToken(token)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The hash under which the token is stored in the database.
+    pub(crate) fn hash(&self) -> Sha256Hash {
+        Sha256Hash::hash_bytes(self.0.as_bytes())
+    }
+}
@@ -0,0 +1,185 @@
+//! Filters for selecting photos from the photo database, e.g. for smart albums.
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use rusqlite::types::ToSql;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::photodb::{Photo, PhotoId, TagName};
+use super::query::PhotoQuery;
+
+/// A set of conditions that all must be met by a photo in order to match the filter.
+/// An empty filter matches all photos.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PhotoFilter {
+    /// Tags that the photo must have, such as `people=Anna` or `location=Home`.
+    #[serde(default)]
+    pub tags: Vec<TagName>,
+    /// The first day (inclusive, local time) on which the photo may have been taken.
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    /// The last day (inclusive, local time) on which the photo may have been taken.
+    #[serde(default)]
+    pub to: Option<NaiveDate>,
+    /// The camera model the photo must have been taken with, compared case-insensitively.
+    #[serde(default)]
+    pub camera: Option<String>,
+    /// The minimum star rating of the photo.
+    #[serde(default)]
+    pub min_rating: Option<u8>,
+    /// A query that the photo must match in addition to the other conditions.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub query: Option<PhotoQuery>,
+}
+
+impl PhotoFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == PhotoFilter::default()
+    }
+
+    /// Translate the filter into an SQL condition on the `photos` table, which must be aliased as `p`.
+    /// The returned parameters are referenced positionally.
+    pub(crate) fn to_sql(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut conditions = vec!["1".to_owned()];
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+        for tag in self.tags.iter() {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM tags_x_photos tp
+                 INNER JOIN tags t ON t.id = tp.tag_id
+                 INNER JOIN tag_kinds k ON k.id = t.kind
+                 WHERE tp.photo_id = p.id AND k.name = ? AND t.name = ?)"
+                    .to_owned(),
+            );
+            params.push(Box::new(tag.kind.clone()));
+            params.push(Box::new(tag.value.clone()));
+        }
+        // Days are compared in local time, like the `taken:` query and the timeline
+        if let Some(from) = self.from {
+            conditions.push("date(p.created, 'localtime') >= ?".to_owned());
+            params.push(Box::new(from.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(to) = self.to {
+            conditions.push("date(p.created, 'localtime') <= ?".to_owned());
+            params.push(Box::new(to.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(ref camera) = self.camera {
+            conditions.push("p.camera = ? COLLATE NOCASE".to_owned());
+            params.push(Box::new(camera.clone()));
+        }
+        if let Some(min_rating) = self.min_rating {
+            conditions.push("p.rating >= ?".to_owned());
+            params.push(Box::new(min_rating));
+        }
+        if let Some(ref query) = self.query {
+            let (condition, query_params) = query.to_sql();
+            conditions.push(format!("({})", condition));
+            params.extend(query_params);
+        }
+
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// The order in which photos are listed. Photos without a capture date always come last.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PhotoOrder {
+    /// Most recent photos first.
+    #[default]
+    Newest,
+    /// Oldest photos first.
+    Oldest,
+}
+
+impl PhotoOrder {
+    /// The `ORDER BY` clause for the `photos` table, which must be aliased as `p`.
+    pub(crate) fn to_sql(self) -> &'static str {
+        match self {
+            PhotoOrder::Newest => "p.created IS NULL, p.created DESC, p.id DESC",
+            PhotoOrder::Oldest => "p.created IS NULL, p.created ASC, p.id ASC",
+        }
+    }
+}
+
+/// Position in a listing of photos, identifying the last photo of the previous page.
+/// It is written as `<created>,<id>`, where the capture time is empty for photos without one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhotoCursor {
+    pub created: Option<DateTime<Utc>>,
+    pub id: PhotoId,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid cursor {0}, expected <created>,<id>")]
+pub struct InvalidCursor(String);
+
+impl PhotoCursor {
+    /// The cursor pointing at the given photo, i.e. the next page starts after it.
+    pub fn after(photo: &Photo) -> Self {
+        PhotoCursor {
+            created: photo.info.created,
+            id: photo.id,
+        }
+    }
+
+    /// Translate the cursor into an SQL condition selecting the photos that come after it
+    /// in the given order. The returned parameters are referenced positionally.
+    pub(crate) fn to_sql(&self, order: PhotoOrder) -> (String, Vec<Box<dyn ToSql>>) {
+        let comparison = match order {
+            PhotoOrder::Newest => "<",
+            PhotoOrder::Oldest => ">",
+        };
+        match self.created {
+            // Timestamps are stored as RFC 3339 strings, which can be compared lexicographically
+            Some(created) => {
+                let created = created.to_rfc3339();
+                (
+                    format!(
+                        "(p.created {0} ? OR (p.created = ? AND p.id {0} ?) OR p.created IS NULL)",
+                        comparison
+                    ),
+                    vec![Box::new(created.clone()), Box::new(created), Box::new(self.id)],
+                )
+            }
+            None => (
+                format!("(p.created IS NULL AND p.id {} ?)", comparison),
+                vec![Box::new(self.id)],
+            ),
+        }
+    }
+}
+
+impl std::str::FromStr for PhotoCursor {
+    type Err = InvalidCursor;
+
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCursor(cursor.to_owned());
+        let (created, id) = cursor.rsplit_once(',').ok_or_else(invalid)?;
+        let created = if created.is_empty() {
+            None
+        } else {
+            Some(
+                DateTime::parse_from_rfc3339(created)
+                    .map_err(|_| invalid())?
+                    .with_timezone(&Utc),
+            )
+        };
+        let id = id.parse().map_err(|_| invalid())?;
+        Ok(PhotoCursor {
+            created,
+            id: PhotoId(id),
+        })
+    }
+}
+
+impl std::fmt::Display for PhotoCursor {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(created) = self.created {
+            formatter.write_str(&created.to_rfc3339_opts(SecondsFormat::AutoSi, true))?;
+        }
+        write!(formatter, ",{}", self.id.0)
+    }
+}
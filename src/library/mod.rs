@@ -1,31 +1,100 @@
+use std::collections::HashSet;
 use std::io;
 use std::path::{Path, PathBuf};
 
+pub mod auth;
+mod filter;
 mod photodb;
+mod query;
 
+pub use filter::{InvalidCursor, PhotoCursor, PhotoFilter, PhotoOrder};
 pub use photodb::*;
+pub use query::{PhotoQuery, QueryError};
+
+/// Name of the photo database file in the library root directory.
+/// Its presence is what marks a directory as the root of a photo library.
+pub const PHOTO_DB_FILE_NAME: &str = "photos.db";
+
+/// Name of the root that is created together with the library.
+pub const MAIN_ROOT_NAME: &str = "main";
 
 /// Holds the paths that a photo library consists of.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LibraryFiles {
-    /// The directory where all the photos are stored.
-    /// Photos outside of that directory cannot be indexed.
+    /// The directory containing the photo database.
+    /// It is also the main root directory of the library.
     pub root_dir: PathBuf,
     /// Path of the Sqlite database containing the photo metadata and cached thumbnails.
     pub photo_db_file: PathBuf,
+    /// The directories where the photos are stored.
+    /// Photos outside of these directories cannot be indexed.
+    pub roots: Vec<LibraryRoot>,
+}
+
+/// A directory in which photos of the library are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryRoot {
+    pub id: RootId,
+    /// Name of the root used for referring to it on the command line.
+    pub name: String,
+    /// Absolute path of the root directory.
+    pub path: PathBuf,
+}
+
+impl LibraryRoot {
+    /// Check whether the root directory is currently accessible.
+    /// Roots on removable or network drives might be offline temporarily.
+    pub fn is_available(&self) -> bool {
+        self.path.is_dir()
+    }
 }
 
 impl LibraryFiles {
     pub fn new(root_path: &Path) -> LibraryFiles {
         let root_dir = root_path.to_owned();
-        let photo_db_file = root_dir.join("photos.db");
+        let photo_db_file = root_dir.join(PHOTO_DB_FILE_NAME);
+        let main_root = LibraryRoot {
+            id: RootId::MAIN,
+            name: MAIN_ROOT_NAME.to_owned(),
+            path: root_dir.clone(),
+        };
 
         LibraryFiles {
             root_dir,
             photo_db_file,
+            roots: vec![main_root],
         }
     }
 
+    /// Find the library that contains the given path, by walking up the directory
+    /// hierarchy until a directory containing a photo database is found.
+    pub fn discover(start_path: &Path) -> Option<LibraryFiles> {
+        start_path
+            .ancestors()
+            .map(LibraryFiles::new)
+            .find(LibraryFiles::photo_db_exists)
+    }
+
+    /// Replace the roots of the library with the ones registered in the photo database.
+    pub fn load_roots(&mut self, photo_db: &PhotoDatabase) -> crate::database::Result<()> {
+        self.roots = photo_db
+            .query_roots()?
+            .into_iter()
+            .map(|root| LibraryRoot {
+                id: root.id,
+                name: root.name,
+                path: self.resolve_root_path(&root.path),
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Resolve the path of a root as stored in the database.
+    /// Relative paths are interpreted relative to the directory containing the photo database.
+    pub fn resolve_root_path(&self, path: &str) -> PathBuf {
+        self.root_dir.join(path).components().collect()
+    }
+
     pub fn root_exists(&self) -> bool {
         self.root_dir.is_dir()
     }
@@ -34,12 +103,46 @@ impl LibraryFiles {
         self.photo_db_file.is_file()
     }
 
+    /// Look up a root by its id.
+    pub fn get_root(&self, id: RootId) -> Option<&LibraryRoot> {
+        self.roots.iter().find(|root| root.id == id)
+    }
+
+    /// Look up a root by its name.
+    pub fn get_root_by_name(&self, name: &str) -> Option<&LibraryRoot> {
+        self.roots.iter().find(|root| root.name == name)
+    }
+
+    /// Find the root that contains the given absolute path.
+    /// When roots are nested, the innermost root is returned.
+    pub fn find_root(&self, absolute_path: &Path) -> Option<&LibraryRoot> {
+        self.roots
+            .iter()
+            .filter(|root| absolute_path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
     /// Retrieve the full path of a photo stored in the database.
-    pub fn get_full_path(&self, photo: &photodb::Photo) -> PathBuf {
-        let mut full_path = self.root_dir.clone();
-        let rel_path = Path::new(&photo.relative_path);
-        full_path.push(rel_path);
-        full_path
+    /// Returns `None` if the photo belongs to a root that is not part of the library.
+    pub fn get_full_path(&self, photo: &photodb::Photo) -> Option<PathBuf> {
+        self.get_root(photo.root_id)
+            .map(|root| PhotoPath::from_relative(root, &photo.relative_path).full_path)
+    }
+
+    /// Check whether the root directory containing a photo is currently accessible.
+    pub fn is_photo_available(&self, photo: &photodb::Photo) -> bool {
+        self.get_root(photo.root_id)
+            .map(LibraryRoot::is_available)
+            .unwrap_or(false)
+    }
+
+    /// Check which root directories are currently accessible, returning their ids.
+    pub fn available_roots(&self) -> HashSet<RootId> {
+        self.roots
+            .iter()
+            .filter(|root| root.is_available())
+            .map(|root| root.id)
+            .collect()
     }
 }
 
@@ -48,6 +151,7 @@ impl LibraryFiles {
 /// Currently only supports paths that can be encoded as UTF-8.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PhotoPath {
+    pub root_id: RootId,
     pub full_path: PathBuf,
     pub relative_path: String,
 }
@@ -59,9 +163,9 @@ impl PhotoPath {
     ///
     /// Returns an error when the absolute photo path is not a subdirectory of the root directory,
     /// or when the path is not representable as UTF-8.
-    pub fn from_absolute(root_dir: &Path, absolute_path: &Path) -> io::Result<Self> {
+    pub fn from_absolute(root: &LibraryRoot, absolute_path: &Path) -> io::Result<Self> {
         let relative_path = absolute_path
-            .strip_prefix(root_dir)
+            .strip_prefix(&root.path)
             .map_err(|_| io::Error::from(io::ErrorKind::NotFound))?;
         let relative_str = match relative_path.to_str() {
             None =>
@@ -75,15 +179,17 @@ impl PhotoPath {
             Some(path_str) => Ok(path_str.to_owned()),
         }?;
         Ok(Self {
+            root_id: root.id,
             full_path: absolute_path.to_path_buf(),
             relative_path: relative_str,
         })
     }
 
     /// Retrieve the full path of a photo stored in the database.
-    pub fn from_relative(root_dir: &Path, relative_path: &str) -> Self {
-        let full_path = root_dir.join(Path::new(relative_path));
+    pub fn from_relative(root: &LibraryRoot, relative_path: &str) -> Self {
+        let full_path = root.path.join(Path::new(relative_path));
         Self {
+            root_id: root.id,
             full_path,
             relative_path: relative_path.to_owned(),
         }
@@ -96,7 +202,7 @@ pub fn scan_library(path: &Path) -> impl Iterator<Item = walkdir::Result<walkdir
     let photo_predicate = |entry: &walkdir::DirEntry| {
         let name = entry.file_name().to_str();
         // TODO: support windows hidden files
-        let is_hidden = name.map_or(false, |s| s.starts_with('.'));
+        let is_hidden = name.is_some_and(|s| s.starts_with('.'));
         !is_hidden
     };
 
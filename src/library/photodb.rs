@@ -3,16 +3,21 @@
 use std::path::Path;
 
 use chrono::{DateTime, Utc};
+use anyhow::format_err;
 use log::debug;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::{FromPrimitive, ToPrimitive};
-use rusqlite::types::{FromSql, ToSql};
+use rusqlite::types::{FromSql, ToSql, Type};
 use rusqlite::{OptionalExtension, Transaction};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
 
 use crate::database;
 use crate::database::{Database, Schema};
 use crate::formats::{PhotoInfo, Sha256Hash, Thumbnail};
+use crate::library::auth::AccessToken;
+use crate::library::{PhotoCursor, PhotoFilter, PhotoOrder};
 
 /// Database containing metadata about photos.
 #[derive(Debug)]
@@ -20,28 +25,197 @@ pub struct PhotoDatabase {
     db: Database<PhotoDbSchema>,
 }
 
-/// Key for uniquely identifying a photo.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
-#[repr(transparent)]
-pub struct PhotoId(pub i64);
+/// Define a newtype wrapper around an integer primary key that can be used directly in queries.
+macro_rules! id_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, ToSchema)]
+        #[repr(transparent)]
+        pub struct $name(pub i64);
 
-impl FromSql for PhotoId {
-    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
-        FromSql::column_result(value).map(PhotoId)
-    }
+        impl FromSql for $name {
+            fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+                FromSql::column_result(value).map($name)
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+                self.0.to_sql()
+            }
+        }
+    };
 }
 
-impl ToSql for PhotoId {
-    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
-        self.0.to_sql()
-    }
+id_type!(
+    /// Key for uniquely identifying a photo.
+    PhotoId
+);
+
+id_type!(
+    /// Key for uniquely identifying a root directory of the library.
+    RootId
+);
+
+impl RootId {
+    /// The root that is created together with the library.
+    pub const MAIN: RootId = RootId(1);
 }
 
+id_type!(
+    /// Key for uniquely identifying a kind of tags, such as people or locations.
+    TagKindId
+);
+
+id_type!(
+    /// Key for uniquely identifying a tag.
+    TagId
+);
+
+id_type!(
+    /// Key for uniquely identifying an album.
+    AlbumId
+);
+
+id_type!(
+    /// Key for uniquely identifying a user of the web server.
+    UserId
+);
+
+id_type!(
+    /// Key for uniquely identifying a share link.
+    ShareId
+);
+
+id_type!(
+    /// Sequence number of an entry in the event log. Later events have larger ids.
+    PhotoEventId
+);
+
+/// The format of the times in the `photo_events` table, which are set by SQLite.
+const EVENT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// The columns of the `photos` table (aliased as `p`) that are needed for constructing a `Photo`.
+const PHOTO_COLUMNS: &str = "p.id, p.root_id, p.rel_path, p.created, p.file_hash, p.camera, \
+    p.title, p.comment, p.rating, p.flag, p.color_label, p.created_override IS NOT NULL";
+
 /// A row in the photo database
 pub struct Photo {
     pub id: PhotoId,
+    /// The root directory the photo is stored in.
+    pub root_id: RootId,
+    /// The path of the photo relative to its root directory.
     pub relative_path: String,
     pub info: PhotoInfo,
+    /// Whether the capture time in `info` was set by the user instead of being read from the file.
+    pub created_overridden: bool,
+    /// A short caption for the photo.
+    pub title: Option<String>,
+    /// A free-text description of the photo.
+    pub comment: Option<String>,
+    /// Star rating from 0 (unrated) to 5.
+    pub rating: u8,
+    /// Whether the photo was picked or rejected while culling.
+    pub flag: Option<Flag>,
+    pub color_label: Option<ColorLabel>,
+}
+
+/// The highest star rating a photo can have.
+pub const MAX_RATING: u8 = 5;
+
+/// Marks a photo as picked or rejected while culling.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Flag {
+    Pick,
+    Reject,
+}
+
+/// Color labels for grouping photos, e.g. during culling.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+/// Implement string conversions for enums that are stored in the database by their lowercase name.
+macro_rules! text_enum {
+    ($name:ident { $($variant:ident => $text:expr),+ $(,)? }) => {
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $text),+
+                }
+            }
+
+            /// Parse an optional value, where `none` or an empty string stand for the absence of a value.
+            pub fn parse_optional(value: &str) -> Result<Option<Self>, InvalidValue> {
+                match value {
+                    "" | "none" => Ok(None),
+                    _ => value.parse().map(Some),
+                }
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = InvalidValue;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                match value {
+                    $($text => Ok($name::$variant),)+
+                    _ => Err(InvalidValue {
+                        value: value.to_owned(),
+                        expected: concat!($($text, ", ",)+ "none"),
+                    }),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str(self.as_str())
+            }
+        }
+
+        impl FromSql for $name {
+            fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+                value
+                    .as_str()?
+                    .parse()
+                    .map_err(|err| rusqlite::types::FromSqlError::Other(Box::new(err)))
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+                Ok(self.as_str().into())
+            }
+        }
+    };
+}
+
+text_enum!(Flag {
+    Pick => "pick",
+    Reject => "reject",
+});
+
+text_enum!(ColorLabel {
+    Red => "red",
+    Yellow => "yellow",
+    Green => "green",
+    Blue => "blue",
+    Purple => "purple",
+});
+
+#[derive(Debug, Error)]
+#[error("Invalid value {value}, expected one of: {expected}")]
+pub struct InvalidValue {
+    value: String,
+    expected: &'static str,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -52,6 +226,223 @@ pub enum ThumbnailState {
 }
 
 
+/// Changes to the user-editable metadata of a photo, see `PhotoDatabase::update_photo_metadata`.
+/// Fields that are `None` are left unchanged, whereas `Some(None)` removes the value.
+#[derive(Debug, Clone, Default)]
+pub struct PhotoChanges {
+    /// Overrides the capture time read from the file. Removing the override restores the time from the file.
+    pub created: Option<Option<DateTime<Utc>>>,
+    pub title: Option<Option<String>>,
+    pub comment: Option<Option<String>>,
+    pub rating: Option<u8>,
+    pub flag: Option<Option<Flag>>,
+    pub color_label: Option<Option<ColorLabel>>,
+    /// Tags to assign to the photo. Their kinds must already exist.
+    pub add_tags: Vec<TagName>,
+    /// Tags to remove from the photo.
+    pub remove_tags: Vec<TagName>,
+}
+
+/// A root directory as registered in the photo database.
+pub struct RootInfo {
+    pub id: RootId,
+    pub name: String,
+    /// The path of the root directory, either absolute or relative to the library directory.
+    pub path: String,
+}
+
+/// A kind of tags, such as people or locations.
+pub struct TagKind {
+    pub id: TagKindId,
+    pub name: String,
+}
+
+/// The human readable name of a tag, consisting of its kind and its value.
+/// It is written as `<kind>=<value>`, e.g. `people=Anna`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct TagName {
+    pub kind: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for TagName {
+    type Err = TagError;
+
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        match tag.split_once('=') {
+            Some((kind, value)) if !kind.is_empty() && !value.is_empty() => Ok(TagName {
+                kind: kind.to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(TagError::InvalidName(tag.to_owned())),
+        }
+    }
+}
+
+impl std::fmt::Display for TagName {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{}={}", self.kind, self.value)
+    }
+}
+
+/// Metadata about a tag.
+pub struct TagInfo {
+    pub id: TagId,
+    pub name: TagName,
+    /// The number of photos that have been tagged with this tag.
+    pub photo_count: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("Invalid tag {0}, expected <kind>=<value>")]
+    InvalidName(String),
+    #[error("Unknown tag kind {0}")]
+    UnknownKind(String),
+}
+
+/// An account for logging in to the web server.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: UserId,
+    pub name: String,
+}
+
+#[derive(Debug, Error)]
+pub enum UserError {
+    #[error("A user named {0} already exists")]
+    AlreadyExists(String),
+}
+
+/// The photos that can be viewed through a share link.
+#[derive(Debug, Clone)]
+pub enum ShareScope {
+    /// The photos of an album, in the order of the album.
+    Album(AlbumId),
+    /// The photos matching a filter, e.g. a range of capture dates.
+    Photos(PhotoFilter),
+}
+
+/// A link for viewing some of the photos without logging in.
+pub struct Share {
+    pub id: ShareId,
+    pub scope: ShareScope,
+    pub created: DateTime<Utc>,
+    /// After this time, the link can no longer be used.
+    pub expires: DateTime<Utc>,
+}
+
+/// What happened to a photo.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhotoEventKind {
+    PhotoAdded,
+    /// The metadata or tags of the photo changed.
+    PhotoUpdated,
+    PhotoRemoved,
+    /// A thumbnail was generated for the photo.
+    ThumbnailReady,
+}
+
+impl PhotoEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PhotoEventKind::PhotoAdded => "photo_added",
+            PhotoEventKind::PhotoUpdated => "photo_updated",
+            PhotoEventKind::PhotoRemoved => "photo_removed",
+            PhotoEventKind::ThumbnailReady => "thumbnail_ready",
+        }
+    }
+}
+
+impl FromSql for PhotoEventKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "photo_added" => Ok(PhotoEventKind::PhotoAdded),
+            "photo_updated" => Ok(PhotoEventKind::PhotoUpdated),
+            "photo_removed" => Ok(PhotoEventKind::PhotoRemoved),
+            "thumbnail_ready" => Ok(PhotoEventKind::ThumbnailReady),
+            kind => Err(rusqlite::types::FromSqlError::Other(
+                format!("Unknown photo event kind {}", kind).into(),
+            )),
+        }
+    }
+}
+
+/// A change of a photo. Changes are recorded by triggers in the database,
+/// so that changes made by other processes, e.g. a scan, are included.
+#[derive(Debug, Clone)]
+pub struct PhotoEvent {
+    pub id: PhotoEventId,
+    pub kind: PhotoEventKind,
+    pub photo_id: PhotoId,
+}
+
+/// A curated collection of photos in a user-defined order.
+pub struct Album {
+    pub id: AlbumId,
+    pub name: String,
+    pub description: String,
+    /// The number of photos in the album.
+    pub photo_count: u32,
+    /// For smart albums, the filter that selects the photos of the album.
+    /// The photos of smart albums are selected when querying the album and cannot be changed manually.
+    pub filter: Option<PhotoFilter>,
+}
+
+/// A term from the full-text search index that can be used for auto-completing search queries.
+pub struct SearchSuggestion {
+    pub term: String,
+    /// The number of photos containing the term.
+    pub photo_count: u32,
+}
+
+/// The length of the periods that photos are grouped into on a timeline.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineGranularity {
+    Year,
+    Month,
+    Day,
+}
+
+impl TimelineGranularity {
+    /// The `strftime` format identifying the period a timestamp belongs to.
+    fn period_format(self) -> &'static str {
+        match self {
+            TimelineGranularity::Year => "%Y",
+            TimelineGranularity::Month => "%Y-%m",
+            TimelineGranularity::Day => "%Y-%m-%d",
+        }
+    }
+}
+
+impl std::str::FromStr for TimelineGranularity {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "year" => Ok(TimelineGranularity::Year),
+            "month" => Ok(TimelineGranularity::Month),
+            "day" => Ok(TimelineGranularity::Day),
+            _ => Err(InvalidValue {
+                value: value.to_owned(),
+                expected: "year, month, day",
+            }),
+        }
+    }
+}
+
+/// A period on the timeline together with the photos taken in it.
+pub struct TimelineBucket {
+    /// The period formatted as `YYYY`, `YYYY-MM` or `YYYY-MM-DD` depending on the granularity,
+    /// or none for the photos without a capture date.
+    pub period: Option<String>,
+    pub photo_count: u32,
+    /// The position of the first photo of the period when listing photos in the default order.
+    pub offset: u32,
+}
+
 /// Metadata about a thumbnail.
 pub struct ThumbnailInfo {
     /// The photo the thumbnail belongs to.
@@ -66,91 +457,1066 @@ pub struct ThumbnailInfo {
     pub relative_path: String,
 }
 
-impl PhotoDatabase {
-    pub fn open_or_create<P: AsRef<Path>>(path: P) -> database::Result<PhotoDatabase> {
-        let mut db = database::Database::open_or_create(path)?;
-        db.upgrade()?;
-        Ok(Self { db })
+impl PhotoDatabase {
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> database::Result<PhotoDatabase> {
+        let mut db = database::Database::open_or_create(path)?;
+        db.upgrade()?;
+        Ok(Self { db })
+    }
+
+    /// Open a database that has been created before, upgrading its schema if necessary.
+    /// Fails with `database::Error::Missing` if it doesn't exist.
+    pub fn open_existing<P: AsRef<Path>>(path: P) -> database::Result<PhotoDatabase> {
+        let mut db = database::Database::open_existing(path)?;
+        db.upgrade()?;
+        Ok(Self { db })
+    }
+
+    /// Open an existing database for querying only, e.g. for serving concurrent requests.
+    /// Its schema must be up to date already, otherwise this fails with
+    /// `database::Error::SchemaOutdated`.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> database::Result<PhotoDatabase> {
+        let db = database::Database::<PhotoDbSchema>::open_read_only(path)?;
+        db.require_latest_schema()?;
+        Ok(Self { db })
+    }
+
+    /// Switch to write-ahead logging, so that readers on other connections don't block the writer
+    /// and vice versa. The setting is stored in the database file.
+    pub fn enable_write_ahead_log(&self) -> database::Result<()> {
+        let mode: String = self
+            .db
+            .connection()
+            .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+        debug!("Photo database uses journal mode {}", mode);
+        Ok(())
+    }
+
+    pub fn insert_photo(
+        &self,
+        root_id: RootId,
+        path_str: &str,
+        info: &PhotoInfo,
+    ) -> database::Result<PhotoId> {
+        let created_str = info.created.map(|ts| ts.to_rfc3339()); // ISO formatted date
+        let (rating, flag) = Self::initial_rating(info);
+        self.db.connection().execute(
+            "INSERT INTO photos(root_id, rel_path, created, scanned_created, file_hash, camera, rating, flag) VALUES (?1, ?2, ?3, ?3, ?4, ?5, ?6, ?7)",
+            [&root_id as &dyn ToSql, &path_str, &created_str, &info.file_hash, &info.camera, &rating, &flag],
+        )?;
+
+        Ok(PhotoId(self.db.connection().last_insert_rowid()))
+    }
+
+    pub fn update_photo(
+        &self,
+        id: PhotoId,
+        root_id: RootId,
+        path_str: &str,
+        info: &PhotoInfo,
+    ) -> database::Result<usize> {
+        let created_str = info.created.map(|ts| ts.to_rfc3339()); // ISO formatted date
+        let (rating, flag) = Self::initial_rating(info);
+        // A capture time set by the user takes precedence over the one from the file.
+        // Likewise, the rating from the file is only applied while the photo is still unrated,
+        // e.g. because it was scanned before ratings existed.
+        Ok(self.db.connection().execute(
+            "UPDATE photos SET root_id = ?1, rel_path = ?2, created = COALESCE(created_override, ?3), scanned_created = ?3,
+             file_hash = ?4, camera = ?5,
+             rating = CASE WHEN rating = 0 AND flag IS NULL THEN ?7 ELSE rating END,
+             flag = CASE WHEN rating = 0 AND flag IS NULL THEN ?8 ELSE flag END
+             WHERE id = ?6",
+            [&root_id as &dyn ToSql, &path_str, &created_str, &info.file_hash, &info.camera, &id, &rating, &flag],
+        )?)
+    }
+
+    /// The rating and flag of a photo as read from the file. A rating of -1 marks rejected photos.
+    /// The rating from the file is only used initially, afterwards the database is authoritative.
+    fn initial_rating(info: &PhotoInfo) -> (u8, Option<Flag>) {
+        match info.rating {
+            Some(rating) if rating < 0 => (0, Some(Flag::Reject)),
+            Some(rating) => (rating as u8, None),
+            None => (0, None),
+        }
+    }
+
+    pub fn get_photo(&self, id: PhotoId) -> database::Result<Option<Photo>> {
+        self.db
+            .connection()
+            .query_row(
+                &format!("SELECT {} FROM photos p WHERE p.id = ?1", PHOTO_COLUMNS),
+                [id],
+                Self::map_photo_row,
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn query_photo_id_by_path(
+        &self,
+        root_id: RootId,
+        path_str: &str,
+    ) -> database::Result<Option<PhotoId>> {
+        self.query_scalar_optional(
+            "SELECT id FROM photos WHERE root_id = ?1 AND rel_path = ?2",
+            [&root_id as &dyn ToSql, &path_str],
+        )
+    }
+
+    pub fn query_all_photo_ids(&self) -> database::Result<std::vec::Vec<PhotoId>> {
+        let mut stmt = self
+            .db
+            .connection()
+            .prepare("SELECT id FROM photos ORDER BY created DESC")?;
+        let ls: rusqlite::Result<std::vec::Vec<PhotoId>> =
+            stmt.query_map([], |row| row.get(0))?.collect();
+        ls.map_err(Into::into)
+    }
+
+    pub fn query_all_photos(&self) -> database::Result<Vec<Photo>> {
+        let mut stmt = self
+            .db
+            .connection()
+            .prepare(&format!(
+                "SELECT {} FROM photos p ORDER BY p.created DESC",
+                PHOTO_COLUMNS
+            ))?;
+        let ls: rusqlite::Result<Vec<Photo>> =
+            stmt.query_map([], Self::map_photo_row)?.collect();
+        ls.map_err(Into::into)
+    }
+
+    pub fn query_photo_count(&self) -> database::Result<u32> {
+        self.query_scalar("SELECT COUNT(*) FROM photos", [])
+    }
+
+    fn map_photo_row(row: &rusqlite::Row) -> rusqlite::Result<Photo> {
+        Ok(Photo {
+            id: row.get(0)?,
+            root_id: row.get(1)?,
+            relative_path: row.get(2)?,
+            info: PhotoInfo {
+                created: row.get::<_, Option<String>>(3)?.map(|ts_str| {
+                    DateTime::parse_from_rfc3339(&ts_str)
+                        .expect("Database corrupted (invalid date in table `photos`)")
+                        .with_timezone(&Utc)
+                }),
+                file_hash: row.get(4)?,
+                camera: row.get(5)?,
+                // Only relevant while scanning, the rating in the database is stored separately
+                rating: None,
+            },
+            created_overridden: row.get(11)?,
+            title: row.get(6)?,
+            comment: row.get(7)?,
+            rating: row.get(8)?,
+            flag: row.get(9)?,
+            color_label: row.get(10)?,
+        })
+    }
+
+    /// Set the star rating of a photo. Returns false if the photo doesn't exist.
+    pub fn set_photo_rating(&self, id: PhotoId, rating: u8) -> database::Result<bool> {
+        if rating > MAX_RATING {
+            return Err(format_err!("Ratings must be between 0 and {}", MAX_RATING));
+        }
+        let updated = self.db.connection().execute(
+            "UPDATE photos SET rating = ?1 WHERE id = ?2",
+            [&rating as &dyn ToSql, &id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Set or, if `None`, remove the pick/reject flag of a photo. Returns false if the photo doesn't exist.
+    pub fn set_photo_flag(&self, id: PhotoId, flag: Option<Flag>) -> database::Result<bool> {
+        let updated = self.db.connection().execute(
+            "UPDATE photos SET flag = ?1 WHERE id = ?2",
+            [&flag as &dyn ToSql, &id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Set or, if `None`, remove the color label of a photo. Returns false if the photo doesn't exist.
+    pub fn set_photo_color_label(
+        &self,
+        id: PhotoId,
+        color_label: Option<ColorLabel>,
+    ) -> database::Result<bool> {
+        let updated = self.db.connection().execute(
+            "UPDATE photos SET color_label = ?1 WHERE id = ?2",
+            [&color_label as &dyn ToSql, &id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Set or, if `None`, remove the title of a photo. Returns false if the photo doesn't exist.
+    pub fn set_photo_title(&self, id: PhotoId, title: Option<&str>) -> database::Result<bool> {
+        let updated = self.db.connection().execute(
+            "UPDATE photos SET title = ?1 WHERE id = ?2",
+            [&title as &dyn ToSql, &id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Set or, if `None`, remove the comment of a photo. Returns false if the photo doesn't exist.
+    pub fn set_photo_comment(&self, id: PhotoId, comment: Option<&str>) -> database::Result<bool> {
+        let updated = self.db.connection().execute(
+            "UPDATE photos SET comment = ?1 WHERE id = ?2",
+            [&comment as &dyn ToSql, &id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Apply several changes to the metadata of a photo at once.
+    /// Either all changes are applied or, if one of them fails, none of them.
+    /// Returns false if the photo doesn't exist.
+    pub fn update_photo_metadata(&self, id: PhotoId, changes: &PhotoChanges) -> database::Result<bool> {
+        if changes.rating.filter(|rating| *rating > MAX_RATING).is_some() {
+            return Err(format_err!("Ratings must be between 0 and {}", MAX_RATING));
+        }
+
+        let tx = self.db.connection().unchecked_transaction()?;
+        let exists: Option<i64> =
+            self.query_scalar_optional("SELECT 1 FROM photos WHERE id = ?1", [id])?;
+        if exists.is_none() {
+            return Ok(false);
+        }
+
+        if let Some(created) = changes.created {
+            let created_str = created.map(|ts| ts.to_rfc3339()); // ISO formatted date
+            tx.execute(
+                "UPDATE photos SET created_override = ?1, created = COALESCE(?1, scanned_created) WHERE id = ?2",
+                [&created_str as &dyn ToSql, &id],
+            )?;
+        }
+        if let Some(ref title) = changes.title {
+            self.set_photo_title(id, title.as_deref())?;
+        }
+        if let Some(ref comment) = changes.comment {
+            self.set_photo_comment(id, comment.as_deref())?;
+        }
+        if let Some(rating) = changes.rating {
+            self.set_photo_rating(id, rating)?;
+        }
+        if let Some(flag) = changes.flag {
+            self.set_photo_flag(id, flag)?;
+        }
+        if let Some(color_label) = changes.color_label {
+            self.set_photo_color_label(id, color_label)?;
+        }
+        for tag in changes.add_tags.iter() {
+            let tag_id = self.get_or_insert_tag(tag)?;
+            self.assign_tag(tag_id, id)?;
+        }
+        for tag in changes.remove_tags.iter() {
+            if let Some(tag_id) = self.query_tag_id(tag)? {
+                Self::delete_tag_assignment(&tx, tag_id, id)?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Register a new root directory for storing photos.
+    pub fn insert_root(&self, name: &str, path_str: &str) -> database::Result<RootId> {
+        self.db.connection().execute(
+            "INSERT INTO roots(name, path) VALUES (?1, ?2)",
+            [name, path_str],
+        )?;
+
+        Ok(RootId(self.db.connection().last_insert_rowid()))
+    }
+
+    pub fn query_roots(&self) -> database::Result<Vec<RootInfo>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare("SELECT id, name, path FROM roots ORDER BY id")?
+            .query_map([], |row| {
+                Ok(RootInfo {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn query_photo_count_by_root(&self, root_id: RootId) -> database::Result<u32> {
+        self.query_scalar("SELECT COUNT(*) FROM photos WHERE root_id = ?1", [root_id])
+    }
+
+    pub fn insert_tag_kind(&self, name: &str) -> database::Result<TagKindId> {
+        self.db
+            .connection()
+            .execute("INSERT INTO tag_kinds(name) VALUES (?1)", [name])?;
+
+        Ok(TagKindId(self.db.connection().last_insert_rowid()))
+    }
+
+    pub fn query_tag_kinds(&self) -> database::Result<Vec<TagKind>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare("SELECT id, name FROM tag_kinds ORDER BY name")?
+            .query_map([], |row| {
+                Ok(TagKind {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn query_tag_kind_id(&self, name: &str) -> database::Result<Option<TagKindId>> {
+        self.query_scalar_optional("SELECT id FROM tag_kinds WHERE name = ?1", [name])
+    }
+
+    pub fn rename_tag_kind(&self, id: TagKindId, new_name: &str) -> database::Result<()> {
+        self.db.connection().execute(
+            "UPDATE tag_kinds SET name = ?1 WHERE id = ?2",
+            [&new_name as &dyn ToSql, &id],
+        )?;
+        Ok(())
+    }
+
+    pub fn query_tag_id(&self, tag: &TagName) -> database::Result<Option<TagId>> {
+        self.query_scalar_optional(
+            "SELECT t.id FROM tags t INNER JOIN tag_kinds k ON k.id = t.kind WHERE k.name = ?1 AND t.name = ?2",
+            [&tag.kind, &tag.value],
+        )
+    }
+
+    /// Look up a tag, creating it if it doesn't exist yet.
+    /// Tag values are created on demand, but the tag kind must already exist.
+    pub fn get_or_insert_tag(&self, tag: &TagName) -> database::Result<TagId> {
+        if let Some(id) = self.query_tag_id(tag)? {
+            return Ok(id);
+        }
+        let kind_id = self
+            .query_tag_kind_id(&tag.kind)?
+            .ok_or_else(|| TagError::UnknownKind(tag.kind.clone()))?;
+        self.db.connection().execute(
+            "INSERT INTO tags(name, kind) VALUES (?1, ?2)",
+            [&tag.value as &dyn ToSql, &kind_id],
+        )?;
+        Ok(TagId(self.db.connection().last_insert_rowid()))
+    }
+
+    /// Tag a photo. Returns false if the photo already had that tag.
+    pub fn assign_tag(&self, tag_id: TagId, photo_id: PhotoId) -> database::Result<bool> {
+        let inserted = self.db.connection().execute(
+            "INSERT OR IGNORE INTO tags_x_photos(photo_id, tag_id) VALUES (?1, ?2)",
+            [&photo_id as &dyn ToSql, &tag_id],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Remove a tag from a photo. Returns false if the photo didn't have that tag.
+    /// Tags that are no longer assigned to any photo are deleted.
+    pub fn unassign_tag(&self, tag_id: TagId, photo_id: PhotoId) -> database::Result<bool> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let deleted = Self::delete_tag_assignment(&tx, tag_id, photo_id)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Remove a tag from a photo within a transaction, deleting the tag if it is no longer used.
+    fn delete_tag_assignment(
+        tx: &Transaction,
+        tag_id: TagId,
+        photo_id: PhotoId,
+    ) -> database::Result<bool> {
+        let deleted = tx.execute(
+            "DELETE FROM tags_x_photos WHERE photo_id = ?1 AND tag_id = ?2",
+            [&photo_id as &dyn ToSql, &tag_id],
+        )?;
+        tx.execute(
+            "DELETE FROM tags WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM tags_x_photos WHERE tag_id = ?1)",
+            [tag_id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Change the value of a tag while keeping its kind.
+    /// If a tag with the new value already exists, both tags are merged.
+    pub fn rename_tag(&self, tag_id: TagId, new_value: &str) -> database::Result<()> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let existing: Option<TagId> = tx
+            .query_row(
+                "SELECT other.id FROM tags other INNER JOIN tags t ON t.kind = other.kind
+                 WHERE t.id = ?1 AND other.name = ?2 AND other.id <> ?1",
+                [&tag_id as &dyn ToSql, &new_value],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(existing) = existing {
+            debug!("Merging tag {:?} into {:?}", tag_id, existing);
+            tx.execute(
+                "INSERT OR IGNORE INTO tags_x_photos(photo_id, tag_id) SELECT photo_id, ?1 FROM tags_x_photos WHERE tag_id = ?2",
+                [existing, tag_id],
+            )?;
+            tx.execute("DELETE FROM tags WHERE id = ?1", [tag_id])?;
+        } else {
+            tx.execute(
+                "UPDATE tags SET name = ?1 WHERE id = ?2",
+                [&new_value as &dyn ToSql, &tag_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Retrieve all tags together with the number of photos they are assigned to.
+    pub fn query_tags(&self) -> database::Result<Vec<TagInfo>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare(
+                "SELECT t.id, k.name, t.name, (SELECT COUNT(*) FROM tags_x_photos tp WHERE tp.tag_id = t.id)
+                 FROM tags t INNER JOIN tag_kinds k ON k.id = t.kind
+                 ORDER BY k.name, t.name",
+            )?
+            .query_map([], |row| {
+                Ok(TagInfo {
+                    id: row.get(0)?,
+                    name: TagName {
+                        kind: row.get(1)?,
+                        value: row.get(2)?,
+                    },
+                    photo_count: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Retrieve the tags assigned to a photo.
+    pub fn query_photo_tags(&self, photo_id: PhotoId) -> database::Result<Vec<TagName>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare(
+                "SELECT k.name, t.name FROM tags_x_photos tp
+                 INNER JOIN tags t ON t.id = tp.tag_id
+                 INNER JOIN tag_kinds k ON k.id = t.kind
+                 WHERE tp.photo_id = ?1
+                 ORDER BY k.name, t.name",
+            )?
+            .query_map([photo_id], |row| {
+                Ok(TagName {
+                    kind: row.get(0)?,
+                    value: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn insert_album(&self, name: &str, description: &str) -> database::Result<AlbumId> {
+        self.db.connection().execute(
+            "INSERT INTO albums(name, description) VALUES (?1, ?2)",
+            [name, description],
+        )?;
+
+        Ok(AlbumId(self.db.connection().last_insert_rowid()))
+    }
+
+    /// Create an album whose photos are selected dynamically by the given filter.
+    pub fn insert_smart_album(
+        &self,
+        name: &str,
+        description: &str,
+        filter: &PhotoFilter,
+    ) -> database::Result<AlbumId> {
+        let filter_json = serde_json::to_string(filter)?;
+        self.db.connection().execute(
+            "INSERT INTO albums(name, description, filter) VALUES (?1, ?2, ?3)",
+            [name, description, &filter_json],
+        )?;
+
+        Ok(AlbumId(self.db.connection().last_insert_rowid()))
+    }
+
+    pub fn get_album(&self, id: AlbumId) -> database::Result<Option<Album>> {
+        self.db
+            .connection()
+            .query_row(
+                "SELECT id, name, description, (SELECT COUNT(*) FROM albums_x_photos ap WHERE ap.album_id = a.id), filter
+                 FROM albums a WHERE id = ?1",
+                [id],
+                Self::map_album_row,
+            )
+            .optional()?
+            .map(|album| self.count_smart_album_photos(album))
+            .transpose()
+    }
+
+    pub fn query_albums(&self) -> database::Result<Vec<Album>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare(
+                "SELECT id, name, description, (SELECT COUNT(*) FROM albums_x_photos ap WHERE ap.album_id = a.id), filter
+                 FROM albums a ORDER BY name",
+            )?
+            .query_map([], Self::map_album_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|album| self.count_smart_album_photos(album))
+            .collect()
+    }
+
+    /// Deserialize a filter stored as JSON, e.g. one that was saved by an incompatible version.
+    fn parse_filter_column(column: usize, filter_json: &str) -> rusqlite::Result<PhotoFilter> {
+        serde_json::from_str(filter_json).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(err))
+        })
+    }
+
+    fn map_album_row(row: &rusqlite::Row) -> rusqlite::Result<Album> {
+        Ok(Album {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            photo_count: row.get(3)?,
+            filter: row
+                .get::<_, Option<String>>(4)?
+                .map(|filter_json| Self::parse_filter_column(4, &filter_json))
+                .transpose()?,
+        })
+    }
+
+    /// The photos of smart albums are not stored in the database, so they need to be counted separately.
+    fn count_smart_album_photos(&self, mut album: Album) -> database::Result<Album> {
+        if let Some(ref filter) = album.filter {
+            album.photo_count = self.query_filtered_photo_count(filter)?;
+        }
+        Ok(album)
+    }
+
+    /// Retrieve all photos matching a filter, most recent first.
+    pub fn query_filtered_photos(&self, filter: &PhotoFilter) -> database::Result<Vec<Photo>> {
+        self.query_photo_page(filter, PhotoOrder::default(), None, None)
+    }
+
+    /// Retrieve a page of the photos matching the filter in the given order.
+    /// The page starts after the cursor, or at the beginning if there is no cursor,
+    /// and contains at most `limit` photos if a limit is given.
+    pub fn query_photo_page(
+        &self,
+        filter: &PhotoFilter,
+        order: PhotoOrder,
+        after: Option<&PhotoCursor>,
+        limit: Option<u32>,
+    ) -> database::Result<Vec<Photo>> {
+        let (mut condition, mut params) = filter.to_sql();
+        if let Some(cursor) = after {
+            let (cursor_condition, cursor_params) = cursor.to_sql(order);
+            condition = format!("{} AND {}", condition, cursor_condition);
+            params.extend(cursor_params);
+        }
+        let mut sql = format!(
+            "SELECT {} FROM photos p WHERE {} ORDER BY {}",
+            PHOTO_COLUMNS,
+            condition,
+            order.to_sql()
+        );
+        if let Some(limit) = limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Box::new(limit));
+        }
+        debug!("query_photo_page: {}", sql);
+        let rows = self
+            .db
+            .connection()
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(params.iter()), Self::map_photo_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn query_filtered_photo_count(&self, filter: &PhotoFilter) -> database::Result<u32> {
+        let (condition, params) = filter.to_sql();
+        let sql = format!("SELECT COUNT(*) FROM photos p WHERE {}", condition);
+        debug!("query_filtered_photo_count: {}", sql);
+        self.db
+            .connection()
+            .query_row(&sql, rusqlite::params_from_iter(params.iter()), |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    /// Count the photos matching the filter per period, most recent period first.
+    /// Periods are based on local time, and empty periods are omitted.
+    pub fn query_timeline(
+        &self,
+        filter: &PhotoFilter,
+        granularity: TimelineGranularity,
+    ) -> database::Result<Vec<TimelineBucket>> {
+        let (condition, params) = filter.to_sql();
+        let sql = format!(
+            "SELECT strftime('{}', p.created, 'localtime') AS period, COUNT(*) FROM photos p
+             WHERE {} GROUP BY period ORDER BY period IS NULL, period DESC",
+            granularity.period_format(),
+            condition
+        );
+        debug!("query_timeline: {}", sql);
+        let mut offset = 0;
+        let rows = self
+            .db
+            .connection()
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .map(|row| {
+                row.map(|(period, photo_count)| {
+                    let bucket = TimelineBucket {
+                        period,
+                        photo_count,
+                        offset,
+                    };
+                    offset += photo_count;
+                    bucket
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Retrieve the photos of an album, either in the user-defined order or,
+    /// for smart albums, all photos matching the filter of the album.
+    pub fn query_album_photos(&self, album: &Album) -> database::Result<Vec<Photo>> {
+        match album.filter {
+            Some(ref filter) => self.query_filtered_photos(filter),
+            None => self.query_static_album_photos(album.id),
+        }
+    }
+
+    /// Retrieve the photos of an album in the user-defined order.
+    fn query_static_album_photos(&self, album_id: AlbumId) -> database::Result<Vec<Photo>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare(&format!(
+                "SELECT {} FROM albums_x_photos ap INNER JOIN photos p ON p.id = ap.photo_id
+                 WHERE ap.album_id = ?1 ORDER BY ap.position",
+                PHOTO_COLUMNS
+            ))?
+            .query_map([album_id], Self::map_photo_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Append photos to the end of an album. Photos that are already part of the album keep their position.
+    /// Returns the number of photos that were added.
+    pub fn add_album_photos(&self, album_id: AlbumId, photos: &[PhotoId]) -> database::Result<usize> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let mut added = 0;
+        for photo_id in photos {
+            added += tx.execute(
+                "INSERT OR IGNORE INTO albums_x_photos(album_id, photo_id, position)
+                 SELECT ?1, ?2, COALESCE(MAX(position) + 1, 0) FROM albums_x_photos WHERE album_id = ?1",
+                [&album_id as &dyn ToSql, photo_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Remove photos from an album. Returns the number of photos that were removed.
+    pub fn remove_album_photos(&self, album_id: AlbumId, photos: &[PhotoId]) -> database::Result<usize> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let mut removed = 0;
+        for photo_id in photos {
+            removed += tx.execute(
+                "DELETE FROM albums_x_photos WHERE album_id = ?1 AND photo_id = ?2",
+                [&album_id as &dyn ToSql, photo_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Move a photo of an album to a new (zero-based) position, shifting the photos in between.
+    /// Positions past the end of the album move the photo to the end.
+    /// Returns false if the photo is not part of the album.
+    pub fn move_album_photo(
+        &self,
+        album_id: AlbumId,
+        photo_id: PhotoId,
+        new_position: usize,
+    ) -> database::Result<bool> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let mut order = tx
+            .prepare("SELECT photo_id FROM albums_x_photos WHERE album_id = ?1 ORDER BY position")?
+            .query_map([album_id], |row| row.get(0))?
+            .collect::<Result<Vec<PhotoId>, _>>()?;
+
+        let old_position = match order.iter().position(|id| *id == photo_id) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+        order.remove(old_position);
+        order.insert(new_position.min(order.len()), photo_id);
+
+        // Renumber all photos so that the positions stay dense
+        for (position, id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE albums_x_photos SET position = ?1 WHERE album_id = ?2 AND photo_id = ?3",
+                [&(position as i64) as &dyn ToSql, &album_id, id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Search photos by their path, title, comment and tags, most relevant first.
+    /// Each word in the query must occur as prefix of a word in the indexed texts.
+    pub fn search_photos(&self, query: &str) -> database::Result<Vec<Photo>> {
+        let fts_query = match Self::fts_query(query) {
+            Some(fts_query) => fts_query,
+            None => return Ok(Vec::new()),
+        };
+        debug!("search_photos: {}", fts_query);
+        let rows = self
+            .db
+            .connection()
+            .prepare(&format!(
+                "SELECT {} FROM photos_fts f INNER JOIN photos p ON p.id = f.rowid
+                 WHERE photos_fts MATCH ?1 ORDER BY f.rank",
+                PHOTO_COLUMNS
+            ))?
+            .query_map([fts_query], Self::map_photo_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
     }
 
-    pub fn insert_photo(&self, path_str: &str, info: &PhotoInfo) -> database::Result<PhotoId> {
-        let created_str = info.created.map(|ts| ts.to_rfc3339()); // ISO formatted date
+    /// Translate a user-supplied search query into an FTS5 query.
+    /// The words are quoted so that the query cannot contain syntax errors.
+    fn fts_query(query: &str) -> Option<String> {
+        let terms = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" "))
+        }
+    }
+
+    /// Find the indexed terms starting with the given prefix, most frequent first.
+    pub fn suggest_search_terms(
+        &self,
+        prefix: &str,
+        limit: u32,
+    ) -> database::Result<Vec<SearchSuggestion>> {
+        // The index only contains lower case terms
+        let prefix = prefix.trim().to_lowercase();
+        let rows = self
+            .db
+            .connection()
+            .prepare(
+                "SELECT term, doc FROM photos_fts_vocab WHERE substr(term, 1, length(?1)) = ?1
+                 ORDER BY doc DESC, term LIMIT ?2",
+            )?
+            .query_map([&prefix as &dyn ToSql, &limit], |row| {
+                Ok(SearchSuggestion {
+                    term: row.get(0)?,
+                    photo_count: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Create a user with the given password hash, see `auth::hash_password`.
+    pub fn insert_user(&self, name: &str, password_hash: &str) -> database::Result<UserId> {
+        let existing: Option<UserId> =
+            self.query_scalar_optional("SELECT id FROM users WHERE name = ?1", [name])?;
+        if existing.is_some() {
+            return Err(UserError::AlreadyExists(name.to_owned()).into());
+        }
         self.db.connection().execute(
-            "INSERT INTO photos(rel_path, created, file_hash) VALUES (?1, ?2, ?3)",
-            &[&path_str as &dyn ToSql, &created_str, &info.file_hash],
+            "INSERT INTO users(name, password_hash) VALUES (?1, ?2)",
+            [name, password_hash],
         )?;
+        Ok(UserId(self.db.connection().last_insert_rowid()))
+    }
 
-        Ok(PhotoId(self.db.connection().last_insert_rowid()))
+    /// Replace the password hash of a user and log out all their sessions.
+    /// Returns false if the user doesn't exist.
+    pub fn set_user_password_hash(&self, name: &str, password_hash: &str) -> database::Result<bool> {
+        let tx = self.db.connection().unchecked_transaction()?;
+        let updated = tx.execute(
+            "UPDATE users SET password_hash = ?1 WHERE name = ?2",
+            [password_hash, name],
+        )?;
+        tx.execute(
+            "DELETE FROM sessions WHERE user_id IN (SELECT id FROM users WHERE name = ?1)",
+            [name],
+        )?;
+        tx.commit()?;
+        Ok(updated > 0)
     }
 
-    pub fn update_photo(
-        &self,
-        id: PhotoId,
-        path_str: &str,
-        info: &PhotoInfo,
-    ) -> database::Result<usize> {
-        let created_str = info.created.map(|ts| ts.to_rfc3339()); // ISO formatted date
-        Ok(self.db.connection().execute(
-            "UPDATE photos SET rel_path = ?1, created = ?2, file_hash = ?3 WHERE id = ?4",
-            &[&path_str as &dyn ToSql, &created_str, &info.file_hash, &id],
-        )?)
+    /// Delete a user together with their sessions. Returns false if the user doesn't exist.
+    pub fn remove_user(&self, name: &str) -> database::Result<bool> {
+        let deleted = self
+            .db
+            .connection()
+            .execute("DELETE FROM users WHERE name = ?1", [name])?;
+        Ok(deleted > 0)
     }
 
-    pub fn get_photo(&self, id: PhotoId) -> database::Result<Option<Photo>> {
+    pub fn query_users(&self) -> database::Result<Vec<User>> {
+        let rows = self
+            .db
+            .connection()
+            .prepare("SELECT id, name FROM users ORDER BY name")?
+            .query_map([], |row| {
+                Ok(User {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Look up a user by name together with their password hash.
+    pub fn query_user_credentials(&self, name: &str) -> database::Result<Option<(User, String)>> {
         self.db
             .connection()
             .query_row(
-                "SELECT id, rel_path, created, file_hash FROM photos WHERE id = ?1",
-                [id],
-                Self::map_photo_row,
+                "SELECT id, name, password_hash FROM users WHERE name = ?1",
+                [name],
+                |row| {
+                    Ok((
+                        User {
+                            id: row.get(0)?,
+                            name: row.get(1)?,
+                        },
+                        row.get(2)?,
+                    ))
+                },
             )
             .optional()
             .map_err(Into::into)
     }
 
-    pub fn query_photo_id_by_path(&self, path_str: &str) -> database::Result<Option<PhotoId>> {
-        self.query_scalar_optional("SELECT id FROM photos WHERE rel_path = ?1", &[path_str])
+    /// Start a session for a user that is valid until the given time.
+    /// Expired sessions of all users are cleaned up on this occasion.
+    pub fn insert_session(
+        &self,
+        token: &AccessToken,
+        user_id: UserId,
+        expires: DateTime<Utc>,
+    ) -> database::Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.db.connection().unchecked_transaction()?;
+        tx.execute("DELETE FROM sessions WHERE expires <= ?1", [&now])?;
+        tx.execute(
+            "INSERT INTO sessions(token_hash, user_id, created, expires) VALUES (?1, ?2, ?3, ?4)",
+            [&token.hash() as &dyn ToSql, &user_id, &now, &expires.to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Look up the user of a session, unless the session doesn't exist or has expired.
+    pub fn query_session_user(&self, token: &AccessToken) -> database::Result<Option<User>> {
+        self.db
+            .connection()
+            .query_row(
+                "SELECT u.id, u.name FROM sessions s INNER JOIN users u ON u.id = s.user_id
+                 WHERE s.token_hash = ?1 AND s.expires > ?2",
+                [&token.hash() as &dyn ToSql, &Utc::now().to_rfc3339()],
+                |row| {
+                    Ok(User {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Into::into)
     }
 
-    pub fn query_all_photo_ids(&self) -> database::Result<std::vec::Vec<PhotoId>> {
-        let mut stmt = self
+    /// End a session. Returns false if there was no such session.
+    pub fn delete_session(&self, token: &AccessToken) -> database::Result<bool> {
+        let deleted = self
             .db
             .connection()
-            .prepare("SELECT id FROM photos ORDER BY created DESC")?;
-        let ls: rusqlite::Result<std::vec::Vec<PhotoId>> =
-            stmt.query_map([], |row| row.get(0))?.collect();
-        ls.map_err(Into::into)
+            .execute("DELETE FROM sessions WHERE token_hash = ?1", [token.hash()])?;
+        Ok(deleted > 0)
     }
 
-    pub fn query_all_photos(&self) -> database::Result<Vec<Photo>> {
-        let mut stmt = self
+    /// Create a share link identified by the token that is valid until the given time.
+    /// Expired share links are cleaned up on this occasion.
+    pub fn insert_share(
+        &self,
+        token: &AccessToken,
+        scope: &ShareScope,
+        expires: DateTime<Utc>,
+    ) -> database::Result<ShareId> {
+        let (album_id, filter_json) = match scope {
+            ShareScope::Album(album_id) => (Some(*album_id), None),
+            ShareScope::Photos(filter) => (None, Some(serde_json::to_string(filter)?)),
+        };
+        let now = Utc::now().to_rfc3339();
+        let tx = self.db.connection().unchecked_transaction()?;
+        tx.execute("DELETE FROM shares WHERE expires <= ?1", [&now])?;
+        tx.execute(
+            "INSERT INTO shares(token_hash, album_id, filter, created, expires) VALUES (?1, ?2, ?3, ?4, ?5)",
+            [
+                &token.hash() as &dyn ToSql,
+                &album_id,
+                &filter_json,
+                &now,
+                &expires.to_rfc3339(),
+            ],
+        )?;
+        let id = ShareId(tx.last_insert_rowid());
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Retrieve all share links, including expired ones, oldest first.
+    pub fn query_shares(&self) -> database::Result<Vec<Share>> {
+        let rows = self
             .db
             .connection()
-            .prepare("SELECT id, rel_path, created, file_hash FROM photos ORDER BY created DESC")?;
-        let ls: rusqlite::Result<Vec<Photo>> =
-            stmt.query_map([], Self::map_photo_row)?.collect();
-        ls.map_err(Into::into)
+            .prepare("SELECT id, album_id, filter, created, expires FROM shares ORDER BY id")?
+            .query_map([], Self::map_share_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
     }
 
-    pub fn query_photo_count(&self) -> database::Result<u32> {
-        self.query_scalar("SELECT COUNT(*) FROM photos", [])
+    /// Look up the share link identified by the token, unless it doesn't exist or has expired.
+    pub fn query_share(&self, token: &AccessToken) -> database::Result<Option<Share>> {
+        self.db
+            .connection()
+            .query_row(
+                "SELECT id, album_id, filter, created, expires FROM shares
+                 WHERE token_hash = ?1 AND expires > ?2",
+                [&token.hash() as &dyn ToSql, &Utc::now().to_rfc3339()],
+                Self::map_share_row,
+            )
+            .optional()
+            .map_err(Into::into)
     }
 
-    fn map_photo_row(row: &rusqlite::Row) -> rusqlite::Result<Photo> {
-        Ok(Photo {
+    fn map_share_row(row: &rusqlite::Row) -> rusqlite::Result<Share> {
+        let scope = match row.get::<_, Option<AlbumId>>(1)? {
+            Some(album_id) => ShareScope::Album(album_id),
+            None => ShareScope::Photos(Self::parse_filter_column(2, &row.get::<_, String>(2)?)?),
+        };
+        let parse_time = |column: usize| -> rusqlite::Result<DateTime<Utc>> {
+            let ts_str: String = row.get(column)?;
+            DateTime::parse_from_rfc3339(&ts_str)
+                .map(|ts| ts.with_timezone(&Utc))
+                .map_err(|err| {
+                    rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(err))
+                })
+        };
+        Ok(Share {
             id: row.get(0)?,
-            relative_path: row.get(1)?,
-            info: PhotoInfo {
-                created: row.get::<_, Option<String>>(2)?.map(|ts_str| {
-                    DateTime::parse_from_rfc3339(&ts_str)
-                        .expect("Database corrupted (invalid date in table `photos`)")
-                        .with_timezone(&Utc)
-                }),
-                file_hash: row.get(3)?,
-            },
+            scope,
+            created: parse_time(3)?,
+            expires: parse_time(4)?,
         })
     }
 
+    /// Revoke a share link. Returns false if there was no such share link.
+    pub fn remove_share(&self, id: ShareId) -> database::Result<bool> {
+        let deleted = self
+            .db
+            .connection()
+            .execute("DELETE FROM shares WHERE id = ?1", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Retrieve the photos that can be viewed through a share link.
+    pub fn query_share_photos(&self, share: &Share) -> database::Result<Vec<Photo>> {
+        match share.scope {
+            ShareScope::Album(album_id) => match self.get_album(album_id)? {
+                Some(album) => self.query_album_photos(&album),
+                None => Ok(Vec::new()),
+            },
+            ShareScope::Photos(ref filter) => self.query_filtered_photos(filter),
+        }
+    }
+
+    /// Check whether a photo can be viewed through a share link.
+    pub fn is_photo_shared(&self, share: &Share, photo_id: PhotoId) -> database::Result<bool> {
+        let filter = match share.scope {
+            ShareScope::Album(album_id) => match self.get_album(album_id)? {
+                Some(Album {
+                    filter: Some(filter),
+                    ..
+                }) => filter,
+                Some(_) => {
+                    return self.query_scalar(
+                        "SELECT EXISTS(SELECT * FROM albums_x_photos WHERE album_id = ?1 AND photo_id = ?2)",
+                        [album_id.0, photo_id.0],
+                    )
+                }
+                None => return Ok(false),
+            },
+            ShareScope::Photos(ref filter) => filter.clone(),
+        };
+        let (condition, mut params) = filter.to_sql();
+        params.push(Box::new(photo_id));
+        let sql = format!(
+            "SELECT EXISTS(SELECT * FROM photos p WHERE {} AND p.id = ?)",
+            condition
+        );
+        self.db
+            .connection()
+            .query_row(&sql, rusqlite::params_from_iter(params.iter()), |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    /// The id of the most recent event, or zero if there are none.
+    pub fn query_last_photo_event_id(&self) -> database::Result<PhotoEventId> {
+        self.query_scalar("SELECT COALESCE(MAX(id), 0) FROM photo_events", [])
+    }
+
+    /// Retrieve the events that happened after the given one, oldest first.
+    pub fn query_photo_events(
+        &self,
+        after: PhotoEventId,
+        limit: u32,
+    ) -> database::Result<Vec<PhotoEvent>> {
+        let mut stmt = self.db.connection().prepare(
+            "SELECT id, kind, photo_id FROM photo_events WHERE id > ?1 ORDER BY id LIMIT ?2",
+        )?;
+        let events = stmt
+            .query_map([after.0, i64::from(limit)], |row| {
+                Ok(PhotoEvent {
+                    id: row.get(0)?,
+                    kind: row.get(1)?,
+                    photo_id: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    /// Remove the events that happened before the given time. Returns the number of removed events.
+    pub fn prune_photo_events(&self, before: DateTime<Utc>) -> database::Result<usize> {
+        let deleted = self.db.connection().execute(
+            "DELETE FROM photo_events WHERE time < ?1",
+            [before.format(EVENT_TIME_FORMAT).to_string()],
+        )?;
+        Ok(deleted)
+    }
+
     /// Insert or update the thumbnail for a given photo.
     /// If generating the thumbnail caused an error, store the error message instead
     pub fn insert_thumbnail<E: AsRef<str>>(
@@ -277,6 +1643,28 @@ pub enum PhotoDbSchema {
     Empty = 0,
     /// The very first version of the photo library database.
     InitialVersion = 1,
+    /// Photos can be stored in several root directories.
+    MultipleRoots = 2,
+    /// Photos can be tagged with user defined tags of different kinds.
+    Tags = 3,
+    /// Photos can be collected in albums.
+    Albums = 4,
+    /// Smart albums select photos using a filter. The filter can use the camera model of a photo.
+    SmartAlbums = 5,
+    /// Photos can have a title and a comment.
+    Annotations = 6,
+    /// Photos can be rated, flagged and labeled.
+    Ratings = 7,
+    /// Full-text search index over paths, titles, comments and tags.
+    FullTextSearch = 8,
+    /// The capture time of photos can be overridden by the user.
+    CaptureTimeOverrides = 9,
+    /// User accounts and login sessions for the web server.
+    Users = 10,
+    /// Links for viewing an album or a range of photos without logging in.
+    Shares = 11,
+    /// Log of changes to photos, so that clients can be notified.
+    PhotoEvents = 12,
 }
 
 impl Schema for PhotoDbSchema {
@@ -289,7 +1677,7 @@ impl Schema for PhotoDbSchema {
     }
 
     fn latest() -> Self {
-        PhotoDbSchema::InitialVersion
+        PhotoDbSchema::PhotoEvents
     }
 
     fn run_upgrade(&self, tx: &Transaction) -> database::Result<()> {
@@ -319,6 +1707,420 @@ impl Schema for PhotoDbSchema {
                 )?;
                 Ok(())
             }
+            PhotoDbSchema::MultipleRoots => {
+                tx.execute(
+                    "CREATE TABLE roots(
+                    id    INTEGER PRIMARY KEY,
+                    name  TEXT NOT NULL UNIQUE,
+                    path  TEXT NOT NULL -- Absolute, or relative to the directory containing the database.
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "INSERT INTO roots(id, name, path) VALUES (?1, ?2, '.')",
+                    [&RootId::MAIN as &dyn ToSql, &crate::library::MAIN_ROOT_NAME],
+                )?;
+                // SQLite does not allow adding a column with a foreign key constraint and a non-NULL default,
+                // so the reference to the `roots` table is not enforced by the database.
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN root_id INTEGER NOT NULL DEFAULT 1",
+                    [],
+                )?;
+                tx.execute("DROP INDEX photos_rel_path_index", [])?;
+                tx.execute(
+                    "CREATE UNIQUE INDEX photos_root_rel_path_index ON photos(root_id, rel_path)",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::Tags => {
+                tx.execute(
+                    "CREATE TABLE tag_kinds(
+                    id    INTEGER PRIMARY KEY,
+                    name  TEXT NOT NULL UNIQUE
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE TABLE tags(
+                    id    INTEGER PRIMARY KEY,
+                    name  TEXT NOT NULL,
+                    kind  INTEGER NOT NULL REFERENCES tag_kinds(id) ON DELETE CASCADE,
+                    UNIQUE(kind, name)
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE TABLE tags_x_photos(
+                    photo_id  INTEGER NOT NULL REFERENCES photos(id) ON DELETE CASCADE,
+                    tag_id    INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+                    PRIMARY KEY(photo_id, tag_id)
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE INDEX tags_x_photos_tag_index ON tags_x_photos(tag_id)",
+                    [],
+                )?;
+                // The default tag kinds
+                tx.execute(
+                    "INSERT INTO tag_kinds(name) VALUES ('people'), ('location')",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::Albums => {
+                tx.execute(
+                    "CREATE TABLE albums(
+                    id           INTEGER PRIMARY KEY,
+                    name         TEXT NOT NULL,
+                    description  TEXT NOT NULL
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE TABLE albums_x_photos(
+                    album_id  INTEGER NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+                    photo_id  INTEGER NOT NULL REFERENCES photos(id) ON DELETE CASCADE,
+                    position  INTEGER NOT NULL, -- User-defined order of the photos in the album
+                    PRIMARY KEY(album_id, photo_id)
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE INDEX albums_x_photos_position_index ON albums_x_photos(album_id, position)",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::SmartAlbums => {
+                // JSON encoded `PhotoFilter`, NULL for regular albums
+                tx.execute("ALTER TABLE albums ADD COLUMN filter TEXT", [])?;
+                // Only known for photos that have been scanned after the migration
+                tx.execute("ALTER TABLE photos ADD COLUMN camera TEXT", [])?;
+                Ok(())
+            }
+            PhotoDbSchema::Annotations => {
+                tx.execute("ALTER TABLE photos ADD COLUMN title TEXT", [])?;
+                tx.execute("ALTER TABLE photos ADD COLUMN comment TEXT", [])?;
+                Ok(())
+            }
+            PhotoDbSchema::Ratings => {
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN rating INTEGER NOT NULL DEFAULT 0 CHECK (rating BETWEEN 0 AND 5)",
+                    [],
+                )?;
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN flag TEXT CHECK (flag IN ('pick', 'reject'))",
+                    [],
+                )?;
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN color_label TEXT CHECK (color_label IN ('red', 'yellow', 'green', 'blue', 'purple'))",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::FullTextSearch => {
+                // The rowid of the index is the id of the photo.
+                // The index is kept up to date by the triggers below.
+                tx.execute(
+                    "CREATE VIRTUAL TABLE photos_fts USING fts5(rel_path, title, comment, tags)",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE VIRTUAL TABLE photos_fts_vocab USING fts5vocab(photos_fts, 'row')",
+                    [],
+                )?;
+                // The tag values of a photo, separated by spaces
+                let photo_tags = |photo_id: &str| {
+                    format!(
+                        "(SELECT COALESCE(group_concat(t.name, ' '), '') FROM tags_x_photos tp
+                         INNER JOIN tags t ON t.id = tp.tag_id WHERE tp.photo_id = {})",
+                        photo_id
+                    )
+                };
+                tx.execute(
+                    &format!(
+                        "INSERT INTO photos_fts(rowid, rel_path, title, comment, tags)
+                         SELECT p.id, p.rel_path, p.title, p.comment, {} FROM photos p",
+                        photo_tags("p.id")
+                    ),
+                    [],
+                )?;
+                tx.execute_batch(&format!(
+                    "CREATE TRIGGER photos_fts_insert AFTER INSERT ON photos BEGIN
+                         INSERT INTO photos_fts(rowid, rel_path, title, comment, tags)
+                         VALUES (new.id, new.rel_path, new.title, new.comment, '');
+                     END;
+                     CREATE TRIGGER photos_fts_update AFTER UPDATE OF rel_path, title, comment ON photos BEGIN
+                         UPDATE photos_fts SET rel_path = new.rel_path, title = new.title, comment = new.comment
+                         WHERE rowid = new.id;
+                     END;
+                     CREATE TRIGGER photos_fts_delete AFTER DELETE ON photos BEGIN
+                         DELETE FROM photos_fts WHERE rowid = old.id;
+                     END;
+                     CREATE TRIGGER photos_fts_tag_assign AFTER INSERT ON tags_x_photos BEGIN
+                         UPDATE photos_fts SET tags = {new_tags} WHERE rowid = new.photo_id;
+                     END;
+                     CREATE TRIGGER photos_fts_tag_unassign AFTER DELETE ON tags_x_photos BEGIN
+                         UPDATE photos_fts SET tags = {old_tags} WHERE rowid = old.photo_id;
+                     END;
+                     CREATE TRIGGER photos_fts_tag_rename AFTER UPDATE OF name ON tags BEGIN
+                         UPDATE photos_fts SET tags = {fts_tags}
+                         WHERE rowid IN (SELECT photo_id FROM tags_x_photos WHERE tag_id = new.id);
+                     END;",
+                    new_tags = photo_tags("new.photo_id"),
+                    old_tags = photo_tags("old.photo_id"),
+                    fts_tags = photo_tags("photos_fts.rowid"),
+                ))?;
+                Ok(())
+            }
+            PhotoDbSchema::CaptureTimeOverrides => {
+                // `created` remains the effective capture time so that queries need not distinguish
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN scanned_created TEXT", // Capture time read from the file
+                    [],
+                )?;
+                tx.execute(
+                    "ALTER TABLE photos ADD COLUMN created_override TEXT", // Capture time set by the user
+                    [],
+                )?;
+                tx.execute("UPDATE photos SET scanned_created = created", [])?;
+                Ok(())
+            }
+            PhotoDbSchema::Users => {
+                tx.execute(
+                    "CREATE TABLE users(
+                    id             INTEGER PRIMARY KEY,
+                    name           TEXT NOT NULL UNIQUE,
+                    password_hash  TEXT NOT NULL -- Argon2 hash in PHC string format
+                    )",
+                    [],
+                )?;
+                tx.execute(
+                    "CREATE TABLE sessions(
+                    token_hash  BLOB PRIMARY KEY, -- SHA-256 hash of the session token
+                    user_id     INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                    created     TEXT NOT NULL,
+                    expires     TEXT NOT NULL
+                    )",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::Shares => {
+                tx.execute(
+                    "CREATE TABLE shares(
+                    id          INTEGER PRIMARY KEY,
+                    token_hash  BLOB NOT NULL UNIQUE, -- SHA-256 hash of the share token
+                    album_id    INTEGER REFERENCES albums(id) ON DELETE CASCADE, -- The shared album, if any
+                    filter      TEXT, -- Otherwise, the JSON encoded filter selecting the shared photos
+                    created     TEXT NOT NULL,
+                    expires     TEXT NOT NULL
+                    )",
+                    [],
+                )?;
+                Ok(())
+            }
+            PhotoDbSchema::PhotoEvents => {
+                // AUTOINCREMENT prevents reusing the ids of pruned events, which clients may have seen.
+                // There is no foreign key, because the events of removed photos are kept.
+                tx.execute(
+                    "CREATE TABLE photo_events(
+                    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind      TEXT NOT NULL,
+                    photo_id  INTEGER NOT NULL,
+                    time      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+                    )",
+                    [],
+                )?;
+                // Rescanning updates every photo, so only actual changes are recorded
+                let changed = [
+                    "root_id", "rel_path", "created", "file_hash", "camera", "title", "comment",
+                    "rating", "flag", "color_label", "created_override",
+                ]
+                .iter()
+                .map(|column| format!("old.{column} IS NOT new.{column}"))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+                tx.execute_batch(&format!(
+                    "CREATE TRIGGER photo_events_insert AFTER INSERT ON photos BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('photo_added', new.id);
+                     END;
+                     CREATE TRIGGER photo_events_update AFTER UPDATE ON photos
+                     WHEN {changed} BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('photo_updated', new.id);
+                     END;
+                     CREATE TRIGGER photo_events_delete AFTER DELETE ON photos BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('photo_removed', old.id);
+                     END;
+                     CREATE TRIGGER photo_events_tag_assign AFTER INSERT ON tags_x_photos BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('photo_updated', new.photo_id);
+                     END;
+                     -- Tags are also unassigned when removing a photo, which is already reported
+                     CREATE TRIGGER photo_events_tag_unassign AFTER DELETE ON tags_x_photos
+                     WHEN EXISTS (SELECT * FROM photos WHERE id = old.photo_id) BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('photo_updated', old.photo_id);
+                     END;
+                     CREATE TRIGGER photo_events_tag_rename AFTER UPDATE OF name ON tags BEGIN
+                         INSERT INTO photo_events(kind, photo_id)
+                         SELECT 'photo_updated', photo_id FROM tags_x_photos WHERE tag_id = new.id;
+                     END;
+                     CREATE TRIGGER photo_events_thumbnail_insert AFTER INSERT ON thumbnails
+                     WHEN new.thumbnail IS NOT NULL BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('thumbnail_ready', new.photo_id);
+                     END;
+                     CREATE TRIGGER photo_events_thumbnail_update AFTER UPDATE OF thumbnail ON thumbnails
+                     WHEN new.thumbnail IS NOT NULL BEGIN
+                         INSERT INTO photo_events(kind, photo_id) VALUES ('thumbnail_ready', new.photo_id);
+                     END;"
+                ))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> PhotoDatabase {
+        PhotoDatabase::open_or_create(":memory:").unwrap()
+    }
+
+    fn photo_info(camera: &str) -> PhotoInfo {
+        PhotoInfo {
+            created: None,
+            file_hash: Sha256Hash::from_bytes(&[0; 32]).unwrap(),
+            camera: Some(camera.to_owned()),
+            rating: None,
         }
     }
+
+    /// Insert a photo taken at noon of the given day.
+    fn insert_photo(db: &PhotoDatabase, path: &str, camera: &str, taken: &str) -> PhotoId {
+        let info = PhotoInfo {
+            created: Some(format!("{}T12:00:00Z", taken).parse().unwrap()),
+            ..photo_info(camera)
+        };
+        db.insert_photo(RootId(1), path, &info).unwrap()
+    }
+
+    fn insert_share(db: &PhotoDatabase, scope: ShareScope, expires: DateTime<Utc>) -> Option<Share> {
+        let token = AccessToken::generate();
+        db.insert_share(&token, &scope, expires).unwrap();
+        db.query_share(&token).unwrap()
+    }
+
+    fn tomorrow() -> DateTime<Utc> {
+        Utc::now() + chrono::Duration::days(1)
+    }
+
+    #[test]
+    fn album_shares_include_only_photos_of_the_album() {
+        let db = test_db();
+        let inside = insert_photo(&db, "a.jpg", "A", "2020-06-15");
+        let outside = insert_photo(&db, "b.jpg", "A", "2020-06-15");
+        let album = db.insert_album("Holiday", "").unwrap();
+        db.add_album_photos(album, &[inside]).unwrap();
+
+        let share = insert_share(&db, ShareScope::Album(album), tomorrow()).unwrap();
+        assert!(db.is_photo_shared(&share, inside).unwrap());
+        assert!(!db.is_photo_shared(&share, outside).unwrap());
+    }
+
+    #[test]
+    fn smart_album_shares_include_photos_matching_the_filter() {
+        let db = test_db();
+        let matching = insert_photo(&db, "a.jpg", "A", "2020-06-15");
+        let other = insert_photo(&db, "b.jpg", "B", "2020-06-15");
+        let filter = PhotoFilter {
+            camera: Some("a".to_owned()),
+            ..PhotoFilter::default()
+        };
+        let album = db.insert_smart_album("Camera A", "", &filter).unwrap();
+
+        let share = insert_share(&db, ShareScope::Album(album), tomorrow()).unwrap();
+        assert!(db.is_photo_shared(&share, matching).unwrap());
+        assert!(!db.is_photo_shared(&share, other).unwrap());
+    }
+
+    #[test]
+    fn date_range_shares_include_photos_taken_in_the_range() {
+        let db = test_db();
+        let inside = insert_photo(&db, "a.jpg", "A", "2020-06-15");
+        let before = insert_photo(&db, "b.jpg", "A", "2020-05-31");
+        let after = insert_photo(&db, "c.jpg", "A", "2020-07-01");
+        let filter = PhotoFilter {
+            from: Some("2020-06-01".parse().unwrap()),
+            to: Some("2020-06-30".parse().unwrap()),
+            ..PhotoFilter::default()
+        };
+
+        let share = insert_share(&db, ShareScope::Photos(filter), tomorrow()).unwrap();
+        assert!(db.is_photo_shared(&share, inside).unwrap());
+        assert!(!db.is_photo_shared(&share, before).unwrap());
+        assert!(!db.is_photo_shared(&share, after).unwrap());
+    }
+
+    #[test]
+    fn expired_shares_are_not_found() {
+        let db = test_db();
+        let album = db.insert_album("Holiday", "").unwrap();
+        let expired = Utc::now() - chrono::Duration::seconds(1);
+        assert!(insert_share(&db, ShareScope::Album(album), expired).is_none());
+    }
+
+    #[test]
+    fn shares_of_deleted_albums_are_not_found() {
+        let db = test_db();
+        let photo = insert_photo(&db, "a.jpg", "A", "2020-06-15");
+        let album = db.insert_album("Holiday", "").unwrap();
+        db.add_album_photos(album, &[photo]).unwrap();
+        let token = AccessToken::generate();
+        db.insert_share(&token, &ShareScope::Album(album), tomorrow()).unwrap();
+        let share = db.query_share(&token).unwrap().unwrap();
+
+        db.db
+            .connection()
+            .execute("DELETE FROM albums WHERE id = ?1", [album])
+            .unwrap();
+        assert!(db.query_share(&token).unwrap().is_none());
+        // Shares that were looked up before don't grant access anymore either
+        assert!(!db.is_photo_shared(&share, photo).unwrap());
+    }
+
+    fn event_kinds(db: &PhotoDatabase) -> Vec<PhotoEventKind> {
+        db.query_photo_events(PhotoEventId(0), 100)
+            .unwrap()
+            .into_iter()
+            .map(|event| event.kind)
+            .collect()
+    }
+
+    #[test]
+    fn unknown_event_kinds_are_errors() {
+        let db = test_db();
+        db.db
+            .connection()
+            .execute("INSERT INTO photo_events(kind, photo_id) VALUES ('photo_lost', 1)", [])
+            .unwrap();
+        assert!(db.query_photo_events(PhotoEventId(0), 100).is_err());
+    }
+
+    #[test]
+    fn unchanged_photos_are_not_reported_as_updated() {
+        let db = test_db();
+        let root = db.insert_root("external", "/media/photos").unwrap();
+        let photo = db.insert_photo(root, "a.jpg", &photo_info("A")).unwrap();
+        db.update_photo(photo, root, "a.jpg", &photo_info("A")).unwrap();
+        assert_eq!(event_kinds(&db), vec![PhotoEventKind::PhotoAdded]);
+
+        db.update_photo(photo, root, "a.jpg", &photo_info("B")).unwrap();
+        assert_eq!(
+            event_kinds(&db),
+            vec![PhotoEventKind::PhotoAdded, PhotoEventKind::PhotoUpdated]
+        );
+    }
 }
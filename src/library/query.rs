@@ -0,0 +1,646 @@
+//! A small query language for selecting photos, e.g.
+//! `taken:2019-06..2019-08 camera:"Pixel 6" tag:people=Anna -tag:location=Work hour:18..21`.
+//!
+//! A query consists of whitespace separated terms that all must match.
+//! Terms are either `<key>:<value>` conditions or plain words, which are looked up in the
+//! full-text index. Words like `12:30`, whose part before the colon is not a name, are plain
+//! words as well. Prefixing a term with `-` negates it, and values containing whitespace
+//! can be enclosed in double quotes.
+
+use chrono::{Datelike, NaiveDate};
+use rusqlite::types::ToSql;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::photodb::{ColorLabel, Flag, TagName, MAX_RATING};
+
+const MONTH_NAMES: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("Unterminated quote in query")]
+    UnterminatedQuote,
+    #[error("Missing value for {0}:")]
+    MissingValue(String),
+    #[error("Unknown search key {0}:, expected one of: taken, camera, tag, hour, month, rating, flag, label")]
+    UnknownKey(String),
+    #[error("Invalid value {value} for {key}: ({expected})")]
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("Invalid range {value} for {key}:, the start must not come after the end")]
+    InvalidRange { key: String, value: String },
+}
+
+/// A parsed photo query.
+#[derive(Debug, Clone)]
+pub struct PhotoQuery {
+    source: String,
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// A word or phrase in the path, title, comment or tags of the photo.
+    Text(String),
+    /// The capture date lies within the range, where the start is inclusive and the end exclusive.
+    Taken(Option<NaiveDate>, Option<NaiveDate>),
+    Camera(String),
+    Tag(TagName),
+    /// The hour of the capture time lies within the inclusive range, which may wrap around midnight.
+    Hour(u32, u32),
+    /// The month of the capture date lies within the inclusive range, which may wrap around new year.
+    Month(u32, u32),
+    Rating(u8, u8),
+    Flag(Option<Flag>),
+    ColorLabel(Option<ColorLabel>),
+}
+
+impl PhotoQuery {
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Translate the query into an SQL condition on the `photos` table, which must be aliased as `p`.
+    /// The returned parameters are referenced positionally.
+    pub(crate) fn to_sql(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut conditions = vec!["1".to_owned()];
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+        for term in self.terms.iter() {
+            let condition = term.condition.to_sql(&mut params);
+            if term.negated {
+                // Photos without a capture date etc. yield NULL, which should not match the
+                // condition but should match its negation.
+                conditions.push(format!("NOT COALESCE({}, 0)", condition));
+            } else {
+                conditions.push(condition);
+            }
+        }
+
+        (conditions.join(" AND "), params)
+    }
+}
+
+impl Condition {
+    fn to_sql(&self, params: &mut Vec<Box<dyn ToSql>>) -> String {
+        // Capture times are stored in UTC, but the date and time of day are meant as local time
+        // just like they were recorded by the camera.
+        match self {
+            Condition::Text(text) => {
+                params.push(Box::new(format!("\"{}\"*", text.replace('"', "\"\""))));
+                "(p.id IN (SELECT rowid FROM photos_fts WHERE photos_fts MATCH ?))".to_owned()
+            }
+            Condition::Taken(start, end) => {
+                let mut conditions = vec!["p.created IS NOT NULL".to_owned()];
+                if let Some(start) = start {
+                    conditions.push("date(p.created, 'localtime') >= ?".to_owned());
+                    params.push(Box::new(start.format("%Y-%m-%d").to_string()));
+                }
+                if let Some(end) = end {
+                    conditions.push("date(p.created, 'localtime') < ?".to_owned());
+                    params.push(Box::new(end.format("%Y-%m-%d").to_string()));
+                }
+                format!("({})", conditions.join(" AND "))
+            }
+            Condition::Camera(camera) => {
+                params.push(Box::new(camera.clone()));
+                "(p.camera = ? COLLATE NOCASE)".to_owned()
+            }
+            Condition::Tag(tag) => {
+                params.push(Box::new(tag.kind.clone()));
+                params.push(Box::new(tag.value.clone()));
+                "EXISTS (SELECT 1 FROM tags_x_photos tp
+                 INNER JOIN tags t ON t.id = tp.tag_id
+                 INNER JOIN tag_kinds k ON k.id = t.kind
+                 WHERE tp.photo_id = p.id AND k.name = ? AND t.name = ?)"
+                    .to_owned()
+            }
+            Condition::Hour(from, to) => Self::cyclic_range_sql(
+                "CAST(strftime('%H', p.created, 'localtime') AS INTEGER)",
+                *from,
+                *to,
+                params,
+            ),
+            Condition::Month(from, to) => Self::cyclic_range_sql(
+                "CAST(strftime('%m', p.created, 'localtime') AS INTEGER)",
+                *from,
+                *to,
+                params,
+            ),
+            Condition::Rating(from, to) => {
+                params.push(Box::new(*from));
+                params.push(Box::new(*to));
+                "(p.rating BETWEEN ? AND ?)".to_owned()
+            }
+            Condition::Flag(flag) => {
+                params.push(Box::new(*flag));
+                "(p.flag IS ?)".to_owned()
+            }
+            Condition::ColorLabel(color_label) => {
+                params.push(Box::new(*color_label));
+                "(p.color_label IS ?)".to_owned()
+            }
+        }
+    }
+
+    fn cyclic_range_sql(
+        expression: &str,
+        from: u32,
+        to: u32,
+        params: &mut Vec<Box<dyn ToSql>>,
+    ) -> String {
+        params.push(Box::new(from));
+        params.push(Box::new(to));
+        if from <= to {
+            format!("({} BETWEEN ? AND ?)", expression)
+        } else {
+            format!("({0} >= ? OR {0} <= ?)", expression)
+        }
+    }
+}
+
+impl std::str::FromStr for PhotoQuery {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let terms = split_terms(query)?
+            .into_iter()
+            .map(|term| parse_term(&term))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PhotoQuery {
+            source: query.trim().to_owned(),
+            terms,
+        })
+    }
+}
+
+impl std::fmt::Display for PhotoQuery {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str(&self.source)
+    }
+}
+
+impl PartialEq for PhotoQuery {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for PhotoQuery {}
+
+impl Serialize for PhotoQuery {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for PhotoQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        source.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Split a query into its terms at whitespace outside of quotes, removing the quotes.
+fn split_terms(query: &str) -> Result<Vec<String>, QueryError> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_term = false;
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_term = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_term {
+                    terms.push(std::mem::take(&mut current));
+                    in_term = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_term = true;
+            }
+        }
+    }
+    if in_quotes {
+        return Err(QueryError::UnterminatedQuote);
+    }
+    if in_term {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+fn parse_term(term: &str) -> Result<Term, QueryError> {
+    let (negated, term) = match term.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, term),
+    };
+    let (key, value) = match term.split_once(':') {
+        Some((key, value)) if is_key(key) => (key.to_lowercase(), value),
+        _ => {
+            return Ok(Term {
+                negated,
+                condition: Condition::Text(term.to_owned()),
+            })
+        }
+    };
+    if value.is_empty() {
+        return Err(QueryError::MissingValue(key));
+    }
+    let invalid = |expected| QueryError::InvalidValue {
+        key: key.clone(),
+        value: value.to_owned(),
+        expected,
+    };
+    let invalid_range = || QueryError::InvalidRange {
+        key: key.clone(),
+        value: value.to_owned(),
+    };
+
+    let condition = match key.as_str() {
+        "taken" => {
+            let expected = "YYYY, YYYY-MM or YYYY-MM-DD, or a range a..b";
+            let (start, end) = split_range(value);
+            let start = match start {
+                Some(start) => Some(parse_date_period(start).ok_or_else(|| invalid(expected))?.0),
+                None => None,
+            };
+            let end = match end {
+                Some(end) => Some(parse_date_period(end).ok_or_else(|| invalid(expected))?.1),
+                None => None,
+            };
+            if let (Some(start), Some(end)) = (start, end) {
+                if start >= end {
+                    return Err(invalid_range());
+                }
+            }
+            Condition::Taken(start, end)
+        }
+        "camera" => Condition::Camera(value.to_owned()),
+        "tag" => Condition::Tag(
+            value
+                .parse()
+                .map_err(|_| invalid("a tag written as <kind>=<value>"))?,
+        ),
+        "hour" => {
+            let (from, to) = parse_number_range(value, 0, 23, |hour| hour.parse().ok())
+                .ok_or_else(|| invalid("an hour between 0 and 23, or a range a..b"))?;
+            Condition::Hour(from, to)
+        }
+        "month" => {
+            let (from, to) = parse_number_range(value, 1, 12, parse_month)
+                .ok_or_else(|| invalid("a month name or number, or a range a..b"))?;
+            Condition::Month(from, to)
+        }
+        "rating" => {
+            let (from, to) =
+                parse_number_range(value, 0, MAX_RATING as u32, |rating| rating.parse().ok())
+                    .ok_or_else(|| invalid("a star rating between 0 and 5, or a range a..b"))?;
+            // Unlike hours and months, ratings don't wrap around
+            if from > to {
+                return Err(invalid_range());
+            }
+            Condition::Rating(from as u8, to as u8)
+        }
+        "flag" => Condition::Flag(
+            Flag::parse_optional(value).map_err(|_| invalid("pick, reject or none"))?,
+        ),
+        "label" => Condition::ColorLabel(
+            ColorLabel::parse_optional(value)
+                .map_err(|_| invalid("red, yellow, green, blue, purple or none"))?,
+        ),
+        _ => return Err(QueryError::UnknownKey(key)),
+    };
+    Ok(Term { negated, condition })
+}
+
+/// Whether the part of a term before the colon is meant as a key, rather than being part of a word
+/// such as a time of day.
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Split `a..b` into its bounds, where either bound may be omitted.
+/// A value without `..` is a range containing only that value.
+fn split_range(value: &str) -> (Option<&str>, Option<&str>) {
+    match value.split_once("..") {
+        Some((from, to)) => (
+            Some(from).filter(|from| !from.is_empty()),
+            Some(to).filter(|to| !to.is_empty()),
+        ),
+        None => (Some(value), Some(value)),
+    }
+}
+
+/// Parse a year, month or day, returning the first day of the period and the first day after it.
+fn parse_date_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts = value
+        .split('-')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match parts[..] {
+        [year] => {
+            let year = year as i32;
+            Some((
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+            ))
+        }
+        [year, month] => {
+            let start = NaiveDate::from_ymd_opt(year as i32, month, 1)?;
+            let end = if month == 12 {
+                NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(start.year(), month + 1, 1)?
+            };
+            Some((start, end))
+        }
+        [year, month, day] => {
+            let start = NaiveDate::from_ymd_opt(year as i32, month, day)?;
+            Some((start, start.succ_opt()?))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a number or an inclusive range of numbers within `min..=max`.
+/// Omitted bounds of a range default to the minimum and maximum, respectively.
+fn parse_number_range(
+    value: &str,
+    min: u32,
+    max: u32,
+    parse: impl Fn(&str) -> Option<u32>,
+) -> Option<(u32, u32)> {
+    let (from, to) = split_range(value);
+    let from = from.map_or(Some(min), &parse)?;
+    let to = to.map_or(Some(max), &parse)?;
+    if (min..=max).contains(&from) && (min..=max).contains(&to) {
+        Some((from, to))
+    } else {
+        None
+    }
+}
+
+/// Parse a month given as number or as (abbreviated) English name.
+fn parse_month(value: &str) -> Option<u32> {
+    if let Ok(month) = value.parse() {
+        return Some(month);
+    }
+    let value = value.to_lowercase();
+    if value.len() < 3 {
+        return None;
+    }
+    MONTH_NAMES
+        .iter()
+        .position(|name| name.starts_with(&value))
+        .map(|index| index as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<Vec<Term>, QueryError> {
+        query.parse::<PhotoQuery>().map(|query| query.terms)
+    }
+
+    fn condition(query: &str) -> Condition {
+        let mut terms = parse(query).unwrap();
+        assert_eq!(terms.len(), 1, "{} should consist of one term", query);
+        let term = terms.pop().unwrap();
+        assert!(!term.negated);
+        term.condition
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    fn invalid_range(key: &str, value: &str) -> QueryError {
+        QueryError::InvalidRange {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn empty_query() {
+        assert!("".parse::<PhotoQuery>().unwrap().is_empty());
+        assert!("  \t ".parse::<PhotoQuery>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(condition("beach"), Condition::Text("beach".to_owned()));
+        assert_eq!(
+            condition("\"summer holiday\""),
+            Condition::Text("summer holiday".to_owned())
+        );
+    }
+
+    #[test]
+    fn words_with_colons_are_text() {
+        assert_eq!(condition("12:30"), Condition::Text("12:30".to_owned()));
+        assert_eq!(condition(":-)"), Condition::Text(":-)".to_owned()));
+        assert_eq!(
+            parse("-2019:x").unwrap(),
+            vec![Term {
+                negated: true,
+                condition: Condition::Text("2019:x".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn taken() {
+        assert_eq!(
+            condition("taken:2019"),
+            Condition::Taken(date(2019, 1, 1), date(2020, 1, 1))
+        );
+        assert_eq!(
+            condition("taken:2019-12"),
+            Condition::Taken(date(2019, 12, 1), date(2020, 1, 1))
+        );
+        assert_eq!(
+            condition("taken:2019-02-28"),
+            Condition::Taken(date(2019, 2, 28), date(2019, 3, 1))
+        );
+        assert_eq!(
+            condition("taken:2019-06..2019-08"),
+            Condition::Taken(date(2019, 6, 1), date(2019, 9, 1))
+        );
+        assert_eq!(
+            condition("taken:2019.."),
+            Condition::Taken(date(2019, 1, 1), None)
+        );
+        assert_eq!(
+            condition("taken:..2019-06-30"),
+            Condition::Taken(None, date(2019, 7, 1))
+        );
+        assert!(matches!(
+            parse("taken:2019-13"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse("taken:yesterday"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert_eq!(
+            parse("taken:2020..2019"),
+            Err(invalid_range("taken", "2020..2019"))
+        );
+    }
+
+    #[test]
+    fn camera() {
+        assert_eq!(
+            condition("camera:\"Pixel 6\""),
+            Condition::Camera("Pixel 6".to_owned())
+        );
+    }
+
+    #[test]
+    fn tag() {
+        assert_eq!(
+            condition("tag:people=Anna"),
+            Condition::Tag("people=Anna".parse().unwrap())
+        );
+        assert_eq!(
+            condition("tag:\"location=New York\""),
+            Condition::Tag("location=New York".parse().unwrap())
+        );
+        assert!(matches!(
+            parse("tag:Anna"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn hour() {
+        assert_eq!(condition("hour:7"), Condition::Hour(7, 7));
+        assert_eq!(condition("hour:18..21"), Condition::Hour(18, 21));
+        assert_eq!(condition("hour:..6"), Condition::Hour(0, 6));
+        // Ranges may wrap around midnight
+        assert_eq!(condition("hour:22..2"), Condition::Hour(22, 2));
+        assert!(matches!(
+            parse("hour:24"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn month() {
+        assert_eq!(condition("month:6"), Condition::Month(6, 6));
+        assert_eq!(condition("month:jun..August"), Condition::Month(6, 8));
+        // Ranges may wrap around new year
+        assert_eq!(condition("month:dec..feb"), Condition::Month(12, 2));
+        assert!(matches!(
+            parse("month:ju"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse("month:13"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn rating() {
+        assert_eq!(condition("rating:5"), Condition::Rating(5, 5));
+        assert_eq!(condition("rating:3.."), Condition::Rating(3, MAX_RATING));
+        assert_eq!(condition("rating:..2"), Condition::Rating(0, 2));
+        assert_eq!(parse("rating:5..2"), Err(invalid_range("rating", "5..2")));
+        assert!(matches!(
+            parse("rating:6"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn flag_and_label() {
+        assert_eq!(condition("flag:pick"), Condition::Flag(Some(Flag::Pick)));
+        assert_eq!(condition("flag:none"), Condition::Flag(None));
+        assert_eq!(
+            condition("label:red"),
+            Condition::ColorLabel(Some(ColorLabel::Red))
+        );
+        assert!(matches!(
+            parse("label:orange"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn keys_are_case_insensitive() {
+        assert_eq!(condition("Rating:4"), Condition::Rating(4, 4));
+    }
+
+    #[test]
+    fn negation() {
+        let terms = parse("-tag:location=Work beach -flag:reject").unwrap();
+        assert_eq!(
+            terms.iter().map(|term| term.negated).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+        // A lone dash is a word
+        assert_eq!(condition("-"), Condition::Text("-".to_owned()));
+
+        let (sql, params) = "-rating:5".parse::<PhotoQuery>().unwrap().to_sql();
+        assert_eq!(sql, "1 AND NOT COALESCE((p.rating BETWEEN ? AND ?), 0)");
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn quoting() {
+        let terms = parse("camera:\"EOS 5D\" \"a b\"c").unwrap();
+        assert_eq!(terms[0].condition, Condition::Camera("EOS 5D".to_owned()));
+        assert_eq!(terms[1].condition, Condition::Text("a bc".to_owned()));
+        assert_eq!(parse("\"open"), Err(QueryError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("camera:"),
+            Err(QueryError::MissingValue("camera".to_owned()))
+        );
+        assert_eq!(
+            parse("color:red"),
+            Err(QueryError::UnknownKey("color".to_owned()))
+        );
+    }
+
+    #[test]
+    fn source_is_preserved() {
+        let query: PhotoQuery = "  hour:22..2  rating:4.. ".parse().unwrap();
+        assert_eq!(query.to_string(), "hour:22..2  rating:4..");
+        assert_eq!(query, "hour:22..2  rating:4..".parse().unwrap());
+    }
+}
@@ -1,6 +1,6 @@
 use log::debug;
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 /// Taken from https://gtk-rs.org/tuto/closures for easily cloning everything that is moved into a closure.
 #[macro_export]
@@ -108,5 +108,22 @@ pub fn backup_file(file_path: &Path, rename: bool) -> Result<(), io::Error> {
             return result;
         }
     }
-    Err(io::Error::new(io::ErrorKind::Other, "Too many backups"))
+    Err(io::Error::other("Too many backups"))
+}
+
+/// Make a path absolute relative to the given base directory and lexically remove
+/// `.` and `..` components. Unlike `std::fs::canonicalize`, this does not resolve symlinks
+/// and works for paths that do not exist.
+pub fn normalize_path(base_dir: &Path, path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in base_dir.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
 }
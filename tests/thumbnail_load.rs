@@ -0,0 +1,257 @@
+//! Load test for the web server, measuring how many thumbnails it serves concurrently
+//! while slow clients are downloading originals.
+//!
+//! It takes a while, so it only runs on request:
+//! `cargo test --release --test thumbnail_load -- --ignored --nocapture`
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PHOTO_COUNT: u32 = 40;
+const CLIENTS: usize = 16;
+const REQUESTS_PER_CLIENT: usize = 250;
+const SLOW_CLIENTS: usize = 4;
+
+#[test]
+#[ignore]
+fn concurrent_thumbnail_throughput() {
+    let library = TempLibrary::create(PHOTO_COUNT);
+    let server = library.browse();
+
+    let photo_ids = {
+        let mut client = HttpClient::connect(server.port);
+        let (status, body) = client.get("/api/v1/photos");
+        assert_eq!(status, 200);
+        let photos: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        photos
+            .iter()
+            .map(|photo| photo["id"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(photo_ids.len(), PHOTO_COUNT as usize);
+
+    // Clients reading originals slowly used to stall every other request handled by the same worker
+    let done = Arc::new(AtomicBool::new(false));
+    let slow_clients = (0..SLOW_CLIENTS)
+        .map(|index| {
+            let done = done.clone();
+            let photo_id = photo_ids[index];
+            let port = server.port;
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let mut client = HttpClient::connect(port);
+                    client.get_slowly(&format!("/api/v1/photos/{}/original", photo_id), &done);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let clients = (0..CLIENTS)
+        .map(|index| {
+            let photo_ids = photo_ids.clone();
+            let port = server.port;
+            std::thread::spawn(move || {
+                let mut client = HttpClient::connect(port);
+                let mut latencies = Vec::with_capacity(REQUESTS_PER_CLIENT);
+                for request in 0..REQUESTS_PER_CLIENT {
+                    let photo_id = photo_ids[(index + request) % photo_ids.len()];
+                    let request_start = Instant::now();
+                    let path = format!("/api/v1/photos/{}/thumbnail", photo_id);
+                    let (status, body) = client.get(&path);
+                    latencies.push(request_start.elapsed());
+                    assert_eq!(status, 200);
+                    assert!(!body.is_empty());
+                }
+                latencies
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut latencies = clients
+        .into_iter()
+        .flat_map(|client| client.join().unwrap())
+        .collect::<Vec<_>>();
+    let elapsed = start.elapsed();
+
+    done.store(true, Ordering::Relaxed);
+    for client in slow_clients {
+        client.join().unwrap();
+    }
+
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "{} thumbnails from {} clients in {:.2?}: {:.0} requests/s, latency p50 {:.2?}, p99 {:.2?}, max {:.2?}",
+        latencies.len(),
+        CLIENTS,
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        percentile(50),
+        percentile(99),
+        percentile(100),
+    );
+}
+
+/// A library in a temporary directory containing generated photos and their thumbnails.
+struct TempLibrary {
+    root: PathBuf,
+}
+
+impl TempLibrary {
+    fn create(photo_count: u32) -> TempLibrary {
+        let root = std::env::temp_dir().join(format!("photo-archive-load-{}", std::process::id()));
+        let photo_dir = root.join("photos");
+        std::fs::create_dir_all(&photo_dir).unwrap();
+        let library = TempLibrary { root };
+
+        for index in 0..photo_count {
+            // Noise makes the originals about as large as real photos
+            let mut seed = index.wrapping_mul(2_654_435_761).wrapping_add(1);
+            let image = image::RgbImage::from_fn(1600, 1200, |x, y| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let noise = (seed % 32) as u8;
+                image::Rgb([(x / 8) as u8 ^ noise, (y / 6) as u8, (index * 6) as u8 ^ noise])
+            });
+            image.save(photo_dir.join(format!("{:03}.jpg", index))).unwrap();
+        }
+
+        library.photoctl(&["init"]);
+        library.photoctl(&["photos", "scan"]);
+        library.photoctl(&["thumbnails", "generate"]);
+        library
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_photoctl"));
+        command
+            .env("PHOTO_ARCHIVE_ROOT", &self.root)
+            .args(["--verbosity", "warn"]);
+        command
+    }
+
+    fn photoctl(&self, args: &[&str]) {
+        let status = self.command().args(args).status().unwrap();
+        assert!(status.success(), "photoctl {:?} failed", args);
+    }
+
+    /// Start the web server on a free port.
+    fn browse(&self) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let cache_dir = self.root.join("render-cache");
+        let child = self
+            .command()
+            .args(["browse", "--no-auth", "--bind", &format!("127.0.0.1:{}", port)])
+            .arg("--render-cache-dir")
+            .arg(&cache_dir)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server { child, port };
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(Instant::now() < deadline, "Web server didn't start");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        server
+    }
+}
+
+impl Drop for TempLibrary {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A minimal HTTP/1.1 client that keeps its connection alive.
+struct HttpClient {
+    stream: BufReader<TcpStream>,
+}
+
+impl HttpClient {
+    fn connect(port: u16) -> HttpClient {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        HttpClient {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// Send a request and read the status and headers of the response,
+    /// returning the status and the length of the body.
+    fn send(&mut self, path: &str) -> (u16, usize) {
+        write!(
+            self.stream.get_mut(),
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            path
+        )
+        .unwrap();
+
+        let mut status_line = String::new();
+        self.stream.read_line(&mut status_line).unwrap();
+        let status = status_line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .unwrap_or_else(|| panic!("Invalid status line {:?}", status_line));
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            self.stream.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        (status, content_length)
+    }
+
+    fn get(&mut self, path: &str) -> (u16, Vec<u8>) {
+        let (status, content_length) = self.send(path);
+        let mut body = vec![0; content_length];
+        self.stream.read_exact(&mut body).unwrap();
+        (status, body)
+    }
+
+    /// Read the response in small chunks with pauses, like a client on a slow network.
+    fn get_slowly(&mut self, path: &str, stop: &AtomicBool) {
+        let (status, mut remaining) = self.send(path);
+        assert_eq!(status, 200);
+        let mut chunk = [0; 4096];
+        while remaining > 0 && !stop.load(Ordering::Relaxed) {
+            let length = chunk.len().min(remaining);
+            self.stream.read_exact(&mut chunk[..length]).unwrap();
+            remaining -= length;
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
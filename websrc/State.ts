@@ -1,16 +1,108 @@
 import { Request } from "./util/AjaxRequest";
+import { API_BASE, PhotoId, PhotoObject } from "./api";
+
+/// How long to wait for further changes on the server before requesting the changed photos.
+const REFRESH_DELAY_MS = 500;
+
+/// If more photos changed at once, e.g. during a scan, all photos are requested again instead.
+const MAX_INDIVIDUAL_REFRESHES = 50;
 
 export class AppState {
     private _photosChanged: Set<StateChangedListener> = new Set();
     private _photos: Photo[] = new Array();
+    private _refreshTimeout: number | null = null;
+    /// The photos that changed on the server since the last refresh.
+    private _changedIds: Set<PhotoId> = new Set();
+    /// Whether the server could not tell which photos changed.
+    private _reloadAll: boolean = false;
+    /// Incremented for every new request, so that pages of outdated requests are ignored.
+    private _photosGeneration: number = 0;
 
     requestPhotos(): void {
-        Request.get('/photos')
-            .onSuccess(r => this.receivePhotos(r.json()))
+        this._photosGeneration += 1;
+        this.requestPhotoPage(`${API_BASE}/photos`, this._photosGeneration, []);
+    }
+
+    /// Request the pages one after another by following the `Link` header of each response.
+    private requestPhotoPage(url: string, generation: number, photos: Photo[]): void {
+        Request.get(url)
+            .onSuccess(r => {
+                if (generation !== this._photosGeneration) {
+                    return;
+                }
+                photos.push(...r.json());
+                let next = nextPageUrl(r.header('Link'));
+                if (next === null) {
+                    this.receivePhotos(photos);
+                } else {
+                    this.requestPhotoPage(next, generation, photos);
+                }
+            })
             .onFailure(r => this.failedPhotos(r.text()))
             .send();
     }
 
+    /// Request the photos again whenever they change on the server, e.g. during a scan.
+    subscribeToChanges(): void {
+        let events = new EventSource(`${API_BASE}/events`);
+        let kinds = ['photo_added', 'photo_updated', 'photo_removed', 'thumbnail_ready'];
+        kinds.forEach(kind => events.addEventListener(kind, event => {
+            this._changedIds.add(JSON.parse((event as MessageEvent).data).photo_id);
+            this.scheduleRefresh();
+        }));
+        events.addEventListener('reset', () => {
+            this._reloadAll = true;
+            this.scheduleRefresh();
+        });
+    }
+
+    private scheduleRefresh(): void {
+        // Changes usually come in bursts, which are combined into a single refresh
+        if (this._refreshTimeout === null) {
+            this._refreshTimeout = window.setTimeout(() => {
+                this._refreshTimeout = null;
+                this.refreshChangedPhotos();
+            }, REFRESH_DELAY_MS);
+        }
+    }
+
+    private refreshChangedPhotos(): void {
+        let ids = Array.from(this._changedIds);
+        this._changedIds.clear();
+        if (this._reloadAll || ids.length > MAX_INDIVIDUAL_REFRESHES) {
+            this._reloadAll = false;
+            this.requestPhotos();
+            return;
+        }
+
+        // The changes are applied at once when all photos have been received
+        let generation = this._photosGeneration;
+        let changed: Map<PhotoId, Photo | null> = new Map();
+        let received = (id: PhotoId, photo: Photo | null) => {
+            changed.set(id, photo);
+            if (changed.size === ids.length && generation === this._photosGeneration) {
+                let unchanged = this._photos.filter(p => !changed.has(p.id));
+                let updated = Array.from(changed.values()).filter((p): p is Photo => p !== null);
+                this.receivePhotos(unchanged.concat(updated));
+            }
+        };
+        ids.forEach(id => {
+            Request.get(`${API_BASE}/photos/${id}`)
+                .onSuccess(r => received(id, r.json()))
+                .onFailure(r => {
+                    if (r.status() === 404) {
+                        // The photo has been removed
+                        received(id, null);
+                    } else {
+                        // Retry with the next change
+                        this._changedIds.add(id);
+                        this.failedPhotos(r.text());
+                    }
+                })
+                .send();
+        });
+    }
+
     public get photos() : Photo[] {
         return this._photos;
     }
@@ -40,11 +132,13 @@ export interface StateChangedListener {
     photosChanged(state: AppState, oldPhotos: Photo[], newPhotos: Photo[]): void;
 }
 
-export type Photo = {
-    id: number,
-    relative_path: string,
-    created: string | null,
-};
+export type Photo = PhotoObject;
+
+/// The URL of the next page in a `Link` header like `</api/v1/photos?after=...>; rel="next"`.
+function nextPageUrl(link: string | null): string | null {
+    let match = link === null ? null : link.match(/<([^>]*)>\s*;\s*rel="next"/);
+    return match ? match[1] : null;
+}
 
 /// Most recent photos come first, photos without a created date come last.
 function createdOrderNullsLast(a: Photo, b: Photo): number {
@@ -0,0 +1,186 @@
+// Types of the REST API, generated from its OpenAPI description.
+// Don't edit this file, but run `UPDATE_API_TYPES=1 cargo test api_types` after changing the API.
+
+/// The base URL of all endpoints.
+export const API_BASE = '/api/v1';
+
+/// Key for uniquely identifying an album.
+export type AlbumId = number;
+
+export type AlbumObject = {
+    description: string,
+    filter: null | PhotoFilter,
+    id: AlbumId,
+    name: string,
+    photo_count: number,
+};
+
+export type AlbumPhotoPatch = {
+    /// The new zero-based position of the photo within the album.
+    position: number,
+};
+
+export type AlbumPhotosAddition = {
+    photos: PhotoId[],
+};
+
+/// Color labels for grouping photos, e.g. during culling.
+export type ColorLabel = "red" | "yellow" | "green" | "blue" | "purple";
+
+export type CountObject = {
+    count: number,
+};
+
+export type Credentials = {
+    name: string,
+    password: string,
+};
+
+/// The photos to download, selected either by id, by album or by a query.
+export type DownloadSelection = {
+    album?: null | AlbumId,
+    photos?: PhotoId[] | null,
+    /// A query in the photo query language, e.g. `taken:2019 tag:people=Anna`.
+    q?: string | null,
+};
+
+/// JSON formatted error response returned by all endpoints.
+export type ErrorResponse = {
+    message: string,
+};
+
+/// Marks a photo as picked or rejected while culling.
+export type Flag = "pick" | "reject";
+
+export type JobId = number;
+
+export type JobKind = "scan" | "thumbnails";
+
+export type JobObject = {
+    /// The reason why the job failed.
+    error: string | null,
+    finished: string | null,
+    id: JobId,
+    kind: JobKind,
+    progress: JobProgressObject,
+    started: string,
+    state: JobState,
+};
+
+/// The number of photos the job has seen so far and what happened to them.
+/// For thumbnail jobs, `added` is the number of generated thumbnails.
+export type JobProgressObject = {
+    added: number,
+    failed: number,
+    skipped: number,
+    total: number,
+};
+
+export type JobState = "running" | "completed" | "cancelled" | "failed";
+
+/// A set of conditions that all must be met by a photo in order to match the filter.
+/// An empty filter matches all photos.
+export type PhotoFilter = {
+    /// The camera model the photo must have been taken with, compared case-insensitively.
+    camera?: string | null,
+    /// The first day (inclusive, local time) on which the photo may have been taken.
+    from?: string | null,
+    /// The minimum star rating of the photo.
+    min_rating?: number | null,
+    /// A query that the photo must match in addition to the other conditions.
+    query?: string | null,
+    /// Tags that the photo must have, such as `people=Anna` or `location=Home`.
+    tags?: TagName[],
+    /// The last day (inclusive, local time) on which the photo may have been taken.
+    to?: string | null,
+};
+
+/// Key for uniquely identifying a photo.
+export type PhotoId = number;
+
+export type PhotoObject = {
+    /// Whether the original file can currently be accessed,
+    /// i.e. the root directory it is stored in is online.
+    available: boolean,
+    color_label: null | ColorLabel,
+    comment: string | null,
+    created: string | null,
+    /// Whether the capture time was set by the user instead of being read from the file.
+    created_overridden: boolean,
+    flag: null | Flag,
+    id: PhotoId,
+    rating: number,
+    relative_path: string,
+    root: RootId,
+    title: string | null,
+};
+
+/// The order in which photos are listed. Photos without a capture date always come last.
+export type PhotoOrder = "newest" | "oldest";
+
+/// Changes to the metadata of a photo. Missing fields are left unchanged,
+/// empty strings remove the capture time override, title, comment, flag or color label.
+export type PhotoPatch = {
+    /// Tags to assign, written as `<kind>=<value>`.
+    add_tags?: string[],
+    color_label?: string | null,
+    comment?: string | null,
+    /// The capture time, either in RFC 3339 format or as local time `YYYY-MM-DDTHH:MM:SS`.
+    created?: string | null,
+    flag?: string | null,
+    rating?: number | null,
+    /// Tags to remove, written as `<kind>=<value>`.
+    remove_tags?: string[],
+    title?: string | null,
+};
+
+/// How a photo is fitted into the requested size.
+export type RenderFit = "contain" | "cover";
+
+/// The file format of rendered photos.
+export type RenderFormat = "jpeg" | "webp";
+
+/// Key for uniquely identifying a root directory of the library.
+export type RootId = number;
+
+/// A photo viewed through a share link. Unlike `PhotoObject`, it reveals nothing about how
+/// the photo is stored in the library or how it has been annotated privately.
+export type SharedPhotoObject = {
+    /// Whether the original file can currently be accessed.
+    available: boolean,
+    created: string | null,
+    id: PhotoId,
+    title: string | null,
+};
+
+export type SuggestionObject = {
+    photo_count: number,
+    term: string,
+};
+
+export type TagAssignment = {
+    /// The tag written as `<kind>=<value>`.
+    tag: string,
+};
+
+/// The human readable name of a tag, consisting of its kind and its value.
+/// It is written as `<kind>=<value>`, e.g. `people=Anna`.
+export type TagName = {
+    kind: string,
+    value: string,
+};
+
+export type TimelineBucketObject = {
+    /// Offset of the first photo of the period in the default order of `/photos`.
+    offset: number,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, or null for photos without a capture date.
+    period: string | null,
+    photo_count: number,
+};
+
+/// The length of the periods that photos are grouped into on a timeline.
+export type TimelineGranularity = "year" | "month" | "day";
+
+export type UserObject = {
+    name: string,
+};
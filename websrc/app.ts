@@ -30,6 +30,7 @@ class App {
 let app = new App('root');
 let state = new AppState();
 state.requestPhotos();
+state.subscribeToChanges();
 
 let router = new HashRouter();
 class Pages {
@@ -4,6 +4,7 @@ import { Position } from "../util/Position";
 import { Header } from "../components/Header";
 import { AppState, StateChangedListener, Photo } from "../State";
 import { HashRouter } from "../routing/HashRouter";
+import { API_BASE } from "../api";
 
 export class GalleryPage implements Page, StateChangedListener {
     private flexContainer: HTMLElement;
@@ -105,7 +106,7 @@ class GalleryItemRenderer implements VirtualGridRenderer {
         // Look up the photo, and assign the url
         if ( index < page.state.photos.length ) {
             let photo = page.state.photos[index];
-            element.style.backgroundImage = `url("/photos/${photo.id}/thumbnail")`;
+            element.style.backgroundImage = `url("${API_BASE}/photos/${photo.id}/thumbnail")`;
             element.onclick = () => {
                 // This allows us to return back to the current position when the slideshow is exited:
                 page.router.replaceHistoryEntry(['gallery', photo.id.toString()]);
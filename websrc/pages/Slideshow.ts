@@ -2,6 +2,7 @@ import { Page } from "./Page";
 import { Position } from "../util/Position";
 import { AppState, StateChangedListener, Photo } from "../State";
 import { HashRouter } from "../routing/HashRouter";
+import { API_BASE } from "../api";
 
 export class SldeshowPage implements Page, StateChangedListener {
     private _imageContainer: HTMLElement;
@@ -47,7 +48,7 @@ export class SldeshowPage implements Page, StateChangedListener {
         let titleSuffix: string;
         if(this._currentIndex >= 0 && this._currentIndex < this.state.photos.length) {
             let photo = this.state.photos[this._currentIndex];
-            this._imageContainer.style.backgroundImage = `url("/photos/${photo.id}/original")`
+            this._imageContainer.style.backgroundImage = `url("${API_BASE}/photos/${photo.id}/original")`
             titleSuffix = ` (photo #${photo.id})`;
         } else {
             this._imageContainer.style.backgroundImage = '';
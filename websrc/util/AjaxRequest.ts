@@ -33,7 +33,10 @@ export class RequestBuilder {
         let that = this;
         req.onreadystatechange = function(this: XMLHttpRequest, _event: Event) {
             if (this.readyState == XMLHttpRequest.DONE) {
-                if (this.status >= 200 && this.status < 300) {
+                if (this.status == 401) {
+                    // The session has expired or the user never logged in
+                    window.location.href = '/login';
+                } else if (this.status >= 200 && this.status < 300) {
                     if(that.onsuccess) {
                         that.onsuccess(new Response(this));
                     }
@@ -62,4 +65,12 @@ export class Response {
     text(): string {
         return this.req.responseText;
     }
+
+    status(): number {
+        return this.req.status;
+    }
+
+    header(name: string): string | null {
+        return this.req.getResponseHeader(name);
+    }
 }
\ No newline at end of file